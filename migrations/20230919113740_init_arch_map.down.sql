@@ -0,0 +1,18 @@
+-- Add migration script here
+DROP TABLE IF EXISTS DocumentProject;
+DROP TABLE IF EXISTS ProjectL2;
+DROP TABLE IF EXISTS ProjectL1;
+
+DROP TABLE IF EXISTS DocumentMF;
+DROP TABLE IF EXISTS MFL2;
+DROP TABLE IF EXISTS MFL1;
+
+DROP TABLE IF EXISTS DocumentSystem;
+DROP TABLE IF EXISTS SystemL2;
+DROP TABLE IF EXISTS SystemL1;
+
+DROP TABLE IF EXISTS DocumentTech;
+DROP TABLE IF EXISTS TechL2;
+DROP TABLE IF EXISTS TechL1;
+
+DROP TABLE IF EXISTS Documents;
@@ -0,0 +1,10 @@
+-- Add migration script here
+DROP INDEX IF EXISTS idx_documents_name;
+DROP INDEX IF EXISTS idx_techl1_name;
+DROP INDEX IF EXISTS idx_techl2_name;
+DROP INDEX IF EXISTS idx_systeml1_name;
+DROP INDEX IF EXISTS idx_systeml2_name;
+DROP INDEX IF EXISTS idx_mfl1_name;
+DROP INDEX IF EXISTS idx_mfl2_name;
+DROP INDEX IF EXISTS idx_projectl1_name;
+DROP INDEX IF EXISTS idx_projectl2_name;
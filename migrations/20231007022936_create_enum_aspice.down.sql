@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE Documents DROP COLUMN IF EXISTS associate_requirement;
+
+DROP TABLE IF EXISTS document_aspice_mapping;
+DROP TYPE IF EXISTS ASPICE;
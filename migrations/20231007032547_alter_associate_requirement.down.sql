@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE Documents ALTER COLUMN associate_requirement DROP NOT NULL;
+ALTER TABLE Documents ALTER COLUMN associate_requirement DROP DEFAULT;
+UPDATE Documents SET associate_requirement = NULL WHERE associate_requirement = '00000000-0000-0000-0000-000000000000';
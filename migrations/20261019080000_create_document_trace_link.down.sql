@@ -0,0 +1,4 @@
+-- Add migration script here
+-- associate_requirement 在 document_requirement 的回滚中恢复
+DROP TABLE IF EXISTS document_trace_link;
+DROP TYPE IF EXISTS TRACE_LINK_TYPE;
@@ -0,0 +1,23 @@
+-- Add migration script here
+CREATE TYPE TRACE_LINK_TYPE AS ENUM ('refines', 'verifies', 'implements');
+
+-- source 文档追溯到 target 文档，例如 单测 -> 详设 -> 架构 -> 需求
+CREATE TABLE document_trace_link (
+    source_docid UUID REFERENCES Documents(id),
+    target_docid UUID REFERENCES Documents(id),
+    link_type TRACE_LINK_TYPE NOT NULL,
+    PRIMARY KEY (source_docid, target_docid)
+);
+CREATE INDEX idx_target_docid_on_document_trace_link ON document_trace_link (target_docid);
+
+-- 由已有的 associate_requirement 生成追溯关系
+INSERT INTO document_trace_link (source_docid, target_docid, link_type)
+SELECT documents.id, documents.associate_requirement,
+    CASE WHEN EXISTS (
+        SELECT 1 FROM document_aspice_mapping
+        WHERE document_aspice_mapping.docid = documents.id
+        AND document_aspice_mapping.aspice_step IN ('单测', '集测', '路测')
+    ) THEN 'verifies'::TRACE_LINK_TYPE ELSE 'refines'::TRACE_LINK_TYPE END
+FROM documents
+INNER JOIN documents AS requirement ON documents.associate_requirement = requirement.id
+WHERE documents.id <> documents.associate_requirement;
@@ -0,0 +1,13 @@
+-- Add migration script here
+ALTER TABLE Documents ADD COLUMN associate_requirement UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000';
+
+-- 只能恢复一个需求：关联多个需求的文档保留按 id 排序的第一个
+UPDATE Documents SET associate_requirement = first_requirement.requirementid
+FROM (
+    SELECT DISTINCT ON (docid) docid, requirementid
+    FROM document_requirement
+    ORDER BY docid, requirementid
+) AS first_requirement
+WHERE Documents.id = first_requirement.docid;
+
+DROP TABLE IF EXISTS document_requirement;
@@ -0,0 +1,16 @@
+-- Add migration script here
+-- 一个文档可以关联多个需求文档
+CREATE TABLE document_requirement (
+    docid UUID REFERENCES Documents(id),
+    requirementid UUID REFERENCES Documents(id),
+    PRIMARY KEY (docid, requirementid)
+);
+CREATE INDEX idx_requirementid_on_document_requirement ON document_requirement (requirementid);
+
+INSERT INTO document_requirement (docid, requirementid)
+SELECT documents.id, documents.associate_requirement
+FROM documents
+INNER JOIN documents AS requirement ON documents.associate_requirement = requirement.id
+WHERE documents.id <> documents.associate_requirement;
+
+ALTER TABLE Documents DROP COLUMN associate_requirement;
@@ -0,0 +1,9 @@
+-- Add migration script here
+ALTER TABLE TechL1 DROP COLUMN IF EXISTS sort_order;
+ALTER TABLE TechL2 DROP COLUMN IF EXISTS sort_order;
+ALTER TABLE SystemL1 DROP COLUMN IF EXISTS sort_order;
+ALTER TABLE SystemL2 DROP COLUMN IF EXISTS sort_order;
+ALTER TABLE MFL1 DROP COLUMN IF EXISTS sort_order;
+ALTER TABLE MFL2 DROP COLUMN IF EXISTS sort_order;
+ALTER TABLE ProjectL1 DROP COLUMN IF EXISTS sort_order;
+ALTER TABLE ProjectL2 DROP COLUMN IF EXISTS sort_order;
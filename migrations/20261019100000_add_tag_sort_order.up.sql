@@ -0,0 +1,59 @@
+-- Add migration script here
+-- 标签的显示顺序，导入时按表格中的行顺序生成
+ALTER TABLE TechL1 ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE TechL2 ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE SystemL1 ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE SystemL2 ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE MFL1 ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE MFL2 ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE ProjectL1 ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE ProjectL2 ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0;
+
+-- 保持原来的顺序：技术方案 L1 按预定义顺序，其余按名称
+UPDATE TechL1 SET sort_order = ordered.sort_order
+FROM (
+    SELECT id, ROW_NUMBER() OVER (ORDER BY
+        CASE name
+            WHEN '传感器方案' THEN 1
+            WHEN '域控硬件方案' THEN 2
+            WHEN 'OS+中间件方案' THEN 3
+            WHEN '执行器交互方案' THEN 4
+            WHEN 'EE架构方案' THEN 5
+            WHEN '信号接入方案' THEN 6
+            WHEN '通讯方案' THEN 7
+            WHEN '应用层基础服务方案' THEN 8
+            WHEN '系统方案' THEN 9
+            WHEN '软件优化方案' THEN 10
+            ELSE 11
+        END, name) AS sort_order
+    FROM TechL1
+) AS ordered
+WHERE TechL1.id = ordered.id;
+
+UPDATE SystemL1 SET sort_order = ordered.sort_order
+FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY name) AS sort_order FROM SystemL1) AS ordered
+WHERE SystemL1.id = ordered.id;
+
+UPDATE MFL1 SET sort_order = ordered.sort_order
+FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY name) AS sort_order FROM MFL1) AS ordered
+WHERE MFL1.id = ordered.id;
+
+UPDATE ProjectL1 SET sort_order = ordered.sort_order
+FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY name) AS sort_order FROM ProjectL1) AS ordered
+WHERE ProjectL1.id = ordered.id;
+
+UPDATE TechL2 SET sort_order = ordered.sort_order
+FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY name) AS sort_order FROM TechL2) AS ordered
+WHERE TechL2.id = ordered.id;
+
+UPDATE SystemL2 SET sort_order = ordered.sort_order
+FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY name) AS sort_order FROM SystemL2) AS ordered
+WHERE SystemL2.id = ordered.id;
+
+UPDATE MFL2 SET sort_order = ordered.sort_order
+FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY name) AS sort_order FROM MFL2) AS ordered
+WHERE MFL2.id = ordered.id;
+
+UPDATE ProjectL2 SET sort_order = ordered.sort_order
+FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY name) AS sort_order FROM ProjectL2) AS ordered
+WHERE ProjectL2.id = ordered.id;
@@ -0,0 +1,11 @@
+-- Add migration script here
+-- 每次数据同步（data_sync）的结果，供 /readyz 和 /metrics 使用
+CREATE TABLE sync_run (
+    id UUID PRIMARY KEY,
+    started_at TIMESTAMPTZ NOT NULL,
+    finished_at TIMESTAMPTZ NOT NULL,
+    success BOOLEAN NOT NULL,
+    error TEXT
+);
+
+CREATE INDEX idx_sync_run_finished_at ON sync_run (finished_at);
@@ -0,0 +1,19 @@
+-- Add migration script here
+DROP TRIGGER IF EXISTS audit_documents ON Documents;
+DROP TRIGGER IF EXISTS audit_techl1 ON TechL1;
+DROP TRIGGER IF EXISTS audit_techl2 ON TechL2;
+DROP TRIGGER IF EXISTS audit_systeml1 ON SystemL1;
+DROP TRIGGER IF EXISTS audit_systeml2 ON SystemL2;
+DROP TRIGGER IF EXISTS audit_mfl1 ON MFL1;
+DROP TRIGGER IF EXISTS audit_mfl2 ON MFL2;
+DROP TRIGGER IF EXISTS audit_projectl1 ON ProjectL1;
+DROP TRIGGER IF EXISTS audit_projectl2 ON ProjectL2;
+DROP TRIGGER IF EXISTS audit_documenttech ON DocumentTech;
+DROP TRIGGER IF EXISTS audit_documentsystem ON DocumentSystem;
+DROP TRIGGER IF EXISTS audit_documentmf ON DocumentMF;
+DROP TRIGGER IF EXISTS audit_documentproject ON DocumentProject;
+DROP TRIGGER IF EXISTS audit_document_aspice_mapping ON document_aspice_mapping;
+DROP TRIGGER IF EXISTS audit_document_requirement ON document_requirement;
+DROP TRIGGER IF EXISTS audit_document_trace_link ON document_trace_link;
+DROP FUNCTION IF EXISTS audit_change();
+DROP TABLE IF EXISTS audit_log;
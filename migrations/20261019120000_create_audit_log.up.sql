@@ -0,0 +1,96 @@
+-- Add migration script here
+-- 文档、标签和关联表的每一次增删改，由触发器写入
+-- changed_by / sync_run_id 来自会话设置 arch_map.changed_by / arch_map.sync_run：
+-- API 在事务内设置，data_sync 在连接参数中设置
+CREATE TABLE audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp(),
+    table_name TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    -- primary key of the changed row, columns joined by '/'
+    row_key TEXT NOT NULL,
+    -- the document the row belongs to, NULL for tags
+    document_id UUID,
+    old_row JSONB,
+    new_row JSONB,
+    -- no foreign key, the sync run is recorded when it finishes
+    sync_run_id UUID,
+    changed_by TEXT
+);
+CREATE INDEX idx_changed_at_on_audit_log ON audit_log (changed_at);
+CREATE INDEX idx_document_id_on_audit_log ON audit_log (document_id);
+CREATE INDEX idx_sync_run_id_on_audit_log ON audit_log (sync_run_id);
+
+-- trigger arguments: primary key columns of the table
+CREATE FUNCTION audit_change() RETURNS TRIGGER AS $$
+DECLARE
+    old_row JSONB;
+    new_row JSONB;
+    changed_row JSONB;
+    row_key TEXT;
+BEGIN
+    IF TG_OP <> 'INSERT' THEN
+        old_row := to_jsonb(OLD);
+    END IF;
+    IF TG_OP <> 'DELETE' THEN
+        new_row := to_jsonb(NEW);
+    END IF;
+    IF old_row = new_row THEN
+        RETURN NULL;
+    END IF;
+    changed_row := COALESCE(new_row, old_row);
+
+    SELECT string_agg(changed_row->>key_column, '/')
+    INTO row_key
+    FROM unnest(TG_ARGV) AS key_column;
+
+    INSERT INTO audit_log (table_name, operation, row_key, document_id, old_row, new_row, sync_run_id, changed_by)
+    VALUES (
+        TG_TABLE_NAME,
+        lower(TG_OP),
+        row_key,
+        CASE
+            WHEN TG_TABLE_NAME = 'documents' THEN changed_row->>'id'
+            ELSE COALESCE(changed_row->>'docid', changed_row->>'source_docid')
+        END::UUID,
+        old_row,
+        new_row,
+        NULLIF(current_setting('arch_map.sync_run', true), '')::UUID,
+        NULLIF(current_setting('arch_map.changed_by', true), '')
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_documents AFTER INSERT OR UPDATE OR DELETE ON Documents
+    FOR EACH ROW EXECUTE FUNCTION audit_change('id');
+CREATE TRIGGER audit_techl1 AFTER INSERT OR UPDATE OR DELETE ON TechL1
+    FOR EACH ROW EXECUTE FUNCTION audit_change('id');
+CREATE TRIGGER audit_techl2 AFTER INSERT OR UPDATE OR DELETE ON TechL2
+    FOR EACH ROW EXECUTE FUNCTION audit_change('id');
+CREATE TRIGGER audit_systeml1 AFTER INSERT OR UPDATE OR DELETE ON SystemL1
+    FOR EACH ROW EXECUTE FUNCTION audit_change('id');
+CREATE TRIGGER audit_systeml2 AFTER INSERT OR UPDATE OR DELETE ON SystemL2
+    FOR EACH ROW EXECUTE FUNCTION audit_change('id');
+CREATE TRIGGER audit_mfl1 AFTER INSERT OR UPDATE OR DELETE ON MFL1
+    FOR EACH ROW EXECUTE FUNCTION audit_change('id');
+CREATE TRIGGER audit_mfl2 AFTER INSERT OR UPDATE OR DELETE ON MFL2
+    FOR EACH ROW EXECUTE FUNCTION audit_change('id');
+CREATE TRIGGER audit_projectl1 AFTER INSERT OR UPDATE OR DELETE ON ProjectL1
+    FOR EACH ROW EXECUTE FUNCTION audit_change('id');
+CREATE TRIGGER audit_projectl2 AFTER INSERT OR UPDATE OR DELETE ON ProjectL2
+    FOR EACH ROW EXECUTE FUNCTION audit_change('id');
+CREATE TRIGGER audit_documenttech AFTER INSERT OR UPDATE OR DELETE ON DocumentTech
+    FOR EACH ROW EXECUTE FUNCTION audit_change('docid', 'techid');
+CREATE TRIGGER audit_documentsystem AFTER INSERT OR UPDATE OR DELETE ON DocumentSystem
+    FOR EACH ROW EXECUTE FUNCTION audit_change('docid', 'sysid');
+CREATE TRIGGER audit_documentmf AFTER INSERT OR UPDATE OR DELETE ON DocumentMF
+    FOR EACH ROW EXECUTE FUNCTION audit_change('docid', 'mfid');
+CREATE TRIGGER audit_documentproject AFTER INSERT OR UPDATE OR DELETE ON DocumentProject
+    FOR EACH ROW EXECUTE FUNCTION audit_change('docid', 'projectid');
+CREATE TRIGGER audit_document_aspice_mapping AFTER INSERT OR UPDATE OR DELETE ON document_aspice_mapping
+    FOR EACH ROW EXECUTE FUNCTION audit_change('docid', 'aspice_step');
+CREATE TRIGGER audit_document_requirement AFTER INSERT OR UPDATE OR DELETE ON document_requirement
+    FOR EACH ROW EXECUTE FUNCTION audit_change('docid', 'requirementid');
+CREATE TRIGGER audit_document_trace_link AFTER INSERT OR UPDATE OR DELETE ON document_trace_link
+    FOR EACH ROW EXECUTE FUNCTION audit_change('source_docid', 'target_docid');
@@ -0,0 +1,14 @@
+-- Add migration script here
+-- 架构图的命名快照：data 是拍快照时全部文档、标签和关联的 JSON，
+-- 用于按里程碑查看当时的标签树（as_of 查询）
+CREATE TABLE snapshot (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    taken_at TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp(),
+    -- 同步后自动拍的快照记录对应的同步，手动拍的为空
+    sync_run_id UUID,
+    taken_by TEXT,
+    data JSONB NOT NULL
+);
+
+CREATE INDEX idx_snapshot_taken_at ON snapshot (taken_at);
@@ -0,0 +1,15 @@
+-- Add migration script here
+-- 文档链接最近一次检查的结果，由 link_check 任务写入
+-- link 是检查时的链接，文档链接改动后这条结果就不再适用
+CREATE TABLE document_link_check (
+    docid UUID PRIMARY KEY REFERENCES Documents(id) ON DELETE CASCADE,
+    link TEXT NOT NULL,
+    ok BOOLEAN NOT NULL,
+    status_code INTEGER,
+    -- 跟随重定向之后的地址
+    final_url TEXT,
+    error TEXT,
+    checked_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_document_link_check_broken ON document_link_check (docid) WHERE NOT ok;
@@ -0,0 +1,9 @@
+-- Add migration script here
+ALTER TABLE TechL1 DROP COLUMN IF EXISTS sort_order_manual;
+ALTER TABLE TechL2 DROP COLUMN IF EXISTS sort_order_manual;
+ALTER TABLE SystemL1 DROP COLUMN IF EXISTS sort_order_manual;
+ALTER TABLE SystemL2 DROP COLUMN IF EXISTS sort_order_manual;
+ALTER TABLE MFL1 DROP COLUMN IF EXISTS sort_order_manual;
+ALTER TABLE MFL2 DROP COLUMN IF EXISTS sort_order_manual;
+ALTER TABLE ProjectL1 DROP COLUMN IF EXISTS sort_order_manual;
+ALTER TABLE ProjectL2 DROP COLUMN IF EXISTS sort_order_manual;
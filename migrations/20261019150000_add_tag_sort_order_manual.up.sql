@@ -0,0 +1,10 @@
+-- Add migration script here
+-- 通过 API 设置过顺序的标签，导入时不再按表格行顺序覆盖
+ALTER TABLE TechL1 ADD COLUMN sort_order_manual BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE TechL2 ADD COLUMN sort_order_manual BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE SystemL1 ADD COLUMN sort_order_manual BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE SystemL2 ADD COLUMN sort_order_manual BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE MFL1 ADD COLUMN sort_order_manual BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE MFL2 ADD COLUMN sort_order_manual BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE ProjectL1 ADD COLUMN sort_order_manual BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE ProjectL2 ADD COLUMN sort_order_manual BOOLEAN NOT NULL DEFAULT false;
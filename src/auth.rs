@@ -0,0 +1,386 @@
+//! Authentication and role based authorisation for the web server.
+//! A bearer token is either a static api token or a JWT signed by a key of the
+//! configured JWKS (a local file or the jwks_uri of an OIDC issuer).
+
+use anyhow::{anyhow, Context};
+use axum::extract::State;
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub fn from_name(s: &str) -> Option<Role> {
+        match s.trim().to_lowercase().as_str() {
+            "viewer" => Some(Role::Viewer),
+            "editor" => Some(Role::Editor),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+// who sent the request, stored in the request extensions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    pub subject: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    pub keys: JwkSet,
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    // claim holding a role name or a list of role names
+    pub role_claim: String,
+    // accepted for keys without `alg`, a key with `alg` only accepts that one
+    pub algorithms: Vec<Algorithm>,
+}
+
+// a static api token, its name is the subject of the requests made with it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiToken {
+    pub name: String,
+    pub role: Role,
+}
+
+impl ApiToken {
+    // subject of tokens configured without a name
+    pub const UNNAMED: &'static str = "api-token";
+
+    pub fn new(name: &str, role: Role) -> ApiToken {
+        ApiToken {
+            name: name.to_string(),
+            role,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuthConfig {
+    // token -> name and role
+    pub api_tokens: HashMap<String, ApiToken>,
+    pub jwt: Option<JwtConfig>,
+    // role of requests without Authorization header, None to reject them
+    pub anonymous_role: Option<Role>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    // missing or invalid credentials
+    Unauthorized(String),
+    // valid credentials, insufficient role
+    Forbidden(String),
+}
+
+impl JwtConfig {
+    pub fn from_jwks_file(path: &Path) -> anyhow::Result<JwtConfig> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read jwks file {}", path.display()))?;
+        let keys: JwkSet = serde_json::from_str(&text)
+            .with_context(|| format!("invalid jwks file {}", path.display()))?;
+        Ok(JwtConfig {
+            keys,
+            issuer: None,
+            audience: None,
+            role_claim: "roles".to_string(),
+            algorithms: vec![Algorithm::RS256],
+        })
+    }
+
+    // fetch the signing keys from the OIDC discovery document of the issuer
+    pub async fn from_issuer(issuer: &str) -> anyhow::Result<JwtConfig> {
+        let discovery_url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let discovery: JsonValue = reqwest::get(&discovery_url)
+            .await
+            .with_context(|| format!("failed to fetch {}", discovery_url))?
+            .json()
+            .await
+            .with_context(|| format!("invalid discovery document {}", discovery_url))?;
+        let jwks_uri = discovery["jwks_uri"]
+            .as_str()
+            .ok_or_else(|| anyhow!("discovery document {} has no jwks_uri", discovery_url))?;
+        let keys: JwkSet = reqwest::get(jwks_uri)
+            .await
+            .with_context(|| format!("failed to fetch {}", jwks_uri))?
+            .json()
+            .await
+            .with_context(|| format!("invalid jwks {}", jwks_uri))?;
+        Ok(JwtConfig {
+            keys,
+            issuer: Some(issuer.to_string()),
+            audience: None,
+            role_claim: "roles".to_string(),
+            algorithms: vec![Algorithm::RS256],
+        })
+    }
+}
+
+// "name:token:role,token:role", the name ends at the first ':' and may be left out
+pub fn parse_api_tokens(s: &str) -> anyhow::Result<HashMap<String, ApiToken>> {
+    let mut tokens = HashMap::new();
+    for entry in s.split(',').map(|entry| entry.trim()).filter(|entry| !entry.is_empty()) {
+        let (token, role) = entry
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("api token entry must look like name:token:role"))?;
+        let role = Role::from_name(role).ok_or_else(|| anyhow!("unknown role '{}'", role))?;
+        let (name, token) = token.split_once(':').unwrap_or((ApiToken::UNNAMED, token));
+        if name.is_empty() || token.is_empty() {
+            return Err(anyhow!("api token entry must look like name:token:role"));
+        }
+        tokens.insert(token.to_string(), ApiToken::new(name, role));
+    }
+    Ok(tokens)
+}
+
+impl AuthConfig {
+    // ARCH_MAP_API_TOKENS        name:token:role,token:role
+    // ARCH_MAP_API_TOKEN         single admin token
+    // ARCH_MAP_JWKS_FILE         local JWKS file
+    // ARCH_MAP_OIDC_ISSUER       issuer, keys fetched from its discovery document
+    // ARCH_MAP_JWT_AUDIENCE      expected aud claim
+    // ARCH_MAP_JWT_ROLE_CLAIM    claim holding the roles, default "roles"
+    // ARCH_MAP_JWT_ALGORITHMS    RS256,ES256 for keys without alg, default RS256
+    // ARCH_MAP_ANONYMOUS_ROLE    role without credentials, default viewer, "none" to disable
+    pub async fn from_env() -> anyhow::Result<AuthConfig> {
+        let var = |key: &str| dotenvy::var(key).ok().filter(|value| !value.is_empty());
+
+        let mut api_tokens = match var("ARCH_MAP_API_TOKENS") {
+            Some(tokens) => parse_api_tokens(&tokens).context("invalid ARCH_MAP_API_TOKENS")?,
+            None => HashMap::new(),
+        };
+        if let Some(token) = var("ARCH_MAP_API_TOKEN") {
+            api_tokens.insert(token, ApiToken::new(ApiToken::UNNAMED, Role::Admin));
+        }
+
+        let mut jwt = match (var("ARCH_MAP_JWKS_FILE"), var("ARCH_MAP_OIDC_ISSUER")) {
+            (Some(path), issuer) => {
+                let mut jwt = JwtConfig::from_jwks_file(Path::new(&path))?;
+                jwt.issuer = issuer;
+                Some(jwt)
+            }
+            (None, Some(issuer)) => Some(JwtConfig::from_issuer(&issuer).await?),
+            (None, None) => None,
+        };
+        if let Some(jwt) = jwt.as_mut() {
+            jwt.audience = var("ARCH_MAP_JWT_AUDIENCE");
+            if let Some(role_claim) = var("ARCH_MAP_JWT_ROLE_CLAIM") {
+                jwt.role_claim = role_claim;
+            }
+            if let Some(algorithms) = var("ARCH_MAP_JWT_ALGORITHMS") {
+                jwt.algorithms = algorithms
+                    .split(',')
+                    .map(|name| name.trim())
+                    .filter(|name| !name.is_empty())
+                    .map(|name| {
+                        Algorithm::from_str(name)
+                            .map_err(|_| anyhow!("invalid ARCH_MAP_JWT_ALGORITHMS '{}'", name))
+                    })
+                    .collect::<anyhow::Result<Vec<Algorithm>>>()?;
+            }
+        }
+
+        let anonymous_role = match var("ARCH_MAP_ANONYMOUS_ROLE") {
+            None => Some(Role::Viewer),
+            Some(role) if role == "none" => None,
+            Some(role) => Some(
+                Role::from_name(&role)
+                    .ok_or_else(|| anyhow!("invalid ARCH_MAP_ANONYMOUS_ROLE '{}'", role))?,
+            ),
+        };
+
+        Ok(AuthConfig {
+            api_tokens,
+            jwt,
+            anonymous_role,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Authenticator {
+    config: AuthConfig,
+}
+
+// compare without leaking the position of the first difference
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+impl Authenticator {
+    pub fn new(config: AuthConfig) -> Authenticator {
+        Authenticator { config }
+    }
+
+    // `authorization` is the raw value of the Authorization header
+    pub fn authenticate(&self, authorization: Option<&str>) -> Result<Principal, AuthError> {
+        let authorization = match authorization {
+            Some(authorization) => authorization,
+            None => {
+                return self
+                    .config
+                    .anonymous_role
+                    .map(|role| Principal {
+                        subject: "anonymous".to_string(),
+                        role,
+                    })
+                    .ok_or_else(|| AuthError::Unauthorized("missing credentials".to_string()))
+            }
+        };
+        let token = authorization
+            .strip_prefix("Bearer ")
+            .ok_or_else(|| AuthError::Unauthorized("expected a bearer token".to_string()))?
+            .trim();
+
+        for (api_token, named) in self.config.api_tokens.iter() {
+            if constant_time_eq(api_token.as_bytes(), token.as_bytes()) {
+                return Ok(Principal {
+                    subject: named.name.clone(),
+                    role: named.role,
+                });
+            }
+        }
+
+        match &self.config.jwt {
+            Some(jwt) => self.validate_jwt(jwt, token),
+            None => Err(AuthError::Unauthorized("invalid token".to_string())),
+        }
+    }
+
+    fn validate_jwt(&self, jwt: &JwtConfig, token: &str) -> Result<Principal, AuthError> {
+        let unauthorized = |err: &dyn std::fmt::Display| AuthError::Unauthorized(err.to_string());
+
+        let header = decode_header(token).map_err(|err| unauthorized(&err))?;
+        let jwk = match &header.kid {
+            Some(kid) => jwt.keys.find(kid),
+            None if jwt.keys.keys.len() == 1 => jwt.keys.keys.first(),
+            None => None,
+        }
+        .ok_or_else(|| AuthError::Unauthorized("unknown signing key".to_string()))?;
+        let key = DecodingKey::from_jwk(jwk).map_err(|err| unauthorized(&err))?;
+
+        // the algorithm comes from the key or the configuration, never from the token
+        let algorithms = match jwk.common.key_algorithm {
+            Some(key_algorithm) => {
+                let algorithm = Algorithm::from_str(&key_algorithm.to_string()).map_err(|_| {
+                    AuthError::Unauthorized("signing key can not verify signatures".to_string())
+                })?;
+                vec![algorithm]
+            }
+            None => jwt.algorithms.clone(),
+        };
+        if !algorithms.contains(&header.alg) {
+            return Err(AuthError::Unauthorized(format!(
+                "algorithm {:?} does not match the signing key",
+                header.alg
+            )));
+        }
+        let mut validation = Validation::new(header.alg);
+        validation.algorithms = algorithms;
+        match &jwt.issuer {
+            Some(issuer) => validation.set_issuer(&[issuer]),
+            None => validation.iss = None,
+        }
+        match &jwt.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let claims = decode::<JsonValue>(token, &key, &validation)
+            .map_err(|err| unauthorized(&err))?
+            .claims;
+
+        let role = match &claims[&jwt.role_claim] {
+            JsonValue::String(role) => Role::from_name(role),
+            JsonValue::Array(roles) => roles
+                .iter()
+                .filter_map(|role| role.as_str().and_then(Role::from_name))
+                .max(),
+            _ => None,
+        }
+        .ok_or_else(|| AuthError::Forbidden("token carries no known role".to_string()))?;
+
+        Ok(Principal {
+            subject: claims["sub"].as_str().unwrap_or("unknown").to_string(),
+            role,
+        })
+    }
+
+    pub fn authorize(&self, authorization: Option<&str>, required: Role) -> Result<Principal, AuthError> {
+        let principal = self.authenticate(authorization)?;
+        if principal.role < required {
+            return Err(AuthError::Forbidden(format!(
+                "{} role required",
+                required.as_str()
+            )));
+        }
+        Ok(principal)
+    }
+}
+
+// state of the `require_role` middleware, one per group of routes
+#[derive(Debug, Clone)]
+pub struct RequireRole {
+    pub authenticator: Arc<Authenticator>,
+    pub role: Role,
+}
+
+impl RequireRole {
+    pub fn new(authenticator: Arc<Authenticator>, role: Role) -> RequireRole {
+        RequireRole {
+            authenticator,
+            role,
+        }
+    }
+}
+
+pub async fn require_role<B>(
+    State(guard): State<RequireRole>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, StatusCode> {
+    let authorization = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+
+    match guard.authenticator.authorize(authorization, guard.role) {
+        Ok(principal) => {
+            req.extensions_mut().insert(principal);
+            Ok(next.run(req).await)
+        }
+        Err(AuthError::Unauthorized(reason)) => {
+            tracing::info!("unauthorized request to {}: {}", req.uri(), reason);
+            Err(StatusCode::UNAUTHORIZED)
+        }
+        Err(AuthError::Forbidden(reason)) => {
+            tracing::info!("forbidden request to {}: {}", req.uri(), reason);
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+}
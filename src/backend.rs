@@ -1,1058 +1,944 @@
 // use anyhow::anyhow;
 use arch_map::database::dml_interface::*;
+use arch_map::graphql;
 use arch_map::database::models::*;
-use axum::extract::Path;
-use axum::response::Json;
+use arch_map::database::repository::{MemoryRepository, PgRepository, Repository};
+use arch_map::report::cooccurrence::cooccurrence;
+use arch_map::report::coverage::{aspice_coverage, CoverageFilter};
+use arch_map::auth::Principal;
+use crate::crud::{begin, parse_dimension, parse_level, ApiError, ApiResult};
+use arch_map::report::graph;
+use arch_map::report::history;
+use arch_map::report::impact;
+use arch_map::report::page::{self, ListOptions, SortKeys};
+use arch_map::report::quality::{data_quality, QualityOptions};
+use arch_map::report::trace::TraceGraph;
+use arch_map::report::tree::{self, ProjectScope};
+use axum::extract::{Extension, Path, Query, State};
+use axum::response::{IntoResponse, Json, Response};
+use chrono::{DateTime, Utc};
+use http::{header, StatusCode};
+use serde::Deserialize;
 use serde_json::Value as JsonValue;
 use serde_json::{json, to_value};
 // use sqlx::any;
-use core::panic;
 use std::collections::HashMap;
-use std::collections::HashSet;
-use std::collections::VecDeque;
+use std::sync::Arc;
 // use std::hash::Hash;
+use sqlx::PgPool;
 use uuid::Uuid;
-/*
-{
-    'tag': 'filter_tag_name',
-    'text': {
-        'all': [doc1, doc2],
-        'arch': [
-            {
-                'name': 'techl2_tag1',
-                'text': [
-                    {
-                        'name': doca_name,
-                        'address': doca_address,
-                        'isRelated': true or false,
-                    },
-                    doc_b,
-                    ...
-                 ],
-            },
-            {
-                ...
-            },
-        ],
-        'component': {},
-        'mf': {},
-        'project': {},
-    }
-}
-
-*/
 
-pub async fn filter_and_classify(Path(tag_name): Path<String>) -> Json<JsonValue> {
-    let mut response: HashMap<String, JsonValue> = HashMap::new();
-    response.insert(
-        "tag".to_string(),
-        to_value(String::from(&tag_name)).unwrap(),
-    );
-    response.insert(
-        "text".to_string(),
-        to_value(HashMap::<String, JsonValue>::new()).unwrap(),
-    );
+// query: ?as_of=<snapshot name or rfc3339 time>, the current map if missing
+#[derive(Debug, Deserialize)]
+pub struct AsOfQuery {
+    pub as_of: Option<String>,
+}
 
-    let mut module_type = Some("arch");
-    let mut docs = query_docs_by_techl2(&tag_name).await.unwrap();
-    if docs.is_empty() {
-        docs = query_docs_by_systeml2(&tag_name).await.unwrap();
-        module_type = Some("component");
-    }
-    if docs.is_empty() {
-        docs = query_docs_by_mfl2(&tag_name).await.unwrap();
-        module_type = Some("mf");
-    }
-    if docs.is_empty() {
-        docs = query_docs_by_projectl2(&tag_name).await.unwrap();
-        module_type = Some("project");
-    }
-    if docs.is_empty() {
-        module_type = None;
-        return Json(json!("No documents"));
+// the current map, or the map as it was in the snapshot `as_of` refers to
+async fn map_repository(db: PgPool, as_of: Option<&str>) -> Result<Box<dyn Repository>, Response> {
+    let as_of = match as_of {
+        Some(as_of) => as_of,
+        None => return Ok(Box::new(PgRepository::new(db))),
+    };
+    let snapshot = read_snapshot_as_of(&db, as_of)
+        .await
+        .map_err(|err| ApiError::from(err).into_response())?;
+    match snapshot {
+        Some((_, data)) => match MemoryRepository::from_snapshot(data).await {
+            Ok(repo) => Ok(Box::new(repo)),
+            Err(err) => Err(ApiError::from(err).into_response()),
+        },
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("no snapshot as of '{}'", as_of) })),
+        )
+            .into_response()),
     }
+}
 
-    //let docs = docs.unwrap();
-
-    // Get a mutable reference to the "text" object
-    if let Some(text_obj) = response.get_mut("text") {
-        if let Some(text_map) = text_obj.as_object_mut() {
-            text_map.insert(
-                "all".to_string(),
-                docs.iter().map(|doc| doc.to_json_value(true)).collect(),
-            );
-            if module_type.unwrap() != "arch" {
-                text_map.insert("arch".to_string(), group_by_techl2(&docs).await.unwrap());
-            }
-
-            if module_type.unwrap() != "component" {
-                text_map.insert(
-                    "component".to_string(),
-                    group_by_systeml2(&docs).await.unwrap(),
-                );
-            }
+// query: ?project=<L1 or L2 project tag>,<another>, the whole map if missing
+// the documents the selected projects share with the map, 400 for unknown projects
+async fn project_scope(repo: &dyn Repository, project: Option<&str>) -> ApiResult<ProjectScope> {
+    let projects: Vec<String> = project
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    ProjectScope::load(repo, &projects)
+        .await
+        .map_err(|err| ApiError::BadRequest(err.to_string()))
+}
 
-            if module_type.unwrap() != "mf" {
-                text_map.insert("mf".to_string(), group_by_mfl2(&docs).await.unwrap());
-            } else {
-                text_map.insert(
-                    "request".to_string(),
-                    get_aspice_graph(&tag_name).await.unwrap(),
-                );
-            }
+// query: ?limit=50&cursor=<next_cursor>&sort=name|-name|modified|-modified|aspice|-aspice&fields=id,name
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    pub sort: Option<String>,
+    pub fields: Option<String>,
+}
 
-            if module_type.unwrap() != "project" {
-                let res = group_by_projectl2(&docs).await.unwrap();
-                text_map.insert("project".to_string(), res.1);
-                text_map.insert("mainLine".to_string(), res.0);
-            }
-        } else {
-            println!("'text' is not an object");
-        }
-    } else {
-        println!("'text' key not found");
+impl ListQuery {
+    // `allowed`: the fields of the listed documents
+    fn options(&self, allowed: &[&str]) -> anyhow::Result<ListOptions> {
+        ListOptions::parse(
+            self.limit,
+            self.cursor.as_deref(),
+            self.sort.as_deref(),
+            self.fields.as_deref(),
+            allowed,
+        )
     }
-    Json(to_value(response).unwrap())
 }
 
 /*
-[
-    {
-        'label': 'techl1_tag1',
-        'level': 1,
-        'children': [
-            {
-                'label': 'techl2_tag1',
-                'level': 2,
-                'content': [], # correspond components
-                'textNum': $num,
-            }
-        ]
-    },
-    ...
-]
+[doc1, doc2] without limit and cursor, else a page:
+{
+    'items': [doc1, doc2],
+    'total': 120, // documents of the whole list
+    'next_cursor': '7b22...', // null on the last page
+}
 */
+async fn listing(db: &PgPool, docs: Vec<JsonValue>, list: &ListOptions) -> Response {
+    let page = match sorted_page(&PgRepository::new(db.clone()), docs, list).await {
+        Ok(page) => page,
+        Err(err) => return err.into_response(),
+    };
+    if list.is_paged() {
+        Json(to_value(page).unwrap()).into_response()
+    } else {
+        Json(JsonValue::Array(page.items)).into_response()
+    }
+}
 
+// the cursor and the sort are checked by ListOptions, errors here come from the database
+async fn sorted_page(
+    repo: &dyn Repository,
+    docs: Vec<JsonValue>,
+    list: &ListOptions,
+) -> Result<page::Page, ApiError> {
+    let keys = SortKeys::load(repo, list).await?;
+    Ok(page::page(docs, list, &keys)?)
+}
 
+const CLASSIFIED_FIELDS: [&str; 6] = ["id", "name", "address", "description", "isRelated", "linkStatus"];
+
+// query: ?as_of=<snapshot>&project=&limit=&sort=&fields= as in ListQuery, pages start at the first document
+#[derive(Debug, Deserialize)]
+pub struct ClassifyQuery {
+    pub as_of: Option<String>,
+    pub project: Option<String>,
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    pub sort: Option<String>,
+    pub fields: Option<String>,
+}
 
-pub async fn arch_tree(Path(cross_module): Path<String>) -> Json<JsonValue> {
-    let db = get_db_pool().await.unwrap();
-    let records = sqlx::query!(
-        r#"
-        SELECT techl1.name as l1name, techl2.name as l2name
-        FROM techl1
-        INNER JOIN techl2 ON techl1.id = techl2.fatherid
-    "#
-    )
-    .fetch_all(&db)
-    .await
-    .unwrap();
-
-    let mut layered_tags = HashMap::new();
-    let mut correspond_tags = HashMap::new();
-    for record in records {
-        layered_tags
-            .entry(record.l1name)
-            .or_insert_with(Vec::new)
-            .push(record.l2name.clone());
-
-        match cross_module.as_str() {
-            "component" => {
-                correspond_tags.insert(
-                    record.l2name.clone(),
-                    arch_correspond_components(&record.l2name).await,
-                );
-            }
-            "mf" => {
-                correspond_tags.insert(
-                    record.l2name.clone(),
-                    arch_correspond_mfs(&record.l2name).await,
-                );
-            }
-            _ => {
-                panic!("Invalid cross module");
-            }
-        }
+pub async fn filter_and_classify(
+    State(db): State<PgPool>,
+    Path(tag_name): Path<String>,
+    Query(query): Query<ClassifyQuery>,
+) -> Response {
+    if query.cursor.is_some() {
+        let error = json!({ "error": "a cursor belongs to one list, use /filter_and_classify/:tag_name/documents" });
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
     }
-
-    let mut result_vec = Vec::new();
-    for (l1name, l2names) in layered_tags.into_iter() {
-        let mut l2_vec = Vec::new();
-        for l2name in l2names {
-            let mut l2_obj = json!({
-                "label": l2name,
-                "level": 2,
-                "textNum": query_docs_by_techl2(&l2name).await.unwrap().len(),
-            });
-            if let Some(correspond_tags) = correspond_tags.get(&l2name) {
-                l2_obj["content"] = to_value(correspond_tags.clone()).unwrap();
-            }
-            l2_vec.push(l2_obj);
+    let list = ListQuery {
+        limit: query.limit,
+        cursor: None,
+        sort: query.sort,
+        fields: query.fields,
+    };
+    let list = match list.options(&CLASSIFIED_FIELDS) {
+        Ok(list) => list,
+        Err(err) => {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": err.to_string() }))).into_response()
         }
-
-        l2_vec.sort_by(|a, b| {
-            let a_label = a["label"].as_str().unwrap_or("");
-            let b_label = b["label"].as_str().unwrap_or("");
-            a_label.cmp(b_label)
-        });
-
-        let l1_obj = json!({
-            "label": l1name,
-            "level": 1,
-            "children": l2_vec,
-        });
-        result_vec.push(l1_obj);
+    };
+    let repo = match map_repository(db, query.as_of.as_deref()).await {
+        Ok(repo) => repo,
+        Err(response) => return response,
+    };
+    let scope = match project_scope(&*repo, query.project.as_deref()).await {
+        Ok(scope) => scope,
+        Err(err) => return err.into_response(),
+    };
+    match tree::filter_and_classify(&*repo, &tag_name, &scope, &list).await {
+        Ok(result) => Json(result).into_response(),
+        Err(err) => ApiError::from(err).into_response(),
     }
-
-    result_vec.sort_by(|a, b| TECHL1COMP.compare(a["label"].as_str().unwrap(), b["label"].as_str().unwrap()));
-    Json(to_value(result_vec).unwrap())
 }
 
-pub async fn component_tree(Path(cross_module): Path<String>) -> Json<JsonValue> {
-    let db = get_db_pool().await.unwrap();
-    let records = sqlx::query!(
-        r#"
-        SELECT systeml1.name as l1name, systeml2.name as l2name
-        FROM systeml1
-        INNER JOIN systeml2 ON systeml1.id = systeml2.fatherid
-    "#
-    )
-    .fetch_all(&db)
-    .await
-    .unwrap();
-
-    let mut layered_tags = HashMap::new();
-    let mut correspond_tags = HashMap::new();
-    for record in records {
-        layered_tags
-            .entry(record.l1name)
-            .or_insert_with(Vec::new)
-            .push(record.l2name.clone());
+// query: ?group=<dimension>:<tag>&as_of=&project=&limit=&cursor=&sort=&fields=, `all` without group
+#[derive(Debug, Deserialize)]
+pub struct ClassifiedPageQuery {
+    pub group: Option<String>,
+    pub as_of: Option<String>,
+    pub project: Option<String>,
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    pub sort: Option<String>,
+    pub fields: Option<String>,
+}
 
-        match cross_module.as_str() {
-            "arch" => {
-                correspond_tags.insert(
-                    record.l2name.clone(),
-                    component_cross_archs(&record.l2name).await,
-                );
-            }
-            _ => {
-                panic!("Invalid cross module")
+/*
+{
+    'items': [doc1, doc2], // as in filter_and_classify
+    'total': 120,
+    'next_cursor': '7b22...', // null on the last page
+}
+*/
+// further pages of `all` or of one group of filter_and_classify
+pub async fn classified_documents(
+    State(db): State<PgPool>,
+    Path(tag_name): Path<String>,
+    Query(query): Query<ClassifiedPageQuery>,
+) -> Response {
+    let group = match query.group.as_deref().map(|group| group.split_once(':')) {
+        None => None,
+        Some(Some((dimension, name))) => match Dimension::from_name(dimension) {
+            Some(dimension) => Some((dimension, name)),
+            None => {
+                let error = json!({ "error": format!("unknown dimension '{}'", dimension) });
+                return (StatusCode::BAD_REQUEST, Json(error)).into_response();
             }
+        },
+        Some(None) => {
+            let error = json!({ "error": "group must be <dimension>:<tag>" });
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
         }
-    }
-
-    let mut result_vec = Vec::new();
-    for (l1name, l2names) in layered_tags.into_iter() {
-        let mut l2_vec = Vec::new();
-        for l2name in l2names {
-            let mut l2_obj = json!({
-                "label": l2name,
-                "level": 2,
-                "textNum": query_docs_by_systeml2(&l2name).await.unwrap().len(),
-            });
-            if let Some(correspond_tags) = correspond_tags.get(&l2name) {
-                l2_obj["content"] = to_value(correspond_tags.clone()).unwrap();
-            }
-            l2_vec.push(l2_obj);
+    };
+    let list = ListQuery {
+        limit: query.limit,
+        cursor: query.cursor,
+        sort: query.sort,
+        fields: query.fields,
+    };
+    let list = match list.options(&CLASSIFIED_FIELDS) {
+        Ok(list) => list,
+        Err(err) => {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": err.to_string() }))).into_response()
+        }
+    };
+    let repo = match map_repository(db, query.as_of.as_deref()).await {
+        Ok(repo) => repo,
+        Err(response) => return response,
+    };
+    let scope = match project_scope(&*repo, query.project.as_deref()).await {
+        Ok(scope) => scope,
+        Err(err) => return err.into_response(),
+    };
+
+    let docs = match tree::classified_documents(&*repo, &tag_name, &scope, group).await {
+        Ok(docs) => docs,
+        Err(err) => return ApiError::from(err).into_response(),
+    };
+    match docs {
+        Some(docs) => match sorted_page(&*repo, docs, &list).await {
+            Ok(page) => Json(to_value(page).unwrap()).into_response(),
+            Err(err) => err.into_response(),
+        },
+        None => {
+            let error = json!({ "error": "no documents" });
+            (StatusCode::NOT_FOUND, Json(error)).into_response()
         }
-
-        l2_vec.sort_by(|a, b| {
-            let a_label = a["label"].as_str().unwrap_or("");
-            let b_label = b["label"].as_str().unwrap_or("");
-            a_label.cmp(b_label)
-        });
-
-        let l1_obj = json!({
-            "label": l1name,
-            "level": 1,
-            "children": l2_vec,
-        });
-        result_vec.push(l1_obj);
     }
-    result_vec.sort_by(|a, b| {
-        let a_label = a["label"].as_str().unwrap_or("");
-        let b_label = b["label"].as_str().unwrap_or("");
-        a_label.cmp(b_label)
-    });
-
-    Json(to_value(result_vec).unwrap())
 }
 
-pub async fn mf_tree(Path(cross_module): Path<String>) -> Json<JsonValue> {
-    let db = get_db_pool().await.unwrap();
-    let records = sqlx::query!(
-        r#"
-        SELECT mfl1.name as l1name, mfl2.name as l2name
-        FROM mfl1
-        INNER JOIN mfl2 ON mfl1.id = mfl2.fatherid
-    "#
-    )
-    .fetch_all(&db)
-    .await
-    .unwrap();
-
-    let mut layered_tags = HashMap::new();
-    let mut correspond_tags = HashMap::new();
-    for record in records {
-        layered_tags
-            .entry(record.l1name)
-            .or_insert_with(Vec::new)
-            .push(record.l2name.clone());
+// query: ?as_of=<snapshot>&project=<L1 or L2 project tag>,<another>
+#[derive(Debug, Deserialize)]
+pub struct TreeQuery {
+    pub as_of: Option<String>,
+    pub project: Option<String>,
+}
 
-        match cross_module.as_str() {
-            "arch" => {
-                correspond_tags.insert(record.l2name.clone(), mf_cross_archs(&record.l2name).await);
-            }
-            _ => {
-                panic!("Invalid cross module")
-            }
+// cross_module: the dimension whose tags are listed in `content`
+async fn tag_tree(db: PgPool, dimension: Dimension, cross_module: &str, query: TreeQuery) -> Response {
+    let cross = match Dimension::from_name(cross_module) {
+        Some(cross) => cross,
+        None => {
+            let error = json!({ "error": format!("unknown dimension '{}'", cross_module) });
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
         }
+    };
+    if cross == dimension {
+        let error = json!({ "error": format!("cannot cross '{}' with itself", cross.as_str()) });
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
     }
-
-    let mut result_vec = Vec::new();
-    for (l1name, l2names) in layered_tags.into_iter() {
-        let mut l2_vec = Vec::new();
-        for l2name in l2names {
-            let mut l2_obj = json!({
-                "label": l2name,
-                "level": 2,
-                "textNum": query_docs_by_mfl2(&l2name).await.unwrap().len(),
-            });
-            if let Some(correspond_tags) = correspond_tags.get(&l2name) {
-                l2_obj["content"] = to_value(correspond_tags.clone()).unwrap();
-            }
-            l2_vec.push(l2_obj);
-        }
-
-        l2_vec.sort_by(|a, b| {
-            let a_label = a["label"].as_str().unwrap_or("");
-            let b_label = b["label"].as_str().unwrap_or("");
-            a_label.cmp(b_label)
-        });
-
-        let l1_obj = json!({
-            "label": l1name,
-            "level": 1,
-            "children": l2_vec,
-        });
-        result_vec.push(l1_obj);
+    let repo = match map_repository(db, query.as_of.as_deref()).await {
+        Ok(repo) => repo,
+        Err(response) => return response,
+    };
+    let scope = match project_scope(&*repo, query.project.as_deref()).await {
+        Ok(scope) => scope,
+        Err(err) => return err.into_response(),
+    };
+    match tree::tag_tree(&*repo, dimension, cross, &scope).await {
+        Ok(tree) => Json(tree).into_response(),
+        Err(err) => ApiError::from(err).into_response(),
     }
-
-    result_vec.sort_by(|a, b| {
-        let a_label = a["label"].as_str().unwrap_or("");
-        let b_label = b["label"].as_str().unwrap_or("");
-        a_label.cmp(b_label)
-    });
-
-    Json(to_value(result_vec).unwrap())
 }
 
-/*
-       text:[
-               {
-                   name: '传感器方案',
-                   level: 1,
-                   children: [
-                       {
-                           name: '传感器布局',
-                           level: 2,
-                           children:[
-                               {
-                                   name: 'Rocky AB面传感器配置',
-                                   address: 'https://momenta.feishu.cn/wiki/ORuBw5pT8iAUmPkkaEYcRsc1nLh'
-                               },
-                               {
-                                   name: 'Rocky sensor set with ASIL_v0.0.4_0330.xlsx',
-                                   address: 'https://momenta.feishu.cn/wiki/HC1uwEdemiusn3k7shGcwUZMnYe'
-                               }
-                           ]
-                       },
-                   ]
-               },
-       ]
-*/
-pub async fn project_arch_tree(Path(project_name): Path<String>) -> Json<JsonValue> {
-    let db = get_db_pool().await.unwrap();
-    let docs_having_project_tag = sqlx::query!(
-        r#"
-        SELECT documentproject.docid
-        FROM documentproject
-        INNER JOIN projectl2 ON documentproject.projectid = projectl2.id
-        WHERE projectl2.name = $1
-    "#,
-        project_name
-    )
-    .fetch_all(&db)
-    .await
-    .unwrap()
-    .into_iter()
-    .map(|record| record.docid)
-    .collect::<Vec<Uuid>>();
-
-    let records = sqlx::query!(r#"
-        SELECT techl1.name as l1name, techl2.name as l2name, documenttech.docid, documents.name as docname, documents.link as doclink
-        FROM techl1
-        INNER JOIN techl2 ON techl1.id = techl2.fatherid
-        INNER JOIN documenttech ON techl2.id = documenttech.techid
-        INNER JOIN documents ON documenttech.docid = documents.id
-    "#).fetch_all(&db).await.unwrap().into_iter().filter(|record| docs_having_project_tag.contains(&record.docid));
-
-    let mut layered_tags = HashMap::new();
-    let mut correspond_docs = HashMap::new();
-    for record in records {
-        layered_tags
-            .entry(record.l1name)
-            .or_insert_with(HashSet::new)
-            .insert(record.l2name.clone());
-
-        correspond_docs
-            .entry(record.l2name)
-            .or_insert_with(Vec::new)
-            .push(json!({
-                "name": record.docname,
-                "address": record.doclink,
-            }));
-    }
-
-    let mut arch_vec = layered_tags
-        .into_iter()
-        .map(|(l1name, l2names)| {
-            let mut l2_vec = l2names
-                .into_iter()
-                .map(|l2name| {
-                    let mut l2_obj = json!({
-                        "name": l2name,
-                        "level": 2,
-                    });
-                    if let Some(correspond_docs) = correspond_docs.get(&l2name) {
-                        l2_obj["children"] = to_value(correspond_docs.clone()).unwrap();
-                    }
-                    l2_obj
-                })
-                .collect::<Vec<JsonValue>>();
-
-            l2_vec.sort_by(|a, b| {
-                let a_label = a["name"].as_str().unwrap_or("");
-                let b_label = b["name"].as_str().unwrap_or("");
-                a_label.cmp(b_label)
-            });
-
-            let l1_obj = json!({
-                "name": l1name,
-                "level": 1,
-                "children": l2_vec,
-            });
-            l1_obj
-        })
-        .collect::<Vec<JsonValue>>();
-
-    arch_vec.sort_by(|a, b| TECHL1COMP.compare(a["name"].as_str().unwrap(), b["name"].as_str().unwrap()));
-
-    Json(to_value(arch_vec).unwrap())
+pub async fn arch_tree(
+    State(db): State<PgPool>,
+    Path(cross_module): Path<String>,
+    Query(query): Query<TreeQuery>,
+) -> Response {
+    tag_tree(db, Dimension::Tech, &cross_module, query).await
 }
 
-async fn arch_correspond_components(techl2: &str) -> Vec<String> {
-    let db = get_db_pool().await.unwrap();
-    // techl2_name | techl2_id | doc_id
-    let docs_having_techl2 = sqlx::query!(
-        r#"
-        SELECT documenttech.docid
-        FROM techl2
-        INNER JOIN Documenttech ON techl2.id = documenttech.techid
-        WHERE techl2.name = $1
-    "#,
-        techl2
-    )
-    .fetch_all(&db)
-    .await
-    .unwrap()
-    .into_iter()
-    .map(|record| record.docid)
-    .collect::<HashSet<Uuid>>();
-
-    let result = sqlx::query!(
-        r#"
-        SELECT systeml2.name, documentsystem.docid
-        FROM systeml2
-        INNER JOIN documentsystem ON systeml2.id = documentsystem.sysid
-        "#
-    )
-    .fetch_all(&db)
-    .await
-    .unwrap()
-    .into_iter()
-    .filter(|record| docs_having_techl2.contains(&record.docid))
-    .map(|record| record.name)
-    .collect::<HashSet<String>>();
-
-    result.into_iter().collect()
+pub async fn component_tree(
+    State(db): State<PgPool>,
+    Path(cross_module): Path<String>,
+    Query(query): Query<TreeQuery>,
+) -> Response {
+    tag_tree(db, Dimension::System, &cross_module, query).await
 }
 
-async fn arch_correspond_mfs(techl2: &str) -> Vec<String> {
-    let db = get_db_pool().await.unwrap();
-    // techl2_name | techl2_id | doc_id
-    let docs_having_techl2 = sqlx::query!(
-        r#"
-        SELECT documenttech.docid
-        FROM techl2
-        INNER JOIN Documenttech ON techl2.id = documenttech.techid
-        WHERE techl2.name = $1
-    "#,
-        techl2
-    )
-    .fetch_all(&db)
-    .await
-    .unwrap()
-    .into_iter()
-    .map(|record| record.docid)
-    .collect::<Vec<Uuid>>();
-
-    let mut result = sqlx::query!(
-        r#"
-        SELECT mfl2.name, documentmf.docid
-        FROM mfl2
-        INNER JOIN documentmf ON mfl2.id = documentmf.mfid
-        "#
-    )
-    .fetch_all(&db)
-    .await
-    .unwrap()
-    .into_iter()
-    .filter(|record| docs_having_techl2.contains(&record.docid))
-    .map(|record| record.name)
-    .collect::<Vec<String>>();
-
-    result.sort();
-    result.dedup();
-    result
+pub async fn mf_tree(
+    State(db): State<PgPool>,
+    Path(cross_module): Path<String>,
+    Query(query): Query<TreeQuery>,
+) -> Response {
+    tag_tree(db, Dimension::Mf, &cross_module, query).await
 }
 
-async fn component_cross_archs(systeml2: &str) -> Vec<String> {
-    let db = get_db_pool().await.unwrap();
-    // systeml2_name | systeml2_id | doc_id
-    let docs_having_systeml2 = sqlx::query!(
-        r#"
-        SELECT documentsystem.docid
-        FROM systeml2
-        INNER JOIN documentsystem ON systeml2.id = documentsystem.sysid
-        WHERE systeml2.name = $1
-    "#,
-        systeml2
-    )
-    .fetch_all(&db)
-    .await
-    .unwrap()
-    .into_iter()
-    .map(|record| record.docid)
-    .collect::<Vec<Uuid>>();
-
-    let mut result = sqlx::query!(
-        r#"
-        SELECT techl2.name, documenttech.docid
-        FROM techl2
-        INNER JOIN documenttech ON techl2.id = documenttech.techid
-        "#
-    )
-    .fetch_all(&db)
-    .await
-    .unwrap()
-    .into_iter()
-    .filter(|record| docs_having_systeml2.contains(&record.docid))
-    .map(|record| record.name)
-    .collect::<Vec<String>>();
-
-    result.sort();
-    result.dedup();
-    result
+pub async fn project_arch_tree(
+    State(db): State<PgPool>,
+    Path(project_name): Path<String>,
+    Query(query): Query<AsOfQuery>,
+) -> Response {
+    let repo = match map_repository(db, query.as_of.as_deref()).await {
+        Ok(repo) => repo,
+        Err(response) => return response,
+    };
+    Json(tree::project_tree(&*repo, &project_name).await.unwrap()).into_response()
 }
 
-async fn mf_cross_archs(mfl2: &str) -> Vec<String> {
-    let db = get_db_pool().await.unwrap();
-    // mfl2_name | mfl2_id | doc_id
-    let docs_having_mfl2 = sqlx::query!(
-        r#"
-        SELECT documentmf.docid
-        FROM mfl2
-        INNER JOIN documentmf ON mfl2.id = documentmf.mfid
-        WHERE mfl2.name = $1
-    "#,
-        mfl2
-    )
-    .fetch_all(&db)
-    .await
-    .unwrap()
-    .into_iter()
-    .map(|record| record.docid)
-    .collect::<Vec<Uuid>>();
-
-    let mut result = sqlx::query!(
-        r#"
-        SELECT techl2.name, documenttech.docid
-        FROM techl2
-        INNER JOIN documenttech ON techl2.id = documenttech.techid
-        "#
-    )
-    .fetch_all(&db)
-    .await
-    .unwrap()
-    .into_iter()
-    .filter(|record| docs_having_mfl2.contains(&record.docid))
-    .map(|record| record.name)
-    .collect::<Vec<String>>();
-
-    result.sort();
-    result.dedup();
-    result
+// query: ?level=l1|l2 (default l2)&as_of=<snapshot>
+#[derive(Debug, Deserialize)]
+pub struct CooccurrenceQuery {
+    pub level: Option<String>,
+    pub as_of: Option<String>,
 }
 
 /*
- */
-
-async fn query_docs_by_techl2(tag_name: &str) -> anyhow::Result<Vec<Document>> {
-    let db = get_db_pool().await?;
-
-    let mut docs = sqlx::query!(
-        r#"
-        SELECT documents.* FROM documents
-        INNER JOIN documenttech ON documents.id = documenttech.docid
-        INNER JOIN techl2 ON documenttech.techid = techl2.id
-        WHERE techl2.name = $1
-        ORDER BY documents.id
-    "#,
-        tag_name
-    )
-    .fetch_all(&db)
-    .await?;
-
-    let docs: Vec<Document> = docs
-        .iter_mut()
-        .map(|doc| Document {
-            id: doc.id,
-            name: doc.name.clone(),
-            link: doc.link.clone(),
-            description: doc.description.clone(),
-            associate_requirement: doc.associate_requirement,
-        })
-        .collect();
-
-    Ok(docs)
+{
+    'rows': { 'dimension': 'arch', 'level': 2, 'labels': ['目标检测', ...], 'totals': [3, ...] },
+    'columns': { 'dimension': 'component', 'level': 2, 'labels': [...], 'totals': [...] },
+    'counts': [[2, 0, ...], ...], // counts[i][j]: documents having rows.labels[i] and columns.labels[j]
+    'max': 2,
+}
+*/
+pub async fn cooccurrence_matrix(
+    State(db): State<PgPool>,
+    Path((rows, columns)): Path<(String, String)>,
+    Query(query): Query<CooccurrenceQuery>,
+) -> Response {
+    let bad_request = |message: String| {
+        (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+    };
+    let (rows, columns) = match (Dimension::from_name(&rows), Dimension::from_name(&columns)) {
+        (Some(rows), Some(columns)) => (rows, columns),
+        (None, _) => return bad_request(format!("unknown dimension '{}'", rows)),
+        (_, None) => return bad_request(format!("unknown dimension '{}'", columns)),
+    };
+    let level = match query.level.as_deref().map(TagLevel::from_name) {
+        None => TagLevel::L2,
+        Some(Some(level)) => level,
+        Some(None) => {
+            return bad_request(format!("unknown tag level '{}'", query.level.unwrap_or_default()))
+        }
+    };
+
+    let repo = match map_repository(db, query.as_of.as_deref()).await {
+        Ok(repo) => repo,
+        Err(response) => return response,
+    };
+    let matrix = cooccurrence(&*repo, rows, columns, level).await.unwrap();
+    Json(to_value(matrix).unwrap()).into_response()
 }
 
-async fn query_docs_by_systeml2(tag_name: &str) -> anyhow::Result<Vec<Document>> {
-    let db = get_db_pool().await?;
-    let docs = sqlx::query_as!(
-        Document,
-        r#"
-        SELECT documents.* FROM documents
-        INNER JOIN documentsystem ON documents.id = documentsystem.docid
-        INNER JOIN systeml2 ON documentsystem.sysid = systeml2.id
-        WHERE systeml2.name = $1
-        ORDER BY documents.id
-    "#,
-        tag_name
-    )
-    .fetch_all(&db)
-    .await?;
-
-    Ok(docs)
+// query: ?format=json|dot|graphml|mermaid&around=<type>:<name>&hops=2&as_of=<snapshot>
+#[derive(Debug, Deserialize)]
+pub struct GraphQuery {
+    pub format: Option<String>,
+    pub around: Option<String>,
+    pub hops: Option<usize>,
+    pub as_of: Option<String>,
 }
 
-async fn query_docs_by_mfl2(tag_name: &str) -> anyhow::Result<Vec<Document>> {
-    let db = get_db_pool().await?;
-    Ok(sqlx::query_as!(
-        Document,
-        r#"
-        SELECT documents.* FROM documents
-        INNER JOIN documentmf ON documents.id = documentmf.docid
-        INNER JOIN mfl2 ON documentmf.mfid = mfl2.id
-        WHERE mfl2.name = $1
-        ORDER BY documents.id
-    "#,
-        tag_name
-    )
-    .fetch_all(&db)
-    .await?)
+/*
+{
+    'nodes': [
+        { 'id': 'n0', 'type': 'requirement', 'label': '需求1', 'address': 'https://...' },
+        { 'id': 'n5', 'type': 'component_l2', 'label': '激光雷达', 'address': null },
+    ],
+    'edges': [
+        { 'source': 'n0', 'target': 'n5', 'type': 'tagged' }, // belongs_to, requires, refines, verifies, implements
+    ],
 }
+or the same graph as dot, graphml or mermaid text
+*/
+pub async fn graph_export(State(db): State<PgPool>, Query(query): Query<GraphQuery>) -> Response {
+    let repo = match map_repository(db, query.as_of.as_deref()).await {
+        Ok(repo) => repo,
+        Err(response) => return response,
+    };
+    let mut map = graph::graph(&*repo).await.unwrap();
+    if let Some(around) = &query.around {
+        map = match map.around(around, query.hops.unwrap_or(2)) {
+            Ok(map) => map,
+            Err(err) => {
+                return (StatusCode::BAD_REQUEST, Json(json!({ "error": err.to_string() })))
+                    .into_response()
+            }
+        };
+    }
 
-async fn query_docs_by_projectl2(tag_name: &str) -> anyhow::Result<Vec<Document>> {
-    let db = get_db_pool().await?;
-    Ok(sqlx::query_as!(
-        Document,
-        r#"
-        SELECT documents.* FROM documents
-        INNER JOIN documentproject ON documents.id = documentproject.docid
-        INNER JOIN projectl2 ON documentproject.projectid = projectl2.id
-        WHERE projectl2.name = $1
-        ORDER BY documents.id
-    "#,
-        tag_name
+    let (content_type, file_name, text) = match query.format.as_deref() {
+        Some("dot") => ("text/vnd.graphviz; charset=utf-8", "arch_map.dot", map.to_dot()),
+        Some("graphml") => ("application/graphml+xml; charset=utf-8", "arch_map.graphml", map.to_graphml()),
+        Some("mermaid") => ("text/plain; charset=utf-8", "arch_map.mmd", map.to_mermaid()),
+        _ => return Json(to_value(map).unwrap()).into_response(),
+    };
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        text,
     )
-    .fetch_all(&db)
-    .await?)
+        .into_response()
 }
 
-// struct TagFilterResponse {
-//     tag: String,
-//     text: HashMap<String, JsonValue>,
-// }
-
-// fn check_documents_order(docs: &Vec<Document>) -> bool {
-//     docs.windows(2).all(|w| w[0] <= w[1])
-// }
+// query: ?start=<type>:<name>&depth=2&trace_links=true&as_of=<snapshot>
+#[derive(Debug, Deserialize)]
+pub struct ImpactQuery {
+    pub start: String,
+    pub depth: Option<usize>,
+    pub trace_links: Option<bool>,
+    pub as_of: Option<String>,
+}
 
 /*
-[
-    {
-        'name': 'techl2_tag1',
-        'text': [
+{
+    'start': 'component_l2:激光雷达',
+    'depth': 2,
+    'dimensions': {
+        'arch': [
             {
-                'name': doca_name,
-                'address': doca_address,
-                'isRelated': true or false,//该文档是否和当前系统部件关联
+                'type': 'arch_l2',
+                'label': '目标检测',
+                'l1': '感知',
+                'address': null,
+                'distance': 2,
+                'path': ['component_l2:激光雷达', 'document:ARCH-感知', 'arch_l2:目标检测'],
             },
-            doc_b
         ],
+        'component': [...], 'mf': [...], 'project': [...],
     },
-    {
-        ...
-    },
-],
+    'aspice': [
+        { 'step': '需求', 'documents': [...] }, // same fields as the tags, l1 is null
+        ... // 架构, 详设, 单测, 集测, 路测, null for documents without step
+    ],
+}
 */
-async fn group_by_techl2(docs: &Vec<Document>) -> anyhow::Result<JsonValue> {
-    //assert!(check_documents_order(docs));
-    let db = get_db_pool().await?;
-    let records = sqlx::query!(
-        r#"
-        SELECT techl2.name, documenttech.docid FROM techl2
-        INNER JOIN documenttech ON techl2.id = documenttech.techid
-        GROUP BY techl2.name, documenttech.docid
-    "#
-    )
-    .fetch_all(&db)
-    .await?;
-
-    let mut grouped: HashMap<String, VecDeque<JsonValue>> = HashMap::new();
-
-    for record in records {
-        let techl2_name = record.name;
-        let docid = record.docid;
-        let doc = docs.iter().find(|doc| doc.id == docid);
-        if let Some(doc) = doc {
-            grouped
-                .entry(techl2_name)
-                .or_insert_with(VecDeque::new)
-                .push_front(doc.to_json_value(true));
-        } else {
-            let doc = sqlx::query_as!(
-                Document,
-                r#"
-                SELECT * FROM documents WHERE id = $1
-            "#,
-                docid
-            )
-            .fetch_one(&db)
-            .await?;
-            grouped
-                .entry(techl2_name)
-                .and_modify(|f| f.push_back(doc.to_json_value(false)));
+pub async fn impact_analysis(State(db): State<PgPool>, Query(query): Query<ImpactQuery>) -> Response {
+    let repo = match map_repository(db, query.as_of.as_deref()).await {
+        Ok(repo) => repo,
+        Err(response) => return response,
+    };
+    let map = graph::graph(&*repo).await.unwrap();
+    let aspice_steps = impact::read_aspice_steps(&*repo).await.unwrap();
+    let defaults = impact::ImpactOptions::default();
+    let options = impact::ImpactOptions {
+        depth: query.depth.unwrap_or(defaults.depth),
+        trace_links: query.trace_links.unwrap_or(defaults.trace_links),
+    };
+
+    match impact::impact(&map, &aspice_steps, &query.start, &options) {
+        Ok(report) => Json(to_value(report).unwrap()).into_response(),
+        Err(err) => {
+            (StatusCode::BAD_REQUEST, Json(json!({ "error": err.to_string() }))).into_response()
         }
     }
-
-    let mut result_vec = Vec::new();
-
-    for (name, text) in grouped.into_iter() {
-        let techl2_obj = json!({
-            "name": name,
-            "text": text,
-        });
-
-        result_vec.push(techl2_obj);
-    }
-
-    Ok(JsonValue::Array(result_vec))
 }
 
-async fn group_by_systeml2(docs: &Vec<Document>) -> anyhow::Result<JsonValue> {
-    let db = get_db_pool().await?;
-    let records = sqlx::query!(
-        r#"
-        SELECT systeml2.name, documentsystem.docid FROM systeml2
-        INNER JOIN documentsystem ON systeml2.id = documentsystem.sysid
-        GROUP BY systeml2.name, documentsystem.docid
-    "#
-    )
-    .fetch_all(&db)
-    .await?;
-
-    let mut grouped = HashMap::new();
+// query: ?format=json|csv&similarity=0.9&as_of=<snapshot>
+#[derive(Debug, Deserialize)]
+pub struct QualityQuery {
+    pub format: Option<String>,
+    pub similarity: Option<f64>,
+    pub as_of: Option<String>,
+}
 
-    for record in records {
-        let systeml2_name = record.name;
-        let docid = record.docid;
-        let doc = docs.iter().find(|doc| doc.id == docid);
-        if let Some(doc) = doc {
-            grouped
-                .entry(systeml2_name)
-                .or_insert_with(VecDeque::new)
-                .push_front(doc.to_json_value(true));
-        } else {
-            let doc = sqlx::query_as!(
-                Document,
-                r#"
-                SELECT * FROM documents WHERE id = $1
-            "#,
-                docid
-            )
-            .fetch_one(&db)
-            .await?;
-            grouped
-                .entry(systeml2_name)
-                .and_modify(|f| f.push_back(doc.to_json_value(false)));
-        }
+/*
+{
+    'summary': { 'empty_tags': 1, 'childless_tags': 0, ... }, // number of entries of each list
+    'empty_tags': [{ 'dimension': 'arch', 'l1': '感知', 'l2': '车道线' }],
+    'childless_tags': [{ 'dimension': 'project', 'name': '平台' }],
+    'untagged_documents': [{ 'id', 'name', 'address', 'dimensions': ['mf', 'project'] }],
+    'documents_without_aspice': [{ 'id', 'name', 'address' }],
+    'similar_names': [{ 'documents': [doc_a, doc_b], 'similarity': 0.923 }],
+    'duplicate_links': [{ 'link': 'https://...', 'documents': [doc_a, doc_b] }],
+    'non_requirement_links': [{ 'document': doc, 'requirement': doc, 'requirement_steps': ['架构'] }],
+}
+*/
+pub async fn data_quality_report(State(db): State<PgPool>, Query(query): Query<QualityQuery>) -> Response {
+    let options = QualityOptions {
+        similarity: query.similarity.unwrap_or(QualityOptions::default().similarity),
+    };
+    if !(0.0..=1.0).contains(&options.similarity) {
+        let error = json!({ "error": "similarity must be between 0 and 1" });
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
     }
-
-    let mut result_vec = Vec::new();
-    for (name, text) in grouped.into_iter() {
-        let systeml2_obj = json!({
-            "name": name,
-            "text": text,
-        });
-
-        result_vec.push(systeml2_obj);
+    let repo = match map_repository(db, query.as_of.as_deref()).await {
+        Ok(repo) => repo,
+        Err(response) => return response,
+    };
+    let report = data_quality(&*repo, &options).await.unwrap();
+
+    match query.format.as_deref() {
+        Some("csv") => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"data_quality.csv\"",
+                ),
+            ],
+            report.to_csv().unwrap(),
+        )
+            .into_response(),
+        _ => Json(to_value(report).unwrap()).into_response(),
     }
-
-    Ok(JsonValue::Array(result_vec))
 }
 
-async fn group_by_mfl2(docs: &Vec<Document>) -> anyhow::Result<JsonValue> {
-    let db = get_db_pool().await?;
-    let records = sqlx::query!(
+/*
+[
+    {
+        'id': requirement_id,
+        'name': '需求1',
+        'address': 'https://...',
+        'description': ...,
+    },
+    ...
+]
+*/
+// 需求文档：带有“需求”ASPICE 标签，或被其他文档关联为需求的文档
+pub async fn requirements(State(db): State<PgPool>, Query(query): Query<ListQuery>) -> Response {
+    let list = match query.options(&["id", "name", "address", "description"]) {
+        Ok(list) => list,
+        Err(err) => {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": err.to_string() }))).into_response()
+        }
+    };
+    let docs = sqlx::query_as!(
+        Document,
         r#"
-        SELECT mfl2.name, documentmf.docid FROM mfl2
-        INNER JOIN documentmf ON mfl2.id = documentmf.mfid
-        GROUP BY mfl2.name, documentmf.docid
+        SELECT documents.* FROM documents
+        WHERE documents.id IN (
+            SELECT docid FROM document_aspice_mapping WHERE aspice_step = '需求'
+        )
+        OR documents.id IN (SELECT requirementid FROM document_requirement)
+        ORDER BY documents.name
     "#
     )
     .fetch_all(&db)
-    .await?;
-
-    let mut grouped = HashMap::new();
+    .await
+    .unwrap();
 
-    for record in records {
-        let mfl2_name = record.name;
-        let docid = record.docid;
-        let doc = docs.iter().find(|doc| doc.id == docid);
-        if let Some(doc) = doc {
-            grouped
-                .entry(mfl2_name)
-                .or_insert_with(VecDeque::new)
-                .push_front(doc.to_json_value(true));
-        } else {
-            let doc = sqlx::query_as!(
-                Document,
-                r#"
-                SELECT * FROM documents WHERE id = $1
-            "#,
-                docid
-            )
-            .fetch_one(&db)
-            .await?;
-            grouped
-                .entry(mfl2_name)
-                .and_modify(|f| f.push_back(doc.to_json_value(false)));
-        }
-    }
+    let result_vec = docs
+        .iter()
+        .map(|doc| {
+            json!({
+                "id": doc.id.to_string(),
+                "name": doc.name,
+                "address": doc.link,
+                "description": doc.description,
+            })
+        })
+        .collect::<Vec<JsonValue>>();
 
-    let mut result_vec = Vec::new();
-    for (name, text) in grouped.into_iter() {
-        let mfl2_obj = json!({
-            "name": name,
-            "text": text,
-        });
+    listing(&db, result_vec, &list).await
+}
 
-        result_vec.push(mfl2_obj);
-    }
+// query: ?mf=<mfl2>&project=<L1 or L2 project tag>,<another>&tech=<techl2>
+#[derive(Debug, Deserialize)]
+pub struct TraceFilter {
+    pub mf: Option<String>,
+    pub project: Option<String>,
+    pub tech: Option<String>,
+}
 
-    Ok(JsonValue::Array(result_vec))
+/*
+{
+    'id': requirement_id,
+    'name': '需求1',
+    'address': 'https://...',
+    'content': [
+        {
+            'name': '软件需求分析',
+            'step': '需求',
+            'content': [
+                {
+                    'name': '文档名称1',
+                    'address': 'https://...',
+                },
+            ],
+        },
+        ... // 架构, 详设, 单测, 集测, 路测, 没有文档的阶段 content 为空
+    ],
 }
+*/
+pub async fn requirement_trace(
+    State(db): State<PgPool>,
+    Path(requirement_id): Path<Uuid>,
+    Query(filter): Query<TraceFilter>,
+) -> ApiResult<Json<JsonValue>> {
+    let requirement = sqlx::query_as!(
+        Document,
+        r#"
+        SELECT * FROM documents WHERE id = $1
+    "#,
+        requirement_id
+    )
+    .fetch_optional(&db)
+    .await?
+    .ok_or_else(|| ApiError::NotFound(format!("document {} does not exist", requirement_id)))?;
+    let scope = project_scope(&PgRepository::new(db.clone()), filter.project.as_deref()).await?;
 
-// return (mainline, projects)
-async fn group_by_projectl2(docs: &Vec<Document>) -> anyhow::Result<(JsonValue, JsonValue)> {
-    let db = get_db_pool().await?;
-    let records = sqlx::query!(
+    // a requirement as listed by /requirements
+    let is_requirement = sqlx::query_scalar!(
         r#"
-        SELECT projectl2.name, documentproject.docid FROM projectl2
-        INNER JOIN documentproject ON projectl2.id = documentproject.projectid
-        GROUP BY projectl2.name, documentproject.docid
-    "#
+        SELECT EXISTS (
+            SELECT 1 FROM document_aspice_mapping WHERE docid = $1 AND aspice_step = '需求'
+        ) OR EXISTS (
+            SELECT 1 FROM document_requirement WHERE requirementid = $1
+        ) AS "is_requirement!"
+    "#,
+        requirement_id
     )
-    .fetch_all(&db)
+    .fetch_one(&db)
     .await?;
-
-    let mut grouped = HashMap::new();
-
-    for record in records {
-        let projectl2_name = record.name;
-        let docid = record.docid;
-        let doc = docs.iter().find(|doc| doc.id == docid);
-        if let Some(doc) = doc {
-            grouped
-                .entry(projectl2_name)
-                .or_insert_with(VecDeque::new)
-                .push_front(doc.to_json_value(true));
-        } else {
-            let doc = sqlx::query_as!(
-                Document,
-                r#"
-                SELECT * FROM documents WHERE id = $1
-            "#,
-                docid
-            )
-            .fetch_one(&db)
-            .await?;
-            grouped
-                .entry(projectl2_name)
-                .and_modify(|f| f.push_back(doc.to_json_value(false)));
-        }
-    }
-
-    let mut project_vec = Vec::new();
-    let mut mainline_vec = Vec::new();
-    for (name, text) in grouped.into_iter() {
-        let projectl2_obj = json!({
-            "name": name,
-            "text": text,
-        });
-
-        if name == "主线" {
-            mainline_vec.push(projectl2_obj);
-        } else {
-            project_vec.push(projectl2_obj);
-        }
+    if !is_requirement {
+        return Err(ApiError::NotFound(format!("document {} is not a requirement", requirement_id)));
     }
 
-    Ok((
-        JsonValue::Array(mainline_vec),
-        JsonValue::Array(project_vec),
-    ))
-}
-
-// request:[
-//     {
-//         name: '需求1',
-//         content:[
-//             {
-//                 name: '软件需求分析',
-//                 content:[
-//                     {
-//                         name:'文档名称1',
-//                         address: 'https://momenta.feishu.cn/wiki/ORuBw5pT8iAUmPkkaEYcRsc1nLh'
-//                     }
-//                 ]
-//             },
-//             {
-//                 name: '软件架构设计',
-//                 content:[
-//                     {
-//                         name:'文档名称2',
-//                         address: 'https://momenta.feishu.cn/wiki/HC1uwEdemiusn3k7shGcwUZMnYe'
-//                     }
-//                 ]
-//             }
-//         ]
-//     },
-//     {
-//         name: '需求2',
-//     }
-// ],
-async fn get_aspice_graph(mf_name: &str) -> anyhow::Result<JsonValue> {
-    let db = get_db_pool().await?;
-
-    // find all docs having mf_name; then extract all aspice tags
+    // the requirement document itself is part of the trace
     let records = sqlx::query!(
         r#"
-        SELECT documents.associate_requirement, document_aspice_mapping.aspice_step as "aspice_step: Aspice", documents.name, documents.link
+        SELECT documents.id, documents.name, documents.link,
+            document_aspice_mapping.aspice_step as "aspice_step: Aspice"
         FROM documents
-        INNER JOIN documentmf ON documents.id = documentmf.docid
-        INNER JOIN mfl2 ON documentmf.mfid = mfl2.id
         INNER JOIN document_aspice_mapping ON documents.id = document_aspice_mapping.docid
-        WHERE mfl2.name = $1
+        WHERE (documents.id = $1 OR EXISTS (
+            SELECT 1 FROM document_requirement
+            WHERE document_requirement.docid = documents.id
+            AND document_requirement.requirementid = $1
+        ))
+        AND ($2::text IS NULL OR EXISTS (
+            SELECT 1 FROM documentmf
+            INNER JOIN mfl2 ON documentmf.mfid = mfl2.id
+            WHERE documentmf.docid = documents.id AND mfl2.name = $2
+        ))
+        AND ($3::text IS NULL OR EXISTS (
+            SELECT 1 FROM documenttech
+            INNER JOIN techl2 ON documenttech.techid = techl2.id
+            WHERE documenttech.docid = documents.id AND techl2.name = $3
+        ))
+        ORDER BY documents.name
     "#,
-        mf_name
+        requirement_id,
+        filter.mf,
+        filter.tech,
     )
     .fetch_all(&db)
     .await?;
 
-    let mut map = HashMap::new();
-    for record in records {
-        map.entry(record.associate_requirement)
-            .or_insert(HashMap::new())
+    let mut step_docs: HashMap<Aspice, Vec<JsonValue>> = HashMap::new();
+    for record in records.into_iter().filter(|record| scope.contains(&record.id)) {
+        step_docs
             .entry(record.aspice_step)
-            .or_insert(Vec::new())
+            .or_default()
             .push(json!({
                 "name": record.name,
                 "address": record.link,
             }));
     }
 
-    let mut result_vec = Vec::new();
-    for (requirement, aspice_map) in map.into_iter() {
-        let req_name = sqlx::query!(
-            r#"
-            SELECT name FROM documents WHERE id = $1"#,
-            requirement
+    let aspice_vec = Aspice::all()
+        .into_iter()
+        .map(|step| {
+            json!({
+                "name": step.to_string(),
+                "step": step.as_str(),
+                "content": step_docs.remove(&step).unwrap_or_default(),
+            })
+        })
+        .collect::<Vec<JsonValue>>();
+
+    Ok(Json(json!({
+        "id": requirement.id.to_string(),
+        "name": requirement.name,
+        "address": requirement.link,
+        "content": aspice_vec,
+    })))
+}
+
+// query: ?mf=<mfl2>&project=<projectl2>&by_project=true&format=json|csv|xlsx
+// mf and project select the requirements, not the documents linked to them
+#[derive(Debug, Deserialize)]
+pub struct CoverageQuery {
+    pub mf: Option<String>,
+    pub project: Option<String>,
+    pub by_project: Option<bool>,
+    pub format: Option<String>,
+}
+
+pub async fn aspice_coverage_report(
+    State(db): State<PgPool>,
+    Query(query): Query<CoverageQuery>,
+) -> Response {
+    let filter = CoverageFilter {
+        mf: query.mf,
+        project: query.project,
+        by_project: query.by_project.unwrap_or(false),
+    };
+    let report = aspice_coverage(&db, &filter).await.unwrap();
+
+    match query.format.as_deref() {
+        Some("csv") => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"aspice_coverage.csv\"",
+                ),
+            ],
+            report.to_csv().unwrap(),
         )
-        .fetch_one(&db)
-        .await?
-        .name;
+            .into_response(),
+        Some("xlsx") => (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                ),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"aspice_coverage.xlsx\"",
+                ),
+            ],
+            report.to_xlsx().unwrap(),
+        )
+            .into_response(),
+        _ => Json(to_value(report).unwrap()).into_response(),
+    }
+}
 
-        let mut aspice_vec = Vec::new();
-        for (aspice_step, docs) in aspice_map.into_iter() {
-            let aspice_obj = json!({
-                "name": aspice_step.to_string(),
-                "content": docs,
-            });
-            aspice_vec.push(aspice_obj);
-        }
-        let requirement_obj = json!({
-            "name": req_name,
-            "content": aspice_vec,
-        });
-        result_vec.push(requirement_obj);
+/*
+{
+    'id': doc_id,
+    'name': '单测文档',
+    'address': 'https://...',
+    'steps': ['单测'],
+    'links': [
+        {
+            'type': 'verifies',
+            'document': { 'id': ..., 'name': '详设文档', 'steps': ['详设'], 'links': [...] },
+        },
+        {
+            'type': 'verifies',
+            // already listed above, or on the current path ('circular': true)
+            'document': { 'id': ..., 'name': '详设文档2', 'steps': ['详设'], 'repeated': true },
+        },
+    ],
+}
+*/
+pub async fn trace_upstream(State(db): State<PgPool>, Path(doc_id): Path<Uuid>) -> Json<JsonValue> {
+    let graph = TraceGraph::load(&PgRepository::new(db)).await.unwrap();
+    if !graph.contains(&doc_id) {
+        return Json(json!("No documents"));
     }
-    Ok(JsonValue::Array(result_vec))
+    Json(graph.walk(&doc_id, true))
 }
 
-lazy_static::lazy_static! {
-    static ref TECHL1COMP: TechL1Compare = TechL1Compare::new();
+pub async fn trace_downstream(
+    State(db): State<PgPool>,
+    Path(doc_id): Path<Uuid>,
+) -> Json<JsonValue> {
+    let graph = TraceGraph::load(&PgRepository::new(db)).await.unwrap();
+    if !graph.contains(&doc_id) {
+        return Json(json!("No documents"));
+    }
+    Json(graph.walk(&doc_id, false))
 }
 
-struct TechL1Compare {
-    predefined_order: HashMap<&'static str, usize>,
+// response: see `TraceGraph::check`
+pub async fn check_trace_links(State(db): State<PgPool>) -> Json<JsonValue> {
+    let graph = TraceGraph::load(&PgRepository::new(db)).await.unwrap();
+    Json(graph.check())
 }
 
-impl TechL1Compare {
-    fn new() -> Self {
-        let mut predefined_order = HashMap::new();
-        predefined_order.insert("传感器方案", 1);
-        predefined_order.insert("域控硬件方案", 2);
-        predefined_order.insert("OS+中间件方案", 3);
-        predefined_order.insert("执行器交互方案", 4);
-        predefined_order.insert("EE架构方案", 5);
-        predefined_order.insert("信号接入方案", 6);
-        predefined_order.insert("通讯方案", 7);
-        predefined_order.insert("应用层基础服务方案", 8);
-        predefined_order.insert("系统方案", 9);
-        predefined_order.insert("软件优化方案", 10);
-        TechL1Compare {
-            predefined_order,
+// body: { 'tag_name': sort_order, ... }, later imports keep these orders
+// nothing is changed when one of the tags does not exist
+pub async fn update_tag_order(
+    State(db): State<PgPool>,
+    Path((dimension, level)): Path<(String, String)>,
+    Extension(principal): Extension<Principal>,
+    Json(orders): Json<HashMap<String, i32>>,
+) -> ApiResult<Json<JsonValue>> {
+    let dimension = parse_dimension(&dimension)?;
+    let level = parse_level(&level)?;
+    let mut tx = begin(&db, &principal).await?;
+    let mut updated = 0;
+    let mut unknown = Vec::new();
+    for (name, sort_order) in orders.into_iter() {
+        let rows = update_tag_sort_order_by_name(&mut tx, dimension, level, &name, sort_order).await?;
+        if rows == 0 {
+            unknown.push(name);
         }
+        updated += rows;
     }
-
-    fn compare(&self, s1: &str, s2: &str) -> std::cmp::Ordering {
-        let order1 = self.predefined_order.get(s1).unwrap_or(&usize::MAX);
-        let order2 = self.predefined_order.get(s2).unwrap_or(&usize::MAX);
-        order1.cmp(order2)
+    if !unknown.is_empty() {
+        unknown.sort();
+        return Err(ApiError::NotFound(format!("unknown tags: {}", unknown.join(", "))));
     }
-}
\ No newline at end of file
+    tx.commit().await?;
+
+    Ok(Json(json!({ "updated": updated })))
+}
+
+/*
+[
+    {
+        'id': 1,
+        'changed_at': '2026-10-19T08:00:00Z',
+        'table_name': 'documenttech',
+        'operation': 'insert', // update, delete
+        'row_key': 'docid/techid',
+        'document_id': doc_id,
+        'old_row': null,
+        'new_row': { 'docid': doc_id, 'techid': tag_id },
+        'sync_run_id': sync_run_id, // null for api changes
+        'changed_by': 'data_sync',
+    },
+]
+*/
+pub async fn document_history(State(db): State<PgPool>, Path(doc_id): Path<Uuid>) -> Json<JsonValue> {
+    let entries = read_document_history(&db, doc_id).await.unwrap();
+    Json(to_value(entries).unwrap())
+}
+
+// query: ?from=<rfc3339>&to=<rfc3339>&sync_run=<id>, all optional
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub sync_run: Option<Uuid>,
+}
+
+/*
+{
+    'from': ..., 'to': ..., 'sync_run': ...,
+    'changes': [
+        {
+            'table_name': 'documents',
+            'row_key': doc_id,
+            'document_id': doc_id,
+            'change': 'changed', // added, removed
+            'old_row': {...}, // at `from`
+            'new_row': {...}, // at `to`
+            'changed_by': ['alice'],
+            'sync_runs': [],
+        },
+    ],
+}
+*/
+pub async fn history_diff(State(db): State<PgPool>, Query(query): Query<HistoryQuery>) -> Json<JsonValue> {
+    let entries = read_audit_log(&db, query.from, query.to, query.sync_run)
+        .await
+        .unwrap();
+    Json(json!({
+        "from": query.from,
+        "to": query.to,
+        "sync_run": query.sync_run,
+        "changes": history::diff(&entries),
+    }))
+}
+
+/*
+[
+    {
+        'id': snapshot_id,
+        'name': 'M1 评审',
+        'taken_at': '2026-10-19T08:00:00Z',
+        'sync_run_id': null, // the sync run for snapshots taken after a sync
+        'taken_by': 'alice',
+    },
+]
+*/
+pub async fn snapshots(State(db): State<PgPool>) -> Json<JsonValue> {
+    let snapshots = read_snapshots(&db).await.unwrap();
+    Json(to_value(snapshots).unwrap())
+}
+
+/*
+[
+    {
+        'id': document_id,
+        'name': '文档1',
+        'address': 'https://...',
+        'status_code': 404, // null if there was no answer
+        'final_url': 'https://...', // after redirects
+        'error': null, // e.g. timeout, invalid link
+        'checked_at': '2026-10-19T08:00:00Z',
+    },
+]
+*/
+// documents whose current link failed the latest `link_check` run
+pub async fn broken_links(State(db): State<PgPool>, Query(query): Query<ListQuery>) -> Response {
+    let allowed = ["id", "name", "address", "status_code", "final_url", "error", "checked_at"];
+    let list = match query.options(&allowed) {
+        Ok(list) => list,
+        Err(err) => {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": err.to_string() }))).into_response()
+        }
+    };
+    let broken = read_broken_links(&db).await.unwrap();
+    let result: Vec<JsonValue> = broken
+        .into_iter()
+        .map(|(doc, check)| {
+            json!({
+                "id": doc.id,
+                "name": doc.name,
+                "address": doc.link,
+                "status_code": check.status_code,
+                "final_url": check.final_url,
+                "error": check.error,
+                "checked_at": check.checked_at,
+            })
+        })
+        .collect();
+    listing(&db, result, &list).await
+}
+
+// POST body: { "query": "...", "variables": {...}, "operationName": "..." },
+// ?as_of=<snapshot> answers from a snapshot as on the trees
+pub async fn graphql_query(
+    State(db): State<PgPool>,
+    Query(query): Query<AsOfQuery>,
+    Json(request): Json<async_graphql::Request>,
+) -> Response {
+    let repo = match map_repository(db, query.as_of.as_deref()).await {
+        Ok(repo) => repo,
+        Err(response) => return response,
+    };
+    Json(graphql::execute(Arc::from(repo), request).await).into_response()
+}
@@ -0,0 +1,62 @@
+use arch_map::database::dml_interface::{self, DbPoolOptions};
+use arch_map::report::coverage::{aspice_coverage, CoverageFilter};
+use clap::{Parser, ValueEnum};
+use std::error::Error;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, ValueEnum)]
+enum Format {
+    Json,
+    Csv,
+    Xlsx,
+}
+
+/// ASPICE coverage gap report of the requirements per MF module
+#[derive(Debug, Parser)]
+struct Args {
+    /// Only report requirements tagged with this MF L2 tag
+    #[arg(long)]
+    mf: Option<String>,
+    /// Only report requirements tagged with this project L2 tag,
+    /// their linked documents count whatever their project
+    #[arg(long)]
+    project: Option<String>,
+    /// Split every MF module by the project of its requirements
+    #[arg(long)]
+    by_project: bool,
+    #[arg(long, value_enum, default_value = "json")]
+    format: Format,
+    /// Output file, print to stdout if not given
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let pool = dml_interface::connect(&DbPoolOptions::from_env()?).await?;
+    let filter = CoverageFilter {
+        mf: args.mf,
+        project: args.project,
+        by_project: args.by_project,
+    };
+    let report = aspice_coverage(&pool, &filter).await?;
+
+    let bytes = match args.format {
+        Format::Json => serde_json::to_vec_pretty(&report)?,
+        Format::Csv => report.to_csv()?,
+        Format::Xlsx => report.to_xlsx()?,
+    };
+
+    match args.output {
+        Some(path) => std::fs::write(path, bytes)?,
+        None => {
+            if let Format::Xlsx = args.format {
+                return Err("xlsx output needs --output".into());
+            }
+            println!("{}", String::from_utf8(bytes)?);
+        }
+    }
+
+    Ok(())
+}
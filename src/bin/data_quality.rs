@@ -0,0 +1,61 @@
+use arch_map::database::dml_interface::{self, DbPoolOptions};
+use arch_map::database::repository::{MemoryRepository, PgRepository};
+use arch_map::report::quality::{data_quality, QualityOptions};
+use clap::{Parser, ValueEnum};
+use std::error::Error;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, ValueEnum)]
+enum Format {
+    Json,
+    Csv,
+}
+
+/// Data quality report: empty tags, untagged documents, duplicates and wrong requirement links
+#[derive(Debug, Parser)]
+struct Args {
+    /// Report document names at least this similar (0..1) as near-duplicates
+    #[arg(long, default_value_t = 0.9)]
+    similarity: f64,
+    /// Check a snapshot (name or rfc3339 time) instead of the current map
+    #[arg(long)]
+    as_of: Option<String>,
+    #[arg(long, value_enum, default_value = "json")]
+    format: Format,
+    /// Output file, print to stdout if not given
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    dotenvy::dotenv().ok();
+    let args = Args::parse();
+    if !(0.0..=1.0).contains(&args.similarity) {
+        return Err("--similarity must be between 0 and 1".into());
+    }
+    let options = QualityOptions {
+        similarity: args.similarity,
+    };
+    let pool = dml_interface::connect(&DbPoolOptions::from_env()?).await?;
+    let report = match &args.as_of {
+        Some(as_of) => {
+            let (_, data) = dml_interface::read_snapshot_as_of(&pool, as_of)
+                .await?
+                .ok_or_else(|| format!("no snapshot as of '{}'", as_of))?;
+            data_quality(&MemoryRepository::from_snapshot(data).await?, &options).await?
+        }
+        None => data_quality(&PgRepository::new(pool), &options).await?,
+    };
+
+    let bytes = match args.format {
+        Format::Json => serde_json::to_vec_pretty(&report)?,
+        Format::Csv => report.to_csv()?,
+    };
+    match args.output {
+        Some(path) => std::fs::write(path, bytes)?,
+        None => println!("{}", String::from_utf8(bytes)?),
+    }
+
+    Ok(())
+}
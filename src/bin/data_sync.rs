@@ -1,28 +1,105 @@
 use std::error::Error;
+use arch_map::database::dml_interface::{self, DbPoolOptions};
+use arch_map::database::migration::{self, MigrateCommand};
+use arch_map::database::models::SyncRun;
+use arch_map::database::repository::PgRepository;
 use arch_map::etl::extract;
 use arch_map::etl::transform_load::InternalData;
+use arch_map::logging;
+use clap::Parser;
 use pyo3::prelude::*;
 use pyo3::types::IntoPyDict;
 use std::path::Path;
 use std::process::Command;
+use tracing::Instrument;
 
-async fn data_sync_task() -> Result<(), Box<dyn Error>> {
+async fn data_sync_task(pool: &sqlx::PgPool) -> Result<(), Box<dyn Error>> {
     let path = extract::get_feishu_data().await?;
     // call python script to handle hyper link
     Command::new("python3").args(&["src/bin/handle_hyperlink.py", path.as_path().to_str().unwrap()]).output()?;
     let mut data = InternalData::new();
-    data.import_and_load(&path).await?;
+    data.import_and_load(&PgRepository::new(pool.clone()), &path).await?;
 
     Ok(())
 }
 
+/// Sync the architecture map from feishu into the database
+#[derive(Debug, Parser)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<SyncCommand>,
+    /// Apply pending migrations before syncing
+    #[arg(long, env = "ARCH_MAP_MIGRATE")]
+    migrate: bool,
+    /// Snapshots taken after a successful sync to keep, older ones are deleted; 0 takes none
+    #[arg(long, env = "ARCH_MAP_SYNC_SNAPSHOTS", default_value_t = 30)]
+    sync_snapshots: u32,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum SyncCommand {
+    /// Inspect, apply or revert the database migrations
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    dotenvy::dotenv().ok();
+    let args = Args::parse();
+    logging::init_from_env()?;
+
+    // every change of this run is attributed to it in the audit log
+    let run_id = uuid::Uuid::new_v4();
+    let pool = dml_interface::connect(&DbPoolOptions {
+        changed_by: Some("data_sync".to_string()),
+        sync_run: Some(run_id),
+        ..DbPoolOptions::from_env()?
+    })
+    .await?;
+    if let Some(SyncCommand::Migrate { command }) = &args.command {
+        migration::run_command(&pool, command).await?;
+        return Ok(());
+    }
+    migration::prepare(&pool, args.migrate).await?;
+
     // call data_sync_task every 5 minutes
     // let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(5 * 60));
     // loop {
         // interval.tick().await;
-    data_sync_task().await?;
+    let started_at = chrono::Utc::now();
+    let result = data_sync_task(&pool)
+        .instrument(tracing::info_span!("sync"))
+        .await;
+    match &result {
+        Ok(()) => tracing::info!("data sync finished"),
+        Err(err) => tracing::error!("data sync failed: {}", err),
+    }
+    // record every run so the server can report the age of its data
+    let run = SyncRun::new(
+        run_id,
+        started_at,
+        chrono::Utc::now(),
+        result.as_ref().err().map(|err| err.to_string()),
+    );
+    dml_interface::insert_sync_run(&pool, &run).await?;
+    result?;
+    // keep the map of the latest successful syncs for the `as_of` queries
+    if args.sync_snapshots > 0 {
+        let name = format!("sync-{}", started_at.format("%Y%m%dT%H%M%SZ"));
+        if dml_interface::insert_snapshot(&pool, &name, Some(run_id), Some("data_sync"))
+            .await?
+            .is_none()
+        {
+            tracing::warn!("snapshot '{}' already exists", name);
+        }
+        let pruned = dml_interface::prune_sync_snapshots(&pool, args.sync_snapshots.into()).await?;
+        if pruned > 0 {
+            tracing::info!("deleted {} old sync snapshots", pruned);
+        }
+    }
     // }
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,65 @@
+use arch_map::database::dml_interface::{self, DbPoolOptions};
+use arch_map::database::repository::{MemoryRepository, PgRepository};
+use arch_map::report::graph;
+use clap::{Parser, ValueEnum};
+use std::error::Error;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, ValueEnum)]
+enum Format {
+    Json,
+    Dot,
+    Graphml,
+    Mermaid,
+}
+
+/// Export the graph of documents, tags and requirements for design reviews
+#[derive(Debug, Parser)]
+struct Args {
+    #[arg(long, value_enum, default_value = "dot")]
+    format: Format,
+    /// Only the part around this node, as <type>:<name>, e.g. component_l2:激光雷达
+    #[arg(long)]
+    around: Option<String>,
+    /// How many edges away from --around a node may be
+    #[arg(long, default_value_t = 2)]
+    hops: usize,
+    /// Export a snapshot (name or rfc3339 time) instead of the current map
+    #[arg(long)]
+    as_of: Option<String>,
+    /// Output file, print to stdout if not given
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    dotenvy::dotenv().ok();
+    let args = Args::parse();
+    let pool = dml_interface::connect(&DbPoolOptions::from_env()?).await?;
+    let mut map = match &args.as_of {
+        Some(as_of) => {
+            let (_, data) = dml_interface::read_snapshot_as_of(&pool, as_of)
+                .await?
+                .ok_or_else(|| format!("no snapshot as of '{}'", as_of))?;
+            graph::graph(&MemoryRepository::from_snapshot(data).await?).await?
+        }
+        None => graph::graph(&PgRepository::new(pool)).await?,
+    };
+    if let Some(around) = &args.around {
+        map = map.around(around, args.hops)?;
+    }
+
+    let text = match args.format {
+        Format::Json => serde_json::to_string_pretty(&map)?,
+        Format::Dot => map.to_dot(),
+        Format::Graphml => map.to_graphml(),
+        Format::Mermaid => map.to_mermaid(),
+    };
+    match args.output {
+        Some(path) => std::fs::write(path, text)?,
+        None => print!("{}", text),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use arch_map::database::dml_interface::{self, DbPoolOptions};
+use arch_map::database::migration;
+use arch_map::database::repository::PgRepository;
+use arch_map::link_check::{self, LinkCheckOptions};
+use arch_map::logging;
+use clap::Parser;
+use std::error::Error;
+use std::time::Duration;
+
+/// Check the links of all documents and record broken ones
+#[derive(Debug, Parser)]
+struct Args {
+    /// Requests in flight at once
+    #[arg(long, default_value_t = 8)]
+    concurrency: usize,
+    /// Least time between two requests to the same host
+    #[arg(long, default_value_t = 500)]
+    host_interval_ms: u64,
+    /// Timeout of one request, redirects included
+    #[arg(long, default_value_t = 10)]
+    timeout_secs: u64,
+    /// Apply pending migrations before checking
+    #[arg(long, env = "ARCH_MAP_MIGRATE")]
+    migrate: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    dotenvy::dotenv().ok();
+    let args = Args::parse();
+    logging::init_from_env()?;
+    let pool = dml_interface::connect(&DbPoolOptions::from_env()?).await?;
+    migration::prepare(&pool, args.migrate).await?;
+
+    let options = LinkCheckOptions {
+        concurrency: args.concurrency,
+        host_interval: Duration::from_millis(args.host_interval_ms),
+        timeout: Duration::from_secs(args.timeout_secs),
+    };
+    let summary = link_check::check_links(&PgRepository::new(pool), &options).await?;
+    tracing::info!("checked {} links, {} broken", summary.checked, summary.broken);
+
+    Ok(())
+}
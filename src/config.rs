@@ -0,0 +1,339 @@
+//! Configuration of the arch_map web server.
+//! Values are layered: defaults < config file < environment variables < command line flags.
+
+use anyhow::{anyhow, Context};
+use clap::Parser;
+use crate::database::migration::MigrateCommand;
+use crate::logging::{self, LogFormat};
+use clap::Subcommand;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+// used when no --config is given and the file exists in the working directory
+pub const DEFAULT_CONFIG_FILE: &str = "arch_map.toml";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen: SocketAddr,
+    pub tls: TlsConfig,
+    pub cors: CorsConfig,
+    pub database: DatabaseConfig,
+    pub health: HealthConfig,
+    // a level (error | warn | info | debug | trace | off) or directives like "info,sqlx=warn"
+    pub log_level: String,
+    pub log_format: LogFormat,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    // false serves plain http on `listen`, e.g. behind a reverse proxy
+    pub enabled: bool,
+    // relative paths of a config file start at its directory, the others at the working directory
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    // seconds between checks of the cert/key modification time, 0 to only reload on SIGHUP
+    pub reload_interval: u64,
+    // plain http listener redirecting to https
+    pub redirect_listen: Option<SocketAddr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    // "*" allows any origin
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: Option<String>,
+    pub max_connections: u32,
+    // how long a request waits for a free connection (milliseconds)
+    pub acquire_timeout_ms: u64,
+    // postgres cancels statements running longer (milliseconds), 0 for no limit
+    pub statement_timeout_ms: u64,
+    // statements slower than this (milliseconds) are logged as warnings
+    pub slow_query_ms: u64,
+    // apply pending migrations on startup instead of refusing to start
+    pub migrate: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    // /readyz fails when the last successful sync is older (seconds), 0 to skip the check
+    pub max_sync_age: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            tls: TlsConfig::default(),
+            cors: CorsConfig::default(),
+            database: DatabaseConfig::default(),
+            health: HealthConfig::default(),
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            enabled: true,
+            cert: PathBuf::from("self_signed_certs/cert.pem"),
+            key: PathBuf::from("self_signed_certs/key.pem"),
+            reload_interval: 30,
+            redirect_listen: None,
+        }
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: vec!["*".to_string()],
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: None,
+            max_connections: 20,
+            acquire_timeout_ms: 30000,
+            statement_timeout_ms: 0,
+            slow_query_ms: 500,
+            migrate: false,
+        }
+    }
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            max_sync_age: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Architecture map web server
+#[derive(Debug, Default, Parser)]
+#[command(name = "arch_map")]
+pub struct ServerArgs {
+    #[command(subcommand)]
+    pub command: Option<ServerCommand>,
+    /// Config file (toml), defaults to ./arch_map.toml if it exists
+    #[arg(short, long, env = "ARCH_MAP_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Address to listen on, e.g. 0.0.0.0:3000
+    #[arg(long, env = "ARCH_MAP_LISTEN")]
+    pub listen: Option<SocketAddr>,
+    /// Serve https (true) or plain http (false)
+    #[arg(long, env = "ARCH_MAP_TLS")]
+    pub tls: Option<bool>,
+    /// TLS certificate chain (PEM)
+    #[arg(long, env = "ARCH_MAP_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+    /// TLS private key (PEM)
+    #[arg(long, env = "ARCH_MAP_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+    /// Seconds between checks for a changed certificate, 0 to only reload on SIGHUP
+    #[arg(long, env = "ARCH_MAP_TLS_RELOAD_INTERVAL")]
+    pub tls_reload_interval: Option<u64>,
+    /// Plain http address redirecting to https, e.g. 0.0.0.0:80
+    #[arg(long, env = "ARCH_MAP_HTTP_REDIRECT_LISTEN")]
+    pub http_redirect_listen: Option<SocketAddr>,
+    /// Allowed CORS origins, comma separated, "*" for any
+    #[arg(long, env = "ARCH_MAP_CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Option<Vec<String>>,
+    /// Postgres connection url
+    #[arg(long, env = "DATABASE_URL", hide_env_values = true)]
+    pub database_url: Option<String>,
+    /// Maximum number of connections in the database pool
+    #[arg(long, env = "ARCH_MAP_DB_MAX_CONNECTIONS")]
+    pub db_max_connections: Option<u32>,
+    /// Milliseconds to wait for a free database connection
+    #[arg(long, env = "ARCH_MAP_DB_ACQUIRE_TIMEOUT_MS")]
+    pub db_acquire_timeout_ms: Option<u64>,
+    /// Cancel statements running longer than this many milliseconds, 0 for no limit
+    #[arg(long, env = "ARCH_MAP_DB_STATEMENT_TIMEOUT_MS")]
+    pub db_statement_timeout_ms: Option<u64>,
+    /// Log statements slower than this many milliseconds
+    #[arg(long, env = "ARCH_MAP_DB_SLOW_QUERY_MS")]
+    pub db_slow_query_ms: Option<u64>,
+    /// Apply pending migrations on startup
+    #[arg(long, env = "ARCH_MAP_MIGRATE")]
+    pub migrate: bool,
+    /// Seconds after the last successful sync before /readyz fails, 0 to skip the check
+    #[arg(long, env = "ARCH_MAP_MAX_SYNC_AGE")]
+    pub max_sync_age: Option<u64>,
+    /// Log level or filter directives, e.g. "info,sqlx=warn"
+    #[arg(long, env = "ARCH_MAP_LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// Plain text or one json object per line
+    #[arg(long, value_enum, env = "ARCH_MAP_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ServerCommand {
+    /// Inspect, apply or revert the database migrations
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+}
+
+impl ServerConfig {
+    // relative tls paths, also the defaults the file leaves out, are resolved against its directory
+    pub fn from_file(path: &Path) -> anyhow::Result<ServerConfig> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        let mut config: ServerConfig =
+            toml::from_str(&text).with_context(|| format!("invalid config file {}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        config.tls.cert = dir.join(&config.tls.cert);
+        config.tls.key = dir.join(&config.tls.key);
+        Ok(config)
+    }
+
+    // config file (if any) overridden by env/flags
+    pub fn resolve(args: &ServerArgs) -> anyhow::Result<ServerConfig> {
+        let mut config = match &args.config {
+            Some(path) => ServerConfig::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+                ServerConfig::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => ServerConfig::default(),
+        };
+        config.apply_args(args);
+        Ok(config)
+    }
+
+    pub fn load(args: &ServerArgs) -> anyhow::Result<ServerConfig> {
+        let config = ServerConfig::resolve(args)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn apply_args(&mut self, args: &ServerArgs) {
+        if let Some(listen) = args.listen {
+            self.listen = listen;
+        }
+        if let Some(tls) = args.tls {
+            self.tls.enabled = tls;
+        }
+        if let Some(cert) = &args.tls_cert {
+            self.tls.cert = cert.clone();
+        }
+        if let Some(key) = &args.tls_key {
+            self.tls.key = key.clone();
+        }
+        if let Some(reload_interval) = args.tls_reload_interval {
+            self.tls.reload_interval = reload_interval;
+        }
+        if let Some(redirect_listen) = args.http_redirect_listen {
+            self.tls.redirect_listen = Some(redirect_listen);
+        }
+        if let Some(origins) = &args.cors_origins {
+            self.cors.allowed_origins = origins
+                .iter()
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect();
+        }
+        if let Some(url) = &args.database_url {
+            self.database.url = Some(url.clone());
+        }
+        if let Some(max_connections) = args.db_max_connections {
+            self.database.max_connections = max_connections;
+        }
+        if let Some(acquire_timeout_ms) = args.db_acquire_timeout_ms {
+            self.database.acquire_timeout_ms = acquire_timeout_ms;
+        }
+        if let Some(statement_timeout_ms) = args.db_statement_timeout_ms {
+            self.database.statement_timeout_ms = statement_timeout_ms;
+        }
+        if args.migrate {
+            self.database.migrate = true;
+        }
+        if let Some(max_sync_age) = args.max_sync_age {
+            self.health.max_sync_age = max_sync_age;
+        }
+        if let Some(slow_query_ms) = args.db_slow_query_ms {
+            self.database.slow_query_ms = slow_query_ms;
+        }
+        if let Some(log_level) = &args.log_level {
+            self.log_level = log_level.clone();
+        }
+        if let Some(log_format) = args.log_format {
+            self.log_format = log_format;
+        }
+    }
+
+    // report every problem at once instead of failing on the first one
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut problems = Vec::new();
+
+        if self.tls.enabled {
+            for (name, path) in [("tls.cert", &self.tls.cert), ("tls.key", &self.tls.key)] {
+                if !path.is_file() {
+                    problems.push(format!("{}: file {} not found", name, path.display()));
+                }
+            }
+            if self.tls.redirect_listen.is_some_and(|redirect| redirect.port() == self.listen.port()) {
+                problems.push("tls.redirect_listen: must use another port than listen".to_string());
+            }
+        } else if self.tls.redirect_listen.is_some() {
+            problems.push("tls.redirect_listen: needs tls.enabled = true".to_string());
+        }
+
+        let origins = &self.cors.allowed_origins;
+        if origins.iter().any(|origin| origin == "*") && origins.len() > 1 {
+            problems.push("cors.allowed_origins: \"*\" can not be combined with other origins".to_string());
+        }
+        for origin in origins.iter().filter(|origin| *origin != "*") {
+            let valid = (origin.starts_with("http://") || origin.starts_with("https://"))
+                && !origin.ends_with('/')
+                && http::HeaderValue::from_str(origin).is_ok();
+            if !valid {
+                problems.push(format!(
+                    "cors.allowed_origins: '{}' is not an origin like https://example.com",
+                    origin
+                ));
+            }
+        }
+
+        match &self.database.url {
+            None => problems.push("database.url: not set (config file, DATABASE_URL or --database-url)".to_string()),
+            Some(url) if !(url.starts_with("postgres://") || url.starts_with("postgresql://")) => {
+                problems.push("database.url: must start with postgres:// or postgresql://".to_string())
+            }
+            Some(_) => {}
+        }
+        if self.database.max_connections == 0 {
+            problems.push("database.max_connections: must be at least 1".to_string());
+        }
+        if self.database.acquire_timeout_ms == 0 {
+            problems.push("database.acquire_timeout_ms: must be at least 1".to_string());
+        }
+
+        if let Err(err) = logging::parse_filter(&self.log_level) {
+            problems.push(format!("log_level: {:#}", err));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("invalid configuration:\n  - {}", problems.join("\n  - ")))
+        }
+    }
+}
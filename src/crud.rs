@@ -0,0 +1,711 @@
+// Write API for small corrections, the bulk of the data still comes from the feishu sync.
+use arch_map::auth::Principal;
+use arch_map::database::dml_interface::*;
+use arch_map::database::models::*;
+use axum::extract::{Extension, Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json, Response};
+use serde::Deserialize;
+use serde_json::json;
+use serde_json::Value as JsonValue;
+use sqlx::{PgConnection, PgPool, Postgres, Row, Transaction};
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    Conflict(String),
+    Internal(anyhow::Error),
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            ApiError::Conflict(message) => (StatusCode::CONFLICT, message),
+            ApiError::Internal(err) => {
+                // the details name tables and constraints, they stay in the log
+                tracing::error!("{:?}", err);
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal error".to_string())
+            }
+        };
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        ApiError::Internal(err)
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        ApiError::Internal(err.into())
+    }
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+pub fn parse_dimension(dimension: &str) -> ApiResult<Dimension> {
+    Dimension::from_name(dimension)
+        .ok_or_else(|| ApiError::BadRequest(format!("unknown dimension '{}'", dimension)))
+}
+
+pub fn parse_level(level: &str) -> ApiResult<TagLevel> {
+    TagLevel::from_name(level)
+        .ok_or_else(|| ApiError::BadRequest(format!("unknown tag level '{}'", level)))
+}
+
+// the writes of a request share one transaction, attributed to its user in the audit log
+pub async fn begin(db: &PgPool, principal: &Principal) -> ApiResult<Transaction<'static, Postgres>> {
+    Ok(begin_change(db, &principal.subject).await?)
+}
+
+async fn find_document(conn: &mut PgConnection, id: Uuid) -> ApiResult<Document> {
+    sqlx::query_as!(Document, r#"SELECT * FROM documents WHERE id = $1"#, id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("document {} does not exist", id)))
+}
+
+async fn ensure_document_name_free(
+    conn: &mut PgConnection,
+    name: &str,
+    except: Option<Uuid>,
+) -> ApiResult<()> {
+    let exist = sqlx::query!(
+        r#"SELECT id FROM documents WHERE name = $1 AND id <> $2"#,
+        name,
+        except.unwrap_or_else(Uuid::nil),
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    match exist {
+        Some(_) => Err(ApiError::Conflict(format!("document '{}' already exists", name))),
+        None => Ok(()),
+    }
+}
+
+// --------------------------------documents--------------------------------
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDocument {
+    pub name: String,
+    pub link: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDocument {
+    pub name: Option<String>,
+    pub link: Option<String>,
+    // an empty string clears the description
+    pub description: Option<String>,
+}
+
+pub async fn create_document(
+    State(db): State<PgPool>,
+    Extension(principal): Extension<Principal>,
+    Json(body): Json<CreateDocument>,
+) -> ApiResult<(StatusCode, Json<JsonValue>)> {
+    let name = body.name.trim();
+    if name.is_empty() || body.link.trim().is_empty() {
+        return Err(ApiError::BadRequest("name and link are required".to_string()));
+    }
+
+    let mut tx = begin(&db, &principal).await?;
+    ensure_document_name_free(&mut tx, name, None).await?;
+
+    let doc = Document::new(name.to_string(), body.link.trim().to_string(), body.description);
+    sqlx::query!(
+        r#"INSERT INTO documents (id, name, link, description) VALUES ($1, $2, $3, $4)"#,
+        doc.id,
+        doc.name,
+        doc.link,
+        doc.description,
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(doc.to_json_value(true, None))))
+}
+
+pub async fn update_document(
+    State(db): State<PgPool>,
+    Path(id): Path<Uuid>,
+    Extension(principal): Extension<Principal>,
+    Json(body): Json<UpdateDocument>,
+) -> ApiResult<Json<JsonValue>> {
+    let mut tx = begin(&db, &principal).await?;
+    let mut doc = find_document(&mut tx, id).await?;
+
+    if let Some(name) = body.name {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err(ApiError::BadRequest("name must not be empty".to_string()));
+        }
+        ensure_document_name_free(&mut tx, &name, Some(id)).await?;
+        doc.name = name;
+    }
+    if let Some(link) = body.link {
+        doc.link = link.trim().to_string();
+    }
+    if let Some(description) = body.description {
+        doc.description = Some(description).filter(|s| !s.is_empty());
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE documents SET name = $2, link = $3, description = $4
+        WHERE id = $1
+    "#,
+        doc.id,
+        doc.name,
+        doc.link,
+        doc.description,
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    // a changed link has not been checked yet, to_json_value leaves its status out
+    let link_check = read_link_check(&db, id).await?;
+    Ok(Json(doc.to_json_value(true, link_check.as_ref())))
+}
+
+// delete the document together with every link pointing to it
+pub async fn delete_document(
+    State(db): State<PgPool>,
+    Path(id): Path<Uuid>,
+    Extension(principal): Extension<Principal>,
+) -> ApiResult<StatusCode> {
+    let mut tx = begin(&db, &principal).await?;
+    find_document(&mut tx, id).await?;
+
+    for dimension in Dimension::all() {
+        let sql = format!("DELETE FROM {} WHERE docid = $1", dimension.link_table());
+        sqlx::query(&sql).bind(id).execute(&mut *tx).await?;
+    }
+    sqlx::query!(r#"DELETE FROM document_aspice_mapping WHERE docid = $1"#, id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        r#"DELETE FROM document_requirement WHERE docid = $1 OR requirementid = $1"#,
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM document_trace_link WHERE source_docid = $1 OR target_docid = $1"#,
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(r#"DELETE FROM documents WHERE id = $1"#, id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// -----------------------------------tags----------------------------------
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTag {
+    pub name: String,
+    // name of the L1 tag, required for L2 tags
+    pub parent: Option<String>,
+    pub sort_order: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateTag {
+    pub name: Option<String>,
+    pub parent: Option<String>,
+    pub sort_order: Option<i32>,
+}
+
+fn tag_json(dimension: Dimension, level: TagLevel, row: &sqlx::postgres::PgRow) -> JsonValue {
+    let mut tag = json!({
+        "dimension": dimension.as_str(),
+        "level": match level {
+            TagLevel::L1 => 1,
+            TagLevel::L2 => 2,
+        },
+        "id": row.get::<Uuid, _>("id").to_string(),
+        "name": row.get::<String, _>("name"),
+        "sort_order": row.get::<i32, _>("sort_order"),
+    });
+    if level == TagLevel::L2 {
+        tag["parent_id"] = json!(row.get::<Option<Uuid>, _>("fatherid").map(|id| id.to_string()));
+    }
+    tag
+}
+
+async fn find_tag(
+    conn: &mut PgConnection,
+    dimension: Dimension,
+    level: TagLevel,
+    id: Uuid,
+) -> ApiResult<sqlx::postgres::PgRow> {
+    let sql = format!("SELECT * FROM {} WHERE id = $1", dimension.table(level));
+    sqlx::query(&sql)
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("tag {} does not exist", id)))
+}
+
+async fn find_parent_id(
+    conn: &mut PgConnection,
+    dimension: Dimension,
+    parent: &str,
+) -> ApiResult<Uuid> {
+    let sql = format!("SELECT id FROM {} WHERE name = $1", dimension.table(TagLevel::L1));
+    let row = sqlx::query(&sql)
+        .bind(parent)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ApiError::BadRequest(format!("parent tag '{}' does not exist", parent)))?;
+    Ok(row.get("id"))
+}
+
+async fn ensure_tag_name_free(
+    conn: &mut PgConnection,
+    dimension: Dimension,
+    level: TagLevel,
+    name: &str,
+    except: Option<Uuid>,
+) -> ApiResult<()> {
+    let sql = format!(
+        "SELECT id FROM {} WHERE name = $1 AND id <> $2",
+        dimension.table(level)
+    );
+    let exist = sqlx::query(&sql)
+        .bind(name)
+        .bind(except.unwrap_or_else(Uuid::nil))
+        .fetch_optional(&mut *conn)
+        .await?;
+    match exist {
+        Some(_) => Err(ApiError::Conflict(format!("tag '{}' already exists", name))),
+        None => Ok(()),
+    }
+}
+
+pub async fn create_tag(
+    State(db): State<PgPool>,
+    Path((dimension, level)): Path<(String, String)>,
+    Extension(principal): Extension<Principal>,
+    Json(body): Json<CreateTag>,
+) -> ApiResult<(StatusCode, Json<JsonValue>)> {
+    let dimension = parse_dimension(&dimension)?;
+    let level = parse_level(&level)?;
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("name must not be empty".to_string()));
+    }
+
+    let mut tx = begin(&db, &principal).await?;
+    ensure_tag_name_free(&mut tx, dimension, level, name, None).await?;
+
+    let table = dimension.table(level);
+    // an order given here is kept by later imports
+    let sort_order_manual = body.sort_order.is_some();
+    let sort_order = match body.sort_order {
+        Some(sort_order) => sort_order,
+        None => {
+            // append to the end
+            let sql = format!("SELECT COALESCE(MAX(sort_order), 0) + 1 AS next FROM {}", table);
+            sqlx::query(&sql).fetch_one(&mut *tx).await?.get::<i32, _>("next")
+        }
+    };
+
+    let id = Uuid::new_v4();
+    match level {
+        TagLevel::L1 => {
+            let sql = format!(
+                "INSERT INTO {} (id, name, sort_order, sort_order_manual) VALUES ($1, $2, $3, $4)",
+                table
+            );
+            sqlx::query(&sql)
+                .bind(id)
+                .bind(name)
+                .bind(sort_order)
+                .bind(sort_order_manual)
+                .execute(&mut *tx)
+                .await?;
+        }
+        TagLevel::L2 => {
+            let parent = body
+                .parent
+                .ok_or_else(|| ApiError::BadRequest("parent is required for L2 tags".to_string()))?;
+            let parent_id = find_parent_id(&mut tx, dimension, &parent).await?;
+            let sql = format!(
+                "INSERT INTO {} (id, name, fatherid, sort_order, sort_order_manual) VALUES ($1, $2, $3, $4, $5)",
+                table
+            );
+            sqlx::query(&sql)
+                .bind(id)
+                .bind(name)
+                .bind(parent_id)
+                .bind(sort_order)
+                .bind(sort_order_manual)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    let row = find_tag(&mut tx, dimension, level, id).await?;
+    tx.commit().await?;
+    Ok((StatusCode::CREATED, Json(tag_json(dimension, level, &row))))
+}
+
+pub async fn update_tag(
+    State(db): State<PgPool>,
+    Path((dimension, level, id)): Path<(String, String, Uuid)>,
+    Extension(principal): Extension<Principal>,
+    Json(body): Json<UpdateTag>,
+) -> ApiResult<Json<JsonValue>> {
+    let dimension = parse_dimension(&dimension)?;
+    let level = parse_level(&level)?;
+    let table = dimension.table(level);
+
+    let mut tx = begin(&db, &principal).await?;
+    find_tag(&mut tx, dimension, level, id).await?;
+
+    if let Some(name) = body.name {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ApiError::BadRequest("name must not be empty".to_string()));
+        }
+        ensure_tag_name_free(&mut tx, dimension, level, name, Some(id)).await?;
+        let sql = format!("UPDATE {} SET name = $2 WHERE id = $1", table);
+        sqlx::query(&sql).bind(id).bind(name).execute(&mut *tx).await?;
+    }
+    if let Some(sort_order) = body.sort_order {
+        // set by an editor, later imports keep it
+        let sql = format!("UPDATE {} SET sort_order = $2, sort_order_manual = true WHERE id = $1", table);
+        sqlx::query(&sql).bind(id).bind(sort_order).execute(&mut *tx).await?;
+    }
+    if let Some(parent) = body.parent {
+        if level == TagLevel::L1 {
+            return Err(ApiError::BadRequest("L1 tags have no parent".to_string()));
+        }
+        let parent_id = find_parent_id(&mut tx, dimension, &parent).await?;
+        let sql = format!("UPDATE {} SET fatherid = $2 WHERE id = $1", table);
+        sqlx::query(&sql).bind(id).bind(parent_id).execute(&mut *tx).await?;
+    }
+
+    let row = find_tag(&mut tx, dimension, level, id).await?;
+    tx.commit().await?;
+    Ok(Json(tag_json(dimension, level, &row)))
+}
+
+// L1 tags must be empty; deleting a L2 tag also removes its document links
+pub async fn delete_tag(
+    State(db): State<PgPool>,
+    Path((dimension, level, id)): Path<(String, String, Uuid)>,
+    Extension(principal): Extension<Principal>,
+) -> ApiResult<StatusCode> {
+    let dimension = parse_dimension(&dimension)?;
+    let level = parse_level(&level)?;
+
+    let mut tx = begin(&db, &principal).await?;
+    find_tag(&mut tx, dimension, level, id).await?;
+
+    match level {
+        TagLevel::L1 => {
+            let sql = format!(
+                "SELECT COUNT(*) AS children FROM {} WHERE fatherid = $1",
+                dimension.table(TagLevel::L2)
+            );
+            let children: i64 = sqlx::query(&sql).bind(id).fetch_one(&mut *tx).await?.get("children");
+            if children > 0 {
+                return Err(ApiError::Conflict(format!(
+                    "tag {} still has {} children",
+                    id, children
+                )));
+            }
+        }
+        TagLevel::L2 => {
+            let sql = format!(
+                "DELETE FROM {} WHERE {} = $1",
+                dimension.link_table(),
+                dimension.link_column()
+            );
+            sqlx::query(&sql).bind(id).execute(&mut *tx).await?;
+        }
+    }
+
+    let sql = format!("DELETE FROM {} WHERE id = $1", dimension.table(level));
+    sqlx::query(&sql).bind(id).execute(&mut *tx).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// -----------------------------------links---------------------------------
+
+#[derive(Debug, Deserialize)]
+pub struct TagLink {
+    // name of the L2 tag
+    pub name: String,
+}
+
+async fn find_l2_tag_id(conn: &mut PgConnection, dimension: Dimension, name: &str) -> ApiResult<Uuid> {
+    let sql = format!("SELECT id FROM {} WHERE name = $1", dimension.table(TagLevel::L2));
+    let row = sqlx::query(&sql)
+        .bind(name)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("tag '{}' does not exist", name)))?;
+    Ok(row.get("id"))
+}
+
+pub async fn link_document_tag(
+    State(db): State<PgPool>,
+    Path((id, dimension)): Path<(Uuid, String)>,
+    Extension(principal): Extension<Principal>,
+    Json(body): Json<TagLink>,
+) -> ApiResult<StatusCode> {
+    let dimension = parse_dimension(&dimension)?;
+    let mut tx = begin(&db, &principal).await?;
+    find_document(&mut tx, id).await?;
+    let tag_id = find_l2_tag_id(&mut tx, dimension, body.name.trim()).await?;
+
+    let sql = format!(
+        "INSERT INTO {} (docid, {}) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        dimension.link_table(),
+        dimension.link_column()
+    );
+    sqlx::query(&sql).bind(id).bind(tag_id).execute(&mut *tx).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn unlink_document_tag(
+    State(db): State<PgPool>,
+    Path((id, dimension, name)): Path<(Uuid, String, String)>,
+    Extension(principal): Extension<Principal>,
+) -> ApiResult<StatusCode> {
+    let dimension = parse_dimension(&dimension)?;
+    let mut tx = begin(&db, &principal).await?;
+    let tag_id = find_l2_tag_id(&mut tx, dimension, &name).await?;
+
+    let sql = format!(
+        "DELETE FROM {} WHERE docid = $1 AND {} = $2",
+        dimension.link_table(),
+        dimension.link_column()
+    );
+    let result = sqlx::query(&sql).bind(id).bind(tag_id).execute(&mut *tx).await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("document {} is not tagged '{}'", id, name)));
+    }
+
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn parse_aspice(step: &str) -> ApiResult<Aspice> {
+    Aspice::from_str(step).ok_or_else(|| ApiError::BadRequest(format!("unknown aspice step '{}'", step)))
+}
+
+pub async fn link_document_aspice(
+    State(db): State<PgPool>,
+    Path((id, step)): Path<(Uuid, String)>,
+    Extension(principal): Extension<Principal>,
+) -> ApiResult<StatusCode> {
+    let step = parse_aspice(&step)?;
+    let mut tx = begin(&db, &principal).await?;
+    find_document(&mut tx, id).await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO document_aspice_mapping (docid, aspice_step)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+    "#,
+        id,
+        step as Aspice,
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn unlink_document_aspice(
+    State(db): State<PgPool>,
+    Path((id, step)): Path<(Uuid, String)>,
+    Extension(principal): Extension<Principal>,
+) -> ApiResult<StatusCode> {
+    let step = parse_aspice(&step)?;
+    let mut tx = begin(&db, &principal).await?;
+    let result = sqlx::query!(
+        r#"DELETE FROM document_aspice_mapping WHERE docid = $1 AND aspice_step = $2"#,
+        id,
+        step as Aspice,
+    )
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("document {} has no such aspice step", id)));
+    }
+
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn link_document_requirement(
+    State(db): State<PgPool>,
+    Path((id, requirement_id)): Path<(Uuid, Uuid)>,
+    Extension(principal): Extension<Principal>,
+) -> ApiResult<StatusCode> {
+    if id == requirement_id {
+        return Err(ApiError::BadRequest("a document can not be its own requirement".to_string()));
+    }
+    let mut tx = begin(&db, &principal).await?;
+    find_document(&mut tx, id).await?;
+    find_document(&mut tx, requirement_id).await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO document_requirement (docid, requirementid)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+    "#,
+        id,
+        requirement_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn unlink_document_requirement(
+    State(db): State<PgPool>,
+    Path((id, requirement_id)): Path<(Uuid, Uuid)>,
+    Extension(principal): Extension<Principal>,
+) -> ApiResult<StatusCode> {
+    let mut tx = begin(&db, &principal).await?;
+    let result = sqlx::query!(
+        r#"DELETE FROM document_requirement WHERE docid = $1 AND requirementid = $2"#,
+        id,
+        requirement_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!(
+            "document {} is not associated with requirement {}",
+            id, requirement_id
+        )));
+    }
+
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TraceLink {
+    pub target: Uuid,
+    #[serde(rename = "type")]
+    pub link_type: TraceLinkType,
+}
+
+pub async fn link_document_trace(
+    State(db): State<PgPool>,
+    Path(id): Path<Uuid>,
+    Extension(principal): Extension<Principal>,
+    Json(body): Json<TraceLink>,
+) -> ApiResult<StatusCode> {
+    if id == body.target {
+        return Err(ApiError::BadRequest("a document can not trace to itself".to_string()));
+    }
+    let mut tx = begin(&db, &principal).await?;
+    find_document(&mut tx, id).await?;
+    find_document(&mut tx, body.target).await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO document_trace_link (source_docid, target_docid, link_type)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (source_docid, target_docid) DO UPDATE SET link_type = EXCLUDED.link_type
+    "#,
+        id,
+        body.target,
+        body.link_type as TraceLinkType,
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn unlink_document_trace(
+    State(db): State<PgPool>,
+    Path((id, target)): Path<(Uuid, Uuid)>,
+    Extension(principal): Extension<Principal>,
+) -> ApiResult<StatusCode> {
+    let mut tx = begin(&db, &principal).await?;
+    let result = sqlx::query!(
+        r#"DELETE FROM document_trace_link WHERE source_docid = $1 AND target_docid = $2"#,
+        id,
+        target,
+    )
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("document {} has no trace link to {}", id, target)));
+    }
+
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ---------------------------------snapshots-------------------------------
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSnapshot {
+    pub name: String,
+}
+
+pub async fn create_snapshot(
+    State(db): State<PgPool>,
+    Extension(principal): Extension<Principal>,
+    Json(body): Json<CreateSnapshot>,
+) -> ApiResult<(StatusCode, Json<JsonValue>)> {
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("name must not be empty".to_string()));
+    }
+    // `as_of` takes a time in the same place
+    if chrono::DateTime::parse_from_rfc3339(name).is_ok() {
+        return Err(ApiError::BadRequest(format!("snapshot name '{}' is a time", name)));
+    }
+
+    let snapshot = insert_snapshot(&db, name, None, Some(&principal.subject))
+        .await?
+        .ok_or_else(|| ApiError::Conflict(format!("snapshot '{}' already exists", name)))?;
+    Ok((StatusCode::CREATED, Json(serde_json::to_value(snapshot).unwrap())))
+}
+
+pub async fn delete_snapshot_by_name(
+    State(db): State<PgPool>,
+    Path(name): Path<String>,
+) -> ApiResult<StatusCode> {
+    if delete_snapshot(&db, &name).await? == 0 {
+        return Err(ApiError::NotFound(format!("snapshot '{}' does not exist", name)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
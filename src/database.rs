@@ -1,2 +1,4 @@
 pub mod models;
 pub mod dml_interface;
+pub mod migration;
+pub mod repository;
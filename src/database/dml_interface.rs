@@ -1,50 +1,96 @@
 use super::models::*;
 use anyhow::Context;
-use async_once::AsyncOnce;
-use sqlx::{postgres::PgPoolOptions, PgPool, Pool, Postgres};
+use sqlx::{postgres::PgConnectOptions, postgres::PgPoolOptions, ConnectOptions, PgPool, Postgres};
 //use crate::etl::transform_load::InternalData;
-use lazy_static::lazy_static;
 use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-lazy_static! {
-    static ref DB_POOL: AsyncOnce<anyhow::Result<PgPool>> = AsyncOnce::new(async {
-        let database_url = dotenvy::var("DATABASE_URL")
-        // The error from `var()` doesn't mention the environment variable.
-        .context("DATABASE_URL must be set")?;
-
-        let db = PgPoolOptions::new()
-        .max_connections(20)
-        .connect(&database_url)
-        .await
-        .context("failed to connect to DATABASE_URL")?;
+// set by the server config, DATABASE_URL and the defaults otherwise
+#[derive(Debug, Clone)]
+pub struct DbPoolOptions {
+    pub database_url: String,
+    pub max_connections: u32,
+    // how long a query waits for a free connection before failing
+    pub acquire_timeout: Duration,
+    // statements running longer are cancelled by postgres, None for no limit
+    pub statement_timeout: Option<Duration>,
+    // statements slower than this are logged as warnings
+    pub slow_statement: Duration,
+    // the audit log attributes the changes made through the pool to this user and sync run
+    pub changed_by: Option<String>,
+    pub sync_run: Option<Uuid>,
+}
 
-        Ok(db)
-    });
+impl DbPoolOptions {
+    // for the command line tools, which only read DATABASE_URL
+    pub fn from_env() -> anyhow::Result<DbPoolOptions> {
+        Ok(DbPoolOptions {
+            database_url: dotenvy::var("DATABASE_URL")
+                // The error from `var()` doesn't mention the environment variable.
+                .context("DATABASE_URL must be set")?,
+            max_connections: 20,
+            acquire_timeout: Duration::from_secs(30),
+            statement_timeout: None,
+            slow_statement: Duration::from_secs(1),
+            changed_by: None,
+            sync_run: None,
+        })
+    }
 }
 
-pub async fn get_db_pool() -> anyhow::Result<sqlx::PgPool> {
-    let pool_ref = DB_POOL.get().await;
-    match pool_ref {
-        Ok(pool) => Ok(pool.clone()), // Clone the PgPool
-        Err(err) => Err(anyhow::anyhow!(err)),
+// the pool is built once by each binary and handed to everything that queries
+pub async fn connect(options: &DbPoolOptions) -> anyhow::Result<PgPool> {
+    let mut connect_options = PgConnectOptions::from_str(&options.database_url)
+        .context("invalid DATABASE_URL")?
+        .log_statements(log::LevelFilter::Debug)
+        .log_slow_statements(log::LevelFilter::Warn, options.slow_statement);
+    if let Some(timeout) = options.statement_timeout {
+        let timeout = timeout.as_millis().to_string();
+        connect_options = connect_options.options([("statement_timeout", timeout.as_str())]);
+    }
+    if let Some(changed_by) = &options.changed_by {
+        connect_options = connect_options.options([("arch_map.changed_by", changed_by)]);
+    }
+    if let Some(sync_run) = options.sync_run {
+        connect_options = connect_options.options([("arch_map.sync_run", sync_run)]);
     }
+
+    PgPoolOptions::new()
+        .max_connections(options.max_connections)
+        .acquire_timeout(options.acquire_timeout)
+        .connect_with(connect_options)
+        .await
+        .context("failed to connect to DATABASE_URL")
 }
 
+// changes made in the transaction are attributed to `changed_by` in the audit log
+pub async fn begin_change(
+    pool: &sqlx::PgPool,
+    changed_by: &str,
+) -> anyhow::Result<sqlx::Transaction<'static, Postgres>> {
+    let mut tx = pool.begin().await?;
+    sqlx::query!("SELECT set_config('arch_map.changed_by', $1, true)", changed_by)
+        .fetch_one(&mut *tx)
+        .await
+        .context("failed to set the audit user")?;
+    Ok(tx)
+}
 
 pub async fn insert_documents(pool: &sqlx::PgPool, docs: Vec<Document>) -> anyhow::Result<()> {
     for doc in docs {
         //dbg!(&doc);
         sqlx::query!(
             r#"
-            INSERT INTO documents (id, name, link, description, associate_requirement)
-            VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO documents (id, name, link, description)
+            VALUES ($1, $2, $3, $4)
             "#,
             doc.id,
             doc.name,
             doc.link,
             doc.description,
-            doc.associate_requirement,
         )
         .execute(pool)
         .await
@@ -58,11 +104,12 @@ pub async fn insert_tech_l1(pool: &sqlx::PgPool, techs: Vec<TechL1>) -> anyhow::
     for tech in techs {
         sqlx::query!(
             r#"
-            INSERT INTO techl1 (id, name)
-            VALUES ($1, $2)
+            INSERT INTO techl1 (id, name, sort_order)
+            VALUES ($1, $2, $3)
             "#,
             tech.id,
             tech.name,
+            tech.sort_order,
         )
         .execute(pool)
         .await
@@ -76,12 +123,13 @@ pub async fn insert_tech_l2(pool: &sqlx::PgPool, techs: Vec<TechL2>) -> anyhow::
     for tech in techs {
         sqlx::query!(
             r#"
-            INSERT INTO techl2 (id, name, fatherid)
-            VALUES ($1, $2, $3)
+            INSERT INTO techl2 (id, name, fatherid, sort_order)
+            VALUES ($1, $2, $3, $4)
             "#,
             tech.id,
             tech.name,
             tech.father_id,
+            tech.sort_order,
         )
         .execute(pool)
         .await
@@ -116,11 +164,12 @@ pub async fn insert_system_l1(pool: &sqlx::PgPool, systems: Vec<SystemL1>) -> an
     for system in systems {
         sqlx::query!(
             r#"
-            INSERT INTO systeml1 (id, name)
-            VALUES ($1, $2)
+            INSERT INTO systeml1 (id, name, sort_order)
+            VALUES ($1, $2, $3)
             "#,
             system.id,
             system.name,
+            system.sort_order,
         )
         .execute(pool)
         .await
@@ -134,12 +183,13 @@ pub async fn insert_system_l2(pool: &sqlx::PgPool, systems: Vec<SystemL2>) -> an
     for system in systems {
         sqlx::query!(
             r#"
-            INSERT INTO systeml2 (id, name, fatherid)
-            VALUES ($1, $2, $3)
+            INSERT INTO systeml2 (id, name, fatherid, sort_order)
+            VALUES ($1, $2, $3, $4)
             "#,
             system.id,
             system.name,
             system.father_id,
+            system.sort_order,
         )
         .execute(pool)
         .await
@@ -174,11 +224,12 @@ pub async fn insert_mf_l1(pool: &sqlx::PgPool, mfs: Vec<MfL1>) -> anyhow::Result
     for mf in mfs {
         sqlx::query!(
             r#"
-            INSERT INTO mfl1 (id, name)
-            VALUES ($1, $2)
+            INSERT INTO mfl1 (id, name, sort_order)
+            VALUES ($1, $2, $3)
             "#,
             mf.id,
             mf.name,
+            mf.sort_order,
         )
         .execute(pool)
         .await
@@ -192,12 +243,13 @@ pub async fn insert_mf_l2(pool: &sqlx::PgPool, mfs: Vec<MfL2>) -> anyhow::Result
     for mf in mfs {
         sqlx::query!(
             r#"
-            INSERT INTO mfl2 (id, name, fatherid)
-            VALUES ($1, $2, $3)
+            INSERT INTO mfl2 (id, name, fatherid, sort_order)
+            VALUES ($1, $2, $3, $4)
             "#,
             mf.id,
             mf.name,
             mf.father_id,
+            mf.sort_order,
         )
         .execute(pool)
         .await
@@ -235,11 +287,12 @@ pub async fn insert_project_l1(
     for project in projects {
         sqlx::query!(
             r#"
-            INSERT INTO projectl1 (id, name)
-            VALUES ($1, $2)
+            INSERT INTO projectl1 (id, name, sort_order)
+            VALUES ($1, $2, $3)
             "#,
             project.id,
             project.name,
+            project.sort_order,
         )
         .execute(pool)
         .await
@@ -256,12 +309,13 @@ pub async fn insert_project_l2(
     for project in projects {
         sqlx::query!(
             r#"
-            INSERT INTO projectl2 (id, name, fatherid)
-            VALUES ($1, $2, $3)
+            INSERT INTO projectl2 (id, name, fatherid, sort_order)
+            VALUES ($1, $2, $3, $4)
             "#,
             project.id,
             project.name,
             project.father_id,
+            project.sort_order,
         )
         .execute(pool)
         .await
@@ -296,9 +350,11 @@ pub async fn insert_document_project(
         match result {
             Ok(_) => {}
             Err(e) => {
-                println!(
-                    "failed to insert document project[{}, {}]: {:?}",
-                    doc_project.doc_id, doc_project.project_id, e
+                tracing::error!(
+                    doc_id = %doc_project.doc_id,
+                    project_id = %doc_project.project_id,
+                    "failed to insert document project: {:?}",
+                    e
                 );
                 return Err(e)?;
             }
@@ -329,6 +385,92 @@ pub async fn insert_document_aspice(
     Ok(())
 }
 
+pub async fn insert_document_requirement(
+    pool: &sqlx::PgPool,
+    doc_requirements: Vec<DocumentRequirement>,
+) -> anyhow::Result<()> {
+    for doc_requirement in doc_requirements {
+        sqlx::query!(
+            r#"
+            INSERT INTO document_requirement (docid, requirementid)
+            VALUES ($1, $2)
+            "#,
+            doc_requirement.doc_id,
+            doc_requirement.requirement_id,
+        )
+        .execute(pool)
+        .await
+        .context("failed to insert document requirement")?;
+    }
+
+    Ok(())
+}
+
+pub async fn insert_document_trace_link(
+    pool: &sqlx::PgPool,
+    trace_links: Vec<DocumentTraceLink>,
+) -> anyhow::Result<()> {
+    for trace_link in trace_links {
+        sqlx::query!(
+            r#"
+            INSERT INTO document_trace_link (source_docid, target_docid, link_type)
+            VALUES ($1, $2, $3)
+            "#,
+            trace_link.source_docid,
+            trace_link.target_docid,
+            trace_link.link_type as TraceLinkType,
+        )
+        .execute(pool)
+        .await
+        .context("failed to insert document trace link")?;
+    }
+
+    Ok(())
+}
+
+// 更新已存在标签的顺序，表名来自 Dimension，不是用户输入；通过 API 设置过顺序的标签保持不变
+pub async fn update_tag_sort_order(
+    pool: &sqlx::PgPool,
+    sort_orders: Vec<TagSortOrder>,
+) -> anyhow::Result<()> {
+    for sort_order in sort_orders {
+        let sql = format!(
+            "UPDATE {} SET sort_order = $1 WHERE id = $2 AND NOT sort_order_manual",
+            sort_order.dimension.table(sort_order.level)
+        );
+        sqlx::query(&sql)
+            .bind(sort_order.sort_order)
+            .bind(sort_order.id)
+            .execute(pool)
+            .await
+            .context("failed to update tag sort order")?;
+    }
+
+    Ok(())
+}
+
+// return the number of updated tags
+pub async fn update_tag_sort_order_by_name(
+    conn: &mut sqlx::PgConnection,
+    dimension: Dimension,
+    level: TagLevel,
+    name: &str,
+    sort_order: i32,
+) -> anyhow::Result<u64> {
+    let sql = format!(
+        "UPDATE {} SET sort_order = $1, sort_order_manual = true WHERE name = $2",
+        dimension.table(level)
+    );
+    let result = sqlx::query(&sql)
+        .bind(sort_order)
+        .bind(name)
+        .execute(conn)
+        .await
+        .context("failed to update tag sort order")?;
+
+    Ok(result.rows_affected())
+}
+
 // 由于数据量比较小，所以在这里直接读取数据库中的数据，然后将其存入HashMap中
 // 目的是数据增量导入操作和数据库检索操作解耦。
 pub async fn read_exist_documents(
@@ -610,4 +752,493 @@ pub async fn read_exist_document_aspice(
 
     Ok(())
 }
+pub async fn read_exist_document_requirement(
+    pool: &sqlx::PgPool,
+    doc_requirements: &mut HashMap<Uuid, HashSet<String>>,
+) -> anyhow::Result<()> {
+    // join with documents to get the requirement name
+    let db_doc_requirements = sqlx::query!(
+        r#"
+        SELECT docid, documents.name AS requirement_name
+        FROM document_requirement
+        INNER JOIN documents
+        ON document_requirement.requirementid = documents.id
+        "#,
+    );
+
+    for doc_requirement in db_doc_requirements.fetch_all(pool).await? {
+        doc_requirements
+            .entry(doc_requirement.docid)
+            .or_default()
+            .insert(doc_requirement.requirement_name);
+    }
+
+    Ok(())
+}
+
+pub async fn read_exist_document_trace_link(
+    pool: &sqlx::PgPool,
+    trace_links: &mut HashMap<Uuid, HashSet<Uuid>>,
+) -> anyhow::Result<()> {
+    let db_trace_links = sqlx::query!(
+        r#"
+        SELECT source_docid, target_docid
+        FROM document_trace_link
+        "#,
+    );
+
+    for trace_link in db_trace_links.fetch_all(pool).await? {
+        trace_links
+            .entry(trace_link.source_docid)
+            .or_default()
+            .insert(trace_link.target_docid);
+    }
+
+    Ok(())
+}
+
+pub async fn read_document_trace_links(pool: &sqlx::PgPool) -> anyhow::Result<Vec<DocumentTraceLink>> {
+    let trace_links = sqlx::query_as!(
+        DocumentTraceLink,
+        r#"
+        SELECT source_docid, target_docid, link_type as "link_type: TraceLinkType"
+        FROM document_trace_link
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to read document trace links")?;
+
+    Ok(trace_links)
+}
+
+// 表名来自 Dimension，不是用户输入
+pub async fn read_tag_pairs(pool: &sqlx::PgPool, dimension: Dimension) -> anyhow::Result<Vec<TagPair>> {
+    let sql = format!(
+        "SELECT l1.name AS l1_name, l2.name AS l2_name, l1.sort_order AS l1_order, l2.sort_order AS l2_order
+        FROM {} l1
+        INNER JOIN {} l2 ON l1.id = l2.fatherid",
+        dimension.table(TagLevel::L1),
+        dimension.table(TagLevel::L2)
+    );
+    let pairs = sqlx::query_as::<_, TagPair>(&sql)
+        .fetch_all(pool)
+        .await
+        .context("failed to read tags")?;
+
+    Ok(pairs)
+}
+
+pub async fn read_document_tags(
+    pool: &sqlx::PgPool,
+    dimension: Dimension,
+) -> anyhow::Result<Vec<DocumentTag>> {
+    let sql = format!(
+        "SELECT link.docid, l2.name, l2.sort_order
+        FROM {} link
+        INNER JOIN {} l2 ON link.{} = l2.id",
+        dimension.link_table(),
+        dimension.table(TagLevel::L2),
+        dimension.link_column()
+    );
+    let tags = sqlx::query_as::<_, DocumentTag>(&sql)
+        .fetch_all(pool)
+        .await
+        .context("failed to read document tags")?;
+
+    Ok(tags)
+}
+
+// documents having the L2 tag, ordered by id
+pub async fn read_documents_by_tag(
+    pool: &sqlx::PgPool,
+    dimension: Dimension,
+    name: &str,
+) -> anyhow::Result<Vec<Document>> {
+    let sql = format!(
+        "SELECT documents.* FROM documents
+        INNER JOIN {} link ON documents.id = link.docid
+        INNER JOIN {} l2 ON link.{} = l2.id
+        WHERE l2.name = $1
+        ORDER BY documents.id",
+        dimension.link_table(),
+        dimension.table(TagLevel::L2),
+        dimension.link_column()
+    );
+    let docs = sqlx::query_as::<_, Document>(&sql)
+        .bind(name)
+        .fetch_all(pool)
+        .await
+        .context("failed to read documents by tag")?;
+
+    Ok(docs)
+}
+
+pub async fn read_document(pool: &sqlx::PgPool, id: Uuid) -> anyhow::Result<Option<Document>> {
+    let doc = sqlx::query_as!(Document, "SELECT * FROM documents WHERE id = $1", id)
+        .fetch_optional(pool)
+        .await
+        .context("failed to read document")?;
+
+    Ok(doc)
+}
+
+pub async fn read_documents(pool: &sqlx::PgPool) -> anyhow::Result<Vec<Document>> {
+    let docs = sqlx::query_as!(Document, "SELECT * FROM documents ORDER BY name")
+        .fetch_all(pool)
+        .await
+        .context("failed to read documents")?;
+
+    Ok(docs)
+}
+
+// document id -> its latest change in the audit log
+pub async fn read_document_modified(pool: &sqlx::PgPool) -> anyhow::Result<HashMap<Uuid, DateTime<Utc>>> {
+    let records = sqlx::query!(
+        r#"
+        SELECT document_id AS "document_id!", MAX(changed_at) AS "changed_at!"
+        FROM audit_log
+        WHERE document_id IS NOT NULL
+        GROUP BY document_id
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to read document modification times")?;
+
+    Ok(records
+        .into_iter()
+        .map(|record| (record.document_id, record.changed_at))
+        .collect())
+}
+
+pub async fn read_link_checks(pool: &sqlx::PgPool) -> anyhow::Result<Vec<LinkCheck>> {
+    let checks = sqlx::query_as!(LinkCheck, "SELECT * FROM document_link_check")
+        .fetch_all(pool)
+        .await
+        .context("failed to read link checks")?;
+
+    Ok(checks)
+}
+
+pub async fn read_link_check(pool: &sqlx::PgPool, id: Uuid) -> anyhow::Result<Option<LinkCheck>> {
+    let check = sqlx::query_as!(
+        LinkCheck,
+        "SELECT * FROM document_link_check WHERE docid = $1",
+        id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to read link check")?;
+
+    Ok(check)
+}
+
+// the latest result replaces the previous one of the document
+pub async fn insert_link_checks(pool: &sqlx::PgPool, checks: Vec<LinkCheck>) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    for check in checks {
+        sqlx::query!(
+            r#"
+            INSERT INTO document_link_check (docid, link, ok, status_code, final_url, error, checked_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (docid) DO UPDATE SET
+                link = EXCLUDED.link,
+                ok = EXCLUDED.ok,
+                status_code = EXCLUDED.status_code,
+                final_url = EXCLUDED.final_url,
+                error = EXCLUDED.error,
+                checked_at = EXCLUDED.checked_at
+            "#,
+            check.docid,
+            check.link,
+            check.ok,
+            check.status_code,
+            check.final_url,
+            check.error,
+            check.checked_at,
+        )
+        .execute(&mut *tx)
+        .await
+        .context("failed to insert link check")?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// documents whose current link failed its latest check
+pub async fn read_broken_links(pool: &sqlx::PgPool) -> anyhow::Result<Vec<(Document, LinkCheck)>> {
+    let records = sqlx::query!(
+        r#"
+        SELECT documents.id, documents.name, documents.link, documents.description,
+            checks.ok, checks.status_code, checks.final_url, checks.error, checks.checked_at
+        FROM documents
+        INNER JOIN document_link_check checks
+            ON checks.docid = documents.id AND checks.link = documents.link
+        WHERE NOT checks.ok
+        ORDER BY documents.name
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to read broken links")?;
+
+    Ok(records
+        .into_iter()
+        .map(|record| {
+            let check = LinkCheck {
+                docid: record.id,
+                link: record.link.clone(),
+                ok: record.ok,
+                status_code: record.status_code,
+                final_url: record.final_url,
+                error: record.error,
+                checked_at: record.checked_at,
+            };
+            let doc = Document {
+                id: record.id,
+                name: record.name,
+                link: record.link,
+                description: record.description,
+            };
+            (doc, check)
+        })
+        .collect())
+}
+
+pub async fn insert_sync_run(pool: &sqlx::PgPool, run: &SyncRun) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO sync_run (id, started_at, finished_at, success, error)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        run.id,
+        run.started_at,
+        run.finished_at,
+        run.success,
+        run.error,
+    )
+    .execute(pool)
+    .await
+    .context("failed to insert sync run")?;
+
+    Ok(())
+}
+
+pub async fn read_sync_stats(pool: &sqlx::PgPool) -> anyhow::Result<SyncStats> {
+    let record = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE success) AS "success_total!",
+            COUNT(*) FILTER (WHERE NOT success) AS "failure_total!",
+            MAX(finished_at) FILTER (WHERE success) AS last_success_at,
+            (SELECT EXTRACT(EPOCH FROM finished_at - started_at)::FLOAT8
+                FROM sync_run ORDER BY finished_at DESC LIMIT 1) AS last_duration_seconds
+        FROM sync_run
+        "#,
+    )
+    .fetch_one(pool)
+    .await
+    .context("failed to read sync runs")?;
+
+    Ok(SyncStats {
+        success_total: record.success_total,
+        failure_total: record.failure_total,
+        last_success_at: record.last_success_at,
+        last_duration_seconds: record.last_duration_seconds,
+    })
+}
+
+// changes of the document row, its tags and links, and of the links pointing to it
+pub async fn read_document_history(pool: &sqlx::PgPool, id: Uuid) -> anyhow::Result<Vec<AuditEntry>> {
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        r#"
+        SELECT * FROM audit_log
+        WHERE document_id = $1
+        OR COALESCE(new_row, old_row)->>'requirementid' = $1::text
+        OR COALESCE(new_row, old_row)->>'target_docid' = $1::text
+        ORDER BY id
+        "#,
+        id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to read document history")?;
+
+    Ok(entries)
+}
+
+// changes after `from` up to and including `to`, optionally only those of one sync run
+pub async fn read_audit_log(
+    pool: &sqlx::PgPool,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    sync_run: Option<Uuid>,
+) -> anyhow::Result<Vec<AuditEntry>> {
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        r#"
+        SELECT * FROM audit_log
+        WHERE ($1::timestamptz IS NULL OR changed_at > $1)
+        AND ($2::timestamptz IS NULL OR changed_at <= $2)
+        AND ($3::uuid IS NULL OR sync_run_id = $3)
+        ORDER BY id
+        "#,
+        from,
+        to,
+        sync_run,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to read audit log")?;
+
+    Ok(entries)
+}
+
+// copies the whole map in one statement, so the snapshot is consistent even while a sync runs.
+// None if a snapshot of that name already exists
+pub async fn insert_snapshot(
+    pool: &sqlx::PgPool,
+    name: &str,
+    sync_run: Option<Uuid>,
+    taken_by: Option<&str>,
+) -> anyhow::Result<Option<Snapshot>> {
+    let snapshot = sqlx::query_as!(
+        Snapshot,
+        r#"
+        INSERT INTO snapshot (id, name, sync_run_id, taken_by, data)
+        SELECT $1, $2, $3, $4, jsonb_build_object(
+            'documents', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT id, name, link, description FROM documents) t),
+            'tech_l1', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT id, name, sort_order FROM techl1) t),
+            'tech_l2', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT id, name, fatherid AS father_id, sort_order FROM techl2) t),
+            'document_tech', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT docid AS doc_id, techid AS tech_id FROM documenttech) t),
+            'system_l1', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT id, name, sort_order FROM systeml1) t),
+            'system_l2', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT id, name, fatherid AS father_id, sort_order FROM systeml2) t),
+            'document_system', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT docid AS doc_id, sysid AS sys_id FROM documentsystem) t),
+            'mf_l1', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT id, name, sort_order FROM mfl1) t),
+            'mf_l2', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT id, name, fatherid AS father_id, sort_order FROM mfl2) t),
+            'document_mf', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT docid AS doc_id, mfid AS mf_id FROM documentmf) t),
+            'project_l1', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT id, name, sort_order FROM projectl1) t),
+            'project_l2', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT id, name, fatherid AS father_id, sort_order FROM projectl2) t),
+            'document_project', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT docid AS doc_id, projectid AS project_id FROM documentproject) t),
+            'document_aspice', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT docid, aspice_step FROM document_aspice_mapping) t),
+            'document_requirement', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT docid AS doc_id, requirementid AS requirement_id FROM document_requirement) t),
+            'document_trace_link', (SELECT COALESCE(jsonb_agg(t), '[]') FROM (
+                SELECT source_docid, target_docid, link_type FROM document_trace_link) t)
+        )
+        ON CONFLICT (name) DO NOTHING
+        RETURNING id, name, taken_at, sync_run_id, taken_by
+        "#,
+        Uuid::new_v4(),
+        name,
+        sync_run,
+        taken_by,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to take snapshot")?;
+
+    Ok(snapshot)
+}
+
+pub async fn read_snapshots(pool: &sqlx::PgPool) -> anyhow::Result<Vec<Snapshot>> {
+    let snapshots = sqlx::query_as!(
+        Snapshot,
+        r#"
+        SELECT id, name, taken_at, sync_run_id, taken_by FROM snapshot
+        ORDER BY taken_at
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to read snapshots")?;
+
+    Ok(snapshots)
+}
+
+// as_of: the name of a snapshot, or an rfc3339 time for the latest snapshot taken at or before it
+pub async fn read_snapshot_as_of(
+    pool: &sqlx::PgPool,
+    as_of: &str,
+) -> anyhow::Result<Option<(Snapshot, SnapshotData)>> {
+    let (name, time) = match DateTime::parse_from_rfc3339(as_of) {
+        Ok(time) => (None, Some(time.with_timezone(&Utc))),
+        Err(_) => (Some(as_of), None),
+    };
+    let record = sqlx::query!(
+        r#"
+        SELECT id, name, taken_at, sync_run_id, taken_by,
+            data AS "data: sqlx::types::Json<SnapshotData>"
+        FROM snapshot
+        WHERE ($1::text IS NULL OR name = $1)
+        AND ($2::timestamptz IS NULL OR taken_at <= $2)
+        ORDER BY taken_at DESC
+        LIMIT 1
+        "#,
+        name,
+        time,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to read snapshot")?;
+
+    Ok(record.map(|record| {
+        let snapshot = Snapshot {
+            id: record.id,
+            name: record.name,
+            taken_at: record.taken_at,
+            sync_run_id: record.sync_run_id,
+            taken_by: record.taken_by,
+        };
+        (snapshot, record.data.0)
+    }))
+}
+
+// keeps the latest `keep` snapshots taken by data_sync, snapshots taken by editors stay
+pub async fn prune_sync_snapshots(pool: &sqlx::PgPool, keep: i64) -> anyhow::Result<u64> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM snapshot
+        WHERE sync_run_id IS NOT NULL
+        AND id NOT IN (
+            SELECT id FROM snapshot WHERE sync_run_id IS NOT NULL
+            ORDER BY taken_at DESC
+            LIMIT $1
+        )
+        "#,
+        keep,
+    )
+    .execute(pool)
+    .await
+    .context("failed to prune sync snapshots")?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn delete_snapshot(pool: &sqlx::PgPool, name: &str) -> anyhow::Result<u64> {
+    let result = sqlx::query!(r#"DELETE FROM snapshot WHERE name = $1"#, name)
+        .execute(pool)
+        .await
+        .context("failed to delete snapshot")?;
+
+    Ok(result.rows_affected())
+}
+
 // ----------------------------backend--------------------------------------
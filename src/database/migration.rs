@@ -0,0 +1,199 @@
+//! Schema migrations embedded from `migrations/`.
+//! The binaries refuse to run against a schema that is ahead of them or was changed
+//! after it was applied, and only apply pending migrations when asked to.
+
+use anyhow::{anyhow, Context};
+use clap::Subcommand;
+use serde::Serialize;
+use sqlx::migrate::Migrator;
+use std::collections::HashMap;
+
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    // the embedded sql differs from the one applied to the database
+    pub changed: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaStatus {
+    pub migrations: Vec<MigrationStatus>,
+    // applied to the database but unknown to this binary
+    pub unknown: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum MigrateCommand {
+    /// List the migrations and whether they are applied
+    Status,
+    /// Apply all pending migrations
+    Up,
+    /// Revert the latest applied migrations
+    Down {
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+}
+
+impl SchemaStatus {
+    pub fn pending(&self) -> Vec<i64> {
+        self.migrations
+            .iter()
+            .filter(|migration| !migration.applied)
+            .map(|migration| migration.version)
+            .collect()
+    }
+
+    pub fn applied(&self) -> Vec<i64> {
+        self.migrations
+            .iter()
+            .filter(|migration| migration.applied)
+            .map(|migration| migration.version)
+            .collect()
+    }
+
+    // the schema can only be used or migrated when it is not ahead of the binary
+    pub fn ensure_compatible(&self) -> anyhow::Result<()> {
+        if !self.unknown.is_empty() {
+            return Err(anyhow!(
+                "database schema is newer than this binary: migrations {:?} are applied but unknown, upgrade arch_map",
+                self.unknown
+            ));
+        }
+        let changed: Vec<i64> = self
+            .migrations
+            .iter()
+            .filter(|migration| migration.changed)
+            .map(|migration| migration.version)
+            .collect();
+        if !changed.is_empty() {
+            return Err(anyhow!(
+                "migrations {:?} were changed after they were applied",
+                changed
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub async fn status(pool: &sqlx::PgPool) -> anyhow::Result<SchemaStatus> {
+    let exists = sqlx::query_scalar!(r#"SELECT to_regclass('_sqlx_migrations') IS NOT NULL AS "exists!""#)
+        .fetch_one(pool)
+        .await
+        .context("failed to look up the migrations table")?;
+    // version -> checksum, the table only exists after the first migration
+    let mut applied: HashMap<i64, Vec<u8>> = if exists {
+        sqlx::query_as::<_, (i64, Vec<u8>)>(
+            "SELECT version, checksum FROM _sqlx_migrations WHERE success ORDER BY version",
+        )
+        .fetch_all(pool)
+        .await
+        .context("failed to read applied migrations")?
+        .into_iter()
+        .collect()
+    } else {
+        HashMap::new()
+    };
+
+    let migrations = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| {
+            let checksum = applied.remove(&migration.version);
+            MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                applied: checksum.is_some(),
+                changed: checksum.is_some_and(|checksum| checksum != *migration.checksum),
+            }
+        })
+        .collect();
+
+    let mut unknown: Vec<i64> = applied.into_keys().collect();
+    unknown.sort();
+    Ok(SchemaStatus {
+        migrations,
+        unknown,
+    })
+}
+
+// returns the versions that were applied
+pub async fn up(pool: &sqlx::PgPool) -> anyhow::Result<Vec<i64>> {
+    let schema = status(pool).await?;
+    schema.ensure_compatible()?;
+    MIGRATOR.run(pool).await.context("failed to apply migrations")?;
+    Ok(schema.pending())
+}
+
+// returns the versions that were reverted
+pub async fn down(pool: &sqlx::PgPool, steps: usize) -> anyhow::Result<Vec<i64>> {
+    let schema = status(pool).await?;
+    schema.ensure_compatible()?;
+    let applied = schema.applied();
+    let keep = applied.len().saturating_sub(steps);
+    // undo reverts every migration newer than the target version
+    let target = if keep == 0 { 0 } else { applied[keep - 1] };
+    MIGRATOR
+        .undo(pool, target)
+        .await
+        .context("failed to revert migrations")?;
+    Ok(applied[keep..].iter().rev().cloned().collect())
+}
+
+// called on startup: refuse an incompatible schema, apply pending migrations only if asked to
+pub async fn prepare(pool: &sqlx::PgPool, migrate: bool) -> anyhow::Result<()> {
+    let schema = status(pool).await?;
+    schema.ensure_compatible()?;
+    let pending = schema.pending();
+    if pending.is_empty() {
+        return Ok(());
+    }
+    if !migrate {
+        return Err(anyhow!(
+            "{} pending migrations {:?}, run `migrate up` or start with --migrate",
+            pending.len(),
+            pending
+        ));
+    }
+    let applied = up(pool).await?;
+    tracing::info!(?applied, "applied migrations");
+    Ok(())
+}
+
+pub async fn run_command(pool: &sqlx::PgPool, command: &MigrateCommand) -> anyhow::Result<()> {
+    match command {
+        MigrateCommand::Status => {
+            let schema = status(pool).await?;
+            for migration in &schema.migrations {
+                let state = match (migration.applied, migration.changed) {
+                    (true, true) => "changed",
+                    (true, false) => "applied",
+                    (false, _) => "pending",
+                };
+                println!("{:<8} {} {}", state, migration.version, migration.description);
+            }
+            for version in &schema.unknown {
+                println!("{:<8} {}", "unknown", version);
+            }
+        }
+        MigrateCommand::Up => {
+            let applied = up(pool).await?;
+            if applied.is_empty() {
+                println!("database is up to date");
+            }
+            for version in applied {
+                println!("applied  {}", version);
+            }
+        }
+        MigrateCommand::Down { steps } => {
+            for version in down(pool, *steps).await? {
+                println!("reverted {}", version);
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,6 @@
 // 对应于 `Documents` 表
 use uuid::Uuid;
+use chrono::{DateTime, Utc};
 use std::collections::HashSet;
 use std::hash::{Hash, Hasher};
 use serde::{Serialize, Deserialize};
@@ -10,101 +11,149 @@ pub struct Document {
     pub name: String,
     pub link: String,
     pub description: Option<String>,
-    pub associate_requirement: Uuid,
 }
 
 // 对应于 `TechL1` 表
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
 pub struct TechL1 {
     pub id: Uuid,
     pub name: String,
+    pub sort_order: i32,
 }
 
 // 对应于 `TechL2` 表
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
 pub struct TechL2 {
     pub id: Uuid,
     pub name: String,
     pub father_id: Uuid,
+    pub sort_order: i32,
 }
 
 
 
 // 对应于 `DocumentTech` 表
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
 pub struct DocumentTech {
     pub doc_id: Uuid,
     pub tech_id: Uuid,
 }
 
 // 对应于 `SystemL1` 表
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
 pub struct SystemL1 {
     pub id: Uuid,
     pub name: String,
+    pub sort_order: i32,
 }
 
 // 对应于 `SystemL2` 表
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
 pub struct SystemL2 {
     pub id: Uuid,
     pub name: String,
     pub father_id: Uuid,
+    pub sort_order: i32,
 }
 
 // 对应于 `DocumentSystem` 表
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
 pub struct DocumentSystem {
     pub doc_id: Uuid,
     pub sys_id: Uuid,
 }
 
 // 对应于 `MFL1` 表
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
 pub struct MfL1 {
     pub id: Uuid,
     pub name: String,
+    pub sort_order: i32,
 }
 
 // 对应于 `MFL2` 表
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
 pub struct MfL2 {
     pub id: Uuid,
     pub name: String,
     pub father_id: Uuid,
+    pub sort_order: i32,
 }
 
 // 对应于 `DocumentMF` 表
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
 pub struct DocumentMf {
     pub doc_id: Uuid,
     pub mf_id: Uuid,
 }
 
 // 对应于 `ProjectL1` 表
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
 pub struct ProjectL1 {
     pub id: Uuid,
     pub name: String,
+    pub sort_order: i32,
 }
 
 // 对应于 `ProjectL2` 表
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
 pub struct ProjectL2 {
     pub id: Uuid,
     pub name: String,
     pub father_id: Uuid,
+    pub sort_order: i32,
 }
 
 // 对应于 `DocumentProject` 表
-#[derive(Debug, Clone, sqlx::FromRow, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize, PartialEq, PartialOrd, Eq, Ord)]
 pub struct DocumentProject {
     pub doc_id: Uuid,
     pub project_id: Uuid,
 }
 
+// 标签的四个维度，每个维度都有 L1/L2 两级标签表和文档关联表
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Dimension {
+    Tech,
+    System,
+    Mf,
+    Project,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum TagLevel {
+    L1,
+    L2,
+}
+
+// 某个维度中的一对 L1/L2 标签，用于构建标签树
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct TagPair {
+    pub l1_name: String,
+    pub l2_name: String,
+    pub l1_order: i32,
+    pub l2_order: i32,
+}
+
+// 文档与某个维度 L2 标签的关联，带标签名和排序
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct DocumentTag {
+    pub docid: Uuid,
+    pub name: String,
+    pub sort_order: i32,
+}
+
+// 某个维度中一个标签的排序
+#[derive(Debug, Clone)]
+pub struct TagSortOrder {
+    pub dimension: Dimension,
+    pub level: TagLevel,
+    pub id: Uuid,
+    pub sort_order: i32,
+}
+
 // mapping to pg enum type: aspice
-#[derive(sqlx::Type, Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[derive(sqlx::Type, Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize)]
 #[sqlx(type_name = "aspice", rename_all = "lowercase")]
 pub enum Aspice {
     需求,
@@ -120,12 +169,124 @@ pub enum Aspice {
 //     aspice_step ASPICE NOT NULL,
 //     PRIMARY KEY (docid, aspice_step)
 // );
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
 pub struct DocumentAspiceMapping {
     pub docid: Uuid,
     pub aspice_step: Aspice,
 }
 
+// 对应于 `document_requirement` 表
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
+pub struct DocumentRequirement {
+    pub doc_id: Uuid,
+    pub requirement_id: Uuid,
+}
+
+// mapping to pg enum type: trace_link_type
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[sqlx(type_name = "trace_link_type", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum TraceLinkType {
+    Refines,
+    Verifies,
+    Implements,
+}
+
+// CREATE TABLE document_trace_link (
+//     source_docid UUID REFERENCES Documents(id),
+//     target_docid UUID REFERENCES Documents(id),
+//     link_type TRACE_LINK_TYPE NOT NULL,
+//     PRIMARY KEY (source_docid, target_docid)
+// );
+#[derive(Debug, Clone, sqlx::FromRow, Deserialize)]
+pub struct DocumentTraceLink {
+    pub source_docid: Uuid,
+    pub target_docid: Uuid,
+    pub link_type: TraceLinkType,
+}
+
+// 一次数据同步的结果
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct SyncRun {
+    pub id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+// 一行数据的一次变更，由 audit_log 表的触发器记录
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub changed_at: DateTime<Utc>,
+    pub table_name: String,
+    // insert, update or delete
+    pub operation: String,
+    pub row_key: String,
+    pub document_id: Option<Uuid>,
+    pub old_row: Option<serde_json::Value>,
+    pub new_row: Option<serde_json::Value>,
+    pub sync_run_id: Option<Uuid>,
+    pub changed_by: Option<String>,
+}
+
+// 文档链接最近一次检查的结果
+#[derive(Debug, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct LinkCheck {
+    pub docid: Uuid,
+    // the link that was checked, the result does not apply to a changed link
+    pub link: String,
+    // a 2xx answer, after following redirects
+    pub ok: bool,
+    pub status_code: Option<i32>,
+    pub final_url: Option<String>,
+    // why there was no answer: invalid link, timeout, connection refused, ...
+    pub error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+// 架构图的一个命名快照，不含数据本身
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct Snapshot {
+    pub id: Uuid,
+    pub name: String,
+    pub taken_at: DateTime<Utc>,
+    // the sync run that took it, None for snapshots taken through the api
+    pub sync_run_id: Option<Uuid>,
+    pub taken_by: Option<String>,
+}
+
+// 快照中保存的全部数据，即 snapshot 表的 data 列
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SnapshotData {
+    pub documents: Vec<Document>,
+    pub tech_l1: Vec<TechL1>,
+    pub tech_l2: Vec<TechL2>,
+    pub document_tech: Vec<DocumentTech>,
+    pub system_l1: Vec<SystemL1>,
+    pub system_l2: Vec<SystemL2>,
+    pub document_system: Vec<DocumentSystem>,
+    pub mf_l1: Vec<MfL1>,
+    pub mf_l2: Vec<MfL2>,
+    pub document_mf: Vec<DocumentMf>,
+    pub project_l1: Vec<ProjectL1>,
+    pub project_l2: Vec<ProjectL2>,
+    pub document_project: Vec<DocumentProject>,
+    pub document_aspice: Vec<DocumentAspiceMapping>,
+    pub document_requirement: Vec<DocumentRequirement>,
+    pub document_trace_link: Vec<DocumentTraceLink>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncStats {
+    pub success_total: i64,
+    pub failure_total: i64,
+    pub last_success_at: Option<DateTime<Utc>>,
+    // duration of the latest run, successful or not
+    pub last_duration_seconds: Option<f64>,
+}
+
 impl Document {
     pub fn new(name: String, link: String, description: Option<String>) -> Document {
         Document {
@@ -133,36 +294,50 @@ impl Document {
             name,
             link,
             description,
-            associate_requirement: Uuid::nil(),
         }
     }
 
-    pub fn to_json_value(&self, is_related: bool) -> serde_json::Value {
+    // link_check: the latest check of the document, shown if it checked the current link
+    pub fn to_json_value(&self, is_related: bool, link_check: Option<&LinkCheck>) -> serde_json::Value {
+        let link_status = link_check
+            .filter(|check| check.docid == self.id && check.link == self.link)
+            .map(|check| {
+                serde_json::json!({
+                    "ok": check.ok,
+                    "statusCode": check.status_code,
+                    "finalUrl": check.final_url,
+                    "error": check.error,
+                    "checkedAt": check.checked_at,
+                })
+            });
         serde_json::json!({
             "id": self.id.to_string(),
             "name": self.name,
             "address": self.link,
             "description": self.description,
             "isRelated": is_related,
+            "linkStatus": link_status,
         })
     }
 }
 
 impl TechL1 {
-    pub fn new(name: String) -> TechL1 {
+    pub fn new(name: String, sort_order: i32) -> TechL1 {
         TechL1 {
             id: Uuid::new_v4(),
             name,
+            sort_order,
         }
     }
 }
 
 impl TechL2 {
-    pub fn new(name: String, father_id: Uuid) -> TechL2 {
+    pub fn new(name: String, father_id: Uuid, sort_order: i32) -> TechL2 {
         TechL2 {
             id: Uuid::new_v4(),
             name,
             father_id,
+            sort_order,
         }
     }
 }
@@ -177,20 +352,22 @@ impl DocumentTech {
 }
 
 impl SystemL1 {
-    pub fn new(name: String) -> SystemL1 {
+    pub fn new(name: String, sort_order: i32) -> SystemL1 {
         SystemL1 {
             id: Uuid::new_v4(),
             name,
+            sort_order,
         }
     }
 }
 
 impl SystemL2 {
-    pub fn new(name: String, father_id: Uuid) -> SystemL2 {
+    pub fn new(name: String, father_id: Uuid, sort_order: i32) -> SystemL2 {
         SystemL2 {
             id: Uuid::new_v4(),
             name,
             father_id,
+            sort_order,
         }
     }
 }
@@ -205,20 +382,22 @@ impl DocumentSystem {
 }
 
 impl MfL1 {
-    pub fn new(name: String) -> MfL1 {
+    pub fn new(name: String, sort_order: i32) -> MfL1 {
         MfL1 {
             id: Uuid::new_v4(),
             name,
+            sort_order,
         }
     }
 }
 
 impl MfL2 {
-    pub fn new(name: String, father_id: Uuid) -> MfL2 {
+    pub fn new(name: String, father_id: Uuid, sort_order: i32) -> MfL2 {
         MfL2 {
             id: Uuid::new_v4(),
             name,
             father_id,
+            sort_order,
         }
     }
 }
@@ -233,20 +412,22 @@ impl DocumentMf {
 }
 
 impl ProjectL1 {
-    pub fn new(name: String) -> ProjectL1 {
+    pub fn new(name: String, sort_order: i32) -> ProjectL1 {
         ProjectL1 {
             id: Uuid::new_v4(),
             name,
+            sort_order,
         }
     }
 }
 
 impl ProjectL2 {
-    pub fn new(name: String, father_id: Uuid) -> ProjectL2 {
+    pub fn new(name: String, father_id: Uuid, sort_order: i32) -> ProjectL2 {
         ProjectL2 {
             id: Uuid::new_v4(),
             name,
             father_id,
+            sort_order,
         }
     }
 }
@@ -273,6 +454,35 @@ impl Aspice {
         }
     }
 
+    // V 模型中从需求到路测的顺序
+    pub fn all() -> Vec<Aspice> {
+        vec![
+            Aspice::需求,
+            Aspice::架构,
+            Aspice::详设,
+            Aspice::单测,
+            Aspice::集测,
+            Aspice::路测,
+        ]
+    }
+
+    // 与数据库中 aspice 枚举值一致的短名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Aspice::需求 => "需求",
+            Aspice::架构 => "架构",
+            Aspice::详设 => "详设",
+            Aspice::单测 => "单测",
+            Aspice::集测 => "集测",
+            Aspice::路测 => "路测",
+        }
+    }
+
+    // 单测、集测、路测 验证 V 模型左侧的阶段
+    pub fn is_verification(&self) -> bool {
+        matches!(self, Aspice::单测 | Aspice::集测 | Aspice::路测)
+    }
+
     pub fn to_string(&self) -> String {
         match self {
             Aspice::需求 => "软件需求分析".to_string(),
@@ -292,4 +502,132 @@ impl DocumentAspiceMapping {
             aspice_step,
         }
     }
-}
\ No newline at end of file
+}
+
+impl DocumentRequirement {
+    pub fn new(doc_id: Uuid, requirement_id: Uuid) -> DocumentRequirement {
+        DocumentRequirement {
+            doc_id,
+            requirement_id,
+        }
+    }
+}
+
+impl TraceLinkType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TraceLinkType::Refines => "refines",
+            TraceLinkType::Verifies => "verifies",
+            TraceLinkType::Implements => "implements",
+        }
+    }
+}
+
+impl DocumentTraceLink {
+    pub fn new(source_docid: Uuid, target_docid: Uuid, link_type: TraceLinkType) -> DocumentTraceLink {
+        DocumentTraceLink {
+            source_docid,
+            target_docid,
+            link_type,
+        }
+    }
+}
+
+impl SyncRun {
+    // `id` is chosen when the run starts, the audit log refers to it
+    pub fn new(id: Uuid, started_at: DateTime<Utc>, finished_at: DateTime<Utc>, error: Option<String>) -> SyncRun {
+        SyncRun {
+            id,
+            started_at,
+            finished_at,
+            success: error.is_none(),
+            error,
+        }
+    }
+}
+
+impl Dimension {
+    pub fn all() -> Vec<Dimension> {
+        vec![
+            Dimension::Tech,
+            Dimension::System,
+            Dimension::Mf,
+            Dimension::Project,
+        ]
+    }
+
+    // 接受接口中使用的模块名 (arch/component/mf/project) 和维度名
+    pub fn from_name(s: &str) -> Option<Dimension> {
+        match s {
+            "arch" | "tech" => Some(Dimension::Tech),
+            "component" | "system" => Some(Dimension::System),
+            "mf" => Some(Dimension::Mf),
+            "project" => Some(Dimension::Project),
+            _ => None,
+        }
+    }
+
+    // 与 filter_and_classify 返回中的 key 一致
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dimension::Tech => "arch",
+            Dimension::System => "component",
+            Dimension::Mf => "mf",
+            Dimension::Project => "project",
+        }
+    }
+
+    pub fn table(&self, level: TagLevel) -> &'static str {
+        match (self, level) {
+            (Dimension::Tech, TagLevel::L1) => "techl1",
+            (Dimension::Tech, TagLevel::L2) => "techl2",
+            (Dimension::System, TagLevel::L1) => "systeml1",
+            (Dimension::System, TagLevel::L2) => "systeml2",
+            (Dimension::Mf, TagLevel::L1) => "mfl1",
+            (Dimension::Mf, TagLevel::L2) => "mfl2",
+            (Dimension::Project, TagLevel::L1) => "projectl1",
+            (Dimension::Project, TagLevel::L2) => "projectl2",
+        }
+    }
+
+    // 文档与 L2 标签的关联表
+    pub fn link_table(&self) -> &'static str {
+        match self {
+            Dimension::Tech => "documenttech",
+            Dimension::System => "documentsystem",
+            Dimension::Mf => "documentmf",
+            Dimension::Project => "documentproject",
+        }
+    }
+
+    // 关联表中指向 L2 标签的列
+    pub fn link_column(&self) -> &'static str {
+        match self {
+            Dimension::Tech => "techid",
+            Dimension::System => "sysid",
+            Dimension::Mf => "mfid",
+            Dimension::Project => "projectid",
+        }
+    }
+}
+
+impl TagLevel {
+    pub fn from_name(s: &str) -> Option<TagLevel> {
+        match s {
+            "l1" | "L1" | "1" => Some(TagLevel::L1),
+            "l2" | "L2" | "2" => Some(TagLevel::L2),
+            _ => None,
+        }
+    }
+}
+
+impl TagSortOrder {
+    pub fn new(dimension: Dimension, level: TagLevel, id: Uuid, sort_order: i32) -> TagSortOrder {
+        TagSortOrder {
+            dimension,
+            level,
+            id,
+            sort_order,
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! Data access used by the import and the tag trees.
+//! `PgRepository` runs the queries of `dml_interface`, `MemoryRepository` keeps the
+//! tables in memory so the import and the trees can be tested without a database.
+
+pub mod memory;
+
+use super::dml_interface;
+use super::models::*;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+pub use memory::MemoryRepository;
+
+#[async_trait]
+pub trait Repository: Send + Sync {
+    // existing rows, keyed as the import looks them up
+    async fn read_exist_documents(&self, docs: &mut HashMap<String, Uuid>) -> anyhow::Result<()>;
+    async fn read_exist_tags(
+        &self,
+        dimension: Dimension,
+        level: TagLevel,
+        tags: &mut HashMap<String, Uuid>,
+    ) -> anyhow::Result<()>;
+    // document id -> names of its L2 tags
+    async fn read_exist_document_tags(
+        &self,
+        dimension: Dimension,
+        doc_tags: &mut HashMap<Uuid, HashSet<String>>,
+    ) -> anyhow::Result<()>;
+    async fn read_exist_document_aspice(
+        &self,
+        doc_aspices: &mut HashMap<Uuid, HashSet<Aspice>>,
+    ) -> anyhow::Result<()>;
+    // document id -> names of its associate requirements
+    async fn read_exist_document_requirement(
+        &self,
+        doc_requirements: &mut HashMap<Uuid, HashSet<String>>,
+    ) -> anyhow::Result<()>;
+    async fn read_exist_document_trace_link(
+        &self,
+        trace_links: &mut HashMap<Uuid, HashSet<Uuid>>,
+    ) -> anyhow::Result<()>;
+
+    async fn insert_documents(&self, docs: Vec<Document>) -> anyhow::Result<()>;
+    async fn insert_tech_l1(&self, techs: Vec<TechL1>) -> anyhow::Result<()>;
+    async fn insert_tech_l2(&self, techs: Vec<TechL2>) -> anyhow::Result<()>;
+    async fn insert_document_tech(&self, doc_techs: Vec<DocumentTech>) -> anyhow::Result<()>;
+    async fn insert_system_l1(&self, systems: Vec<SystemL1>) -> anyhow::Result<()>;
+    async fn insert_system_l2(&self, systems: Vec<SystemL2>) -> anyhow::Result<()>;
+    async fn insert_document_system(&self, doc_systems: Vec<DocumentSystem>) -> anyhow::Result<()>;
+    async fn insert_mf_l1(&self, mfs: Vec<MfL1>) -> anyhow::Result<()>;
+    async fn insert_mf_l2(&self, mfs: Vec<MfL2>) -> anyhow::Result<()>;
+    async fn insert_document_mf(&self, doc_mfs: Vec<DocumentMf>) -> anyhow::Result<()>;
+    async fn insert_project_l1(&self, projects: Vec<ProjectL1>) -> anyhow::Result<()>;
+    async fn insert_project_l2(&self, projects: Vec<ProjectL2>) -> anyhow::Result<()>;
+    async fn insert_document_project(
+        &self,
+        doc_projects: Vec<DocumentProject>,
+    ) -> anyhow::Result<()>;
+    async fn insert_document_aspice(
+        &self,
+        doc_aspices: Vec<DocumentAspiceMapping>,
+    ) -> anyhow::Result<()>;
+    async fn insert_document_requirement(
+        &self,
+        doc_requirements: Vec<DocumentRequirement>,
+    ) -> anyhow::Result<()>;
+    async fn insert_document_trace_link(
+        &self,
+        trace_links: Vec<DocumentTraceLink>,
+    ) -> anyhow::Result<()>;
+    // the order found by an import, tags whose order was set through the API keep theirs
+    async fn update_tag_sort_order(&self, sort_orders: Vec<TagSortOrder>) -> anyhow::Result<()>;
+    // the order set by an editor, later imports keep it. Returns the number of tags changed
+    async fn set_tag_sort_order(
+        &self,
+        dimension: Dimension,
+        level: TagLevel,
+        name: &str,
+        sort_order: i32,
+    ) -> anyhow::Result<u64>;
+
+    // every L2 tag of the dimension with its L1 tag
+    async fn read_tag_pairs(&self, dimension: Dimension) -> anyhow::Result<Vec<TagPair>>;
+    async fn read_document_tags(&self, dimension: Dimension) -> anyhow::Result<Vec<DocumentTag>>;
+    // documents having the L2 tag, ordered by id
+    async fn read_documents_by_tag(
+        &self,
+        dimension: Dimension,
+        name: &str,
+    ) -> anyhow::Result<Vec<Document>>;
+    async fn read_document(&self, id: Uuid) -> anyhow::Result<Option<Document>>;
+    // all documents, ordered by name
+    async fn read_documents(&self) -> anyhow::Result<Vec<Document>>;
+    async fn read_document_trace_links(&self) -> anyhow::Result<Vec<DocumentTraceLink>>;
+    async fn read_link_checks(&self) -> anyhow::Result<Vec<LinkCheck>>;
+    // replaces the previous result of each document
+    async fn insert_link_checks(&self, checks: Vec<LinkCheck>) -> anyhow::Result<()>;
+    // document id -> the time of its latest change
+    async fn read_document_modified(&self) -> anyhow::Result<HashMap<Uuid, DateTime<Utc>>>;
+}
+
+#[derive(Debug, Clone)]
+pub struct PgRepository {
+    pool: sqlx::PgPool,
+}
+
+impl PgRepository {
+    pub fn new(pool: sqlx::PgPool) -> PgRepository {
+        PgRepository { pool }
+    }
+}
+
+#[async_trait]
+impl Repository for PgRepository {
+    async fn read_exist_documents(&self, docs: &mut HashMap<String, Uuid>) -> anyhow::Result<()> {
+        dml_interface::read_exist_documents(&self.pool, docs).await
+    }
+
+    async fn read_exist_tags(
+        &self,
+        dimension: Dimension,
+        level: TagLevel,
+        tags: &mut HashMap<String, Uuid>,
+    ) -> anyhow::Result<()> {
+        let pool = &self.pool;
+        match (dimension, level) {
+            (Dimension::Tech, TagLevel::L1) => dml_interface::read_exist_tech_l1(pool, tags).await,
+            (Dimension::Tech, TagLevel::L2) => dml_interface::read_exist_tech_l2(pool, tags).await,
+            (Dimension::System, TagLevel::L1) => {
+                dml_interface::read_exist_system_l1(pool, tags).await
+            }
+            (Dimension::System, TagLevel::L2) => {
+                dml_interface::read_exist_system_l2(pool, tags).await
+            }
+            (Dimension::Mf, TagLevel::L1) => dml_interface::read_exist_mf_l1(pool, tags).await,
+            (Dimension::Mf, TagLevel::L2) => dml_interface::read_exist_mf_l2(pool, tags).await,
+            (Dimension::Project, TagLevel::L1) => {
+                dml_interface::read_exist_project_l1(pool, tags).await
+            }
+            (Dimension::Project, TagLevel::L2) => {
+                dml_interface::read_exist_project_l2(pool, tags).await
+            }
+        }
+    }
+
+    async fn read_exist_document_tags(
+        &self,
+        dimension: Dimension,
+        doc_tags: &mut HashMap<Uuid, HashSet<String>>,
+    ) -> anyhow::Result<()> {
+        let pool = &self.pool;
+        match dimension {
+            Dimension::Tech => dml_interface::read_exist_document_tech(pool, doc_tags).await,
+            Dimension::System => dml_interface::read_exist_document_system(pool, doc_tags).await,
+            Dimension::Mf => dml_interface::read_exist_document_mf(pool, doc_tags).await,
+            Dimension::Project => dml_interface::read_exist_document_project(pool, doc_tags).await,
+        }
+    }
+
+    async fn read_exist_document_aspice(
+        &self,
+        doc_aspices: &mut HashMap<Uuid, HashSet<Aspice>>,
+    ) -> anyhow::Result<()> {
+        dml_interface::read_exist_document_aspice(&self.pool, doc_aspices).await
+    }
+
+    async fn read_exist_document_requirement(
+        &self,
+        doc_requirements: &mut HashMap<Uuid, HashSet<String>>,
+    ) -> anyhow::Result<()> {
+        dml_interface::read_exist_document_requirement(&self.pool, doc_requirements).await
+    }
+
+    async fn read_exist_document_trace_link(
+        &self,
+        trace_links: &mut HashMap<Uuid, HashSet<Uuid>>,
+    ) -> anyhow::Result<()> {
+        dml_interface::read_exist_document_trace_link(&self.pool, trace_links).await
+    }
+
+    async fn insert_documents(&self, docs: Vec<Document>) -> anyhow::Result<()> {
+        dml_interface::insert_documents(&self.pool, docs).await
+    }
+
+    async fn insert_tech_l1(&self, techs: Vec<TechL1>) -> anyhow::Result<()> {
+        dml_interface::insert_tech_l1(&self.pool, techs).await
+    }
+
+    async fn insert_tech_l2(&self, techs: Vec<TechL2>) -> anyhow::Result<()> {
+        dml_interface::insert_tech_l2(&self.pool, techs).await
+    }
+
+    async fn insert_document_tech(&self, doc_techs: Vec<DocumentTech>) -> anyhow::Result<()> {
+        dml_interface::insert_document_tech(&self.pool, doc_techs).await
+    }
+
+    async fn insert_system_l1(&self, systems: Vec<SystemL1>) -> anyhow::Result<()> {
+        dml_interface::insert_system_l1(&self.pool, systems).await
+    }
+
+    async fn insert_system_l2(&self, systems: Vec<SystemL2>) -> anyhow::Result<()> {
+        dml_interface::insert_system_l2(&self.pool, systems).await
+    }
+
+    async fn insert_document_system(&self, doc_systems: Vec<DocumentSystem>) -> anyhow::Result<()> {
+        dml_interface::insert_document_system(&self.pool, doc_systems).await
+    }
+
+    async fn insert_mf_l1(&self, mfs: Vec<MfL1>) -> anyhow::Result<()> {
+        dml_interface::insert_mf_l1(&self.pool, mfs).await
+    }
+
+    async fn insert_mf_l2(&self, mfs: Vec<MfL2>) -> anyhow::Result<()> {
+        dml_interface::insert_mf_l2(&self.pool, mfs).await
+    }
+
+    async fn insert_document_mf(&self, doc_mfs: Vec<DocumentMf>) -> anyhow::Result<()> {
+        dml_interface::insert_document_mf(&self.pool, doc_mfs).await
+    }
+
+    async fn insert_project_l1(&self, projects: Vec<ProjectL1>) -> anyhow::Result<()> {
+        dml_interface::insert_project_l1(&self.pool, projects).await
+    }
+
+    async fn insert_project_l2(&self, projects: Vec<ProjectL2>) -> anyhow::Result<()> {
+        dml_interface::insert_project_l2(&self.pool, projects).await
+    }
+
+    async fn insert_document_project(
+        &self,
+        doc_projects: Vec<DocumentProject>,
+    ) -> anyhow::Result<()> {
+        dml_interface::insert_document_project(&self.pool, doc_projects).await
+    }
+
+    async fn insert_document_aspice(
+        &self,
+        doc_aspices: Vec<DocumentAspiceMapping>,
+    ) -> anyhow::Result<()> {
+        dml_interface::insert_document_aspice(&self.pool, doc_aspices).await
+    }
+
+    async fn insert_document_requirement(
+        &self,
+        doc_requirements: Vec<DocumentRequirement>,
+    ) -> anyhow::Result<()> {
+        dml_interface::insert_document_requirement(&self.pool, doc_requirements).await
+    }
+
+    async fn insert_document_trace_link(
+        &self,
+        trace_links: Vec<DocumentTraceLink>,
+    ) -> anyhow::Result<()> {
+        dml_interface::insert_document_trace_link(&self.pool, trace_links).await
+    }
+
+    async fn update_tag_sort_order(&self, sort_orders: Vec<TagSortOrder>) -> anyhow::Result<()> {
+        dml_interface::update_tag_sort_order(&self.pool, sort_orders).await
+    }
+
+    async fn set_tag_sort_order(
+        &self,
+        dimension: Dimension,
+        level: TagLevel,
+        name: &str,
+        sort_order: i32,
+    ) -> anyhow::Result<u64> {
+        let mut conn = self.pool.acquire().await?;
+        dml_interface::update_tag_sort_order_by_name(&mut conn, dimension, level, name, sort_order).await
+    }
+
+    async fn read_tag_pairs(&self, dimension: Dimension) -> anyhow::Result<Vec<TagPair>> {
+        dml_interface::read_tag_pairs(&self.pool, dimension).await
+    }
+
+    async fn read_document_tags(&self, dimension: Dimension) -> anyhow::Result<Vec<DocumentTag>> {
+        dml_interface::read_document_tags(&self.pool, dimension).await
+    }
+
+    async fn read_documents_by_tag(
+        &self,
+        dimension: Dimension,
+        name: &str,
+    ) -> anyhow::Result<Vec<Document>> {
+        dml_interface::read_documents_by_tag(&self.pool, dimension, name).await
+    }
+
+    async fn read_document(&self, id: Uuid) -> anyhow::Result<Option<Document>> {
+        dml_interface::read_document(&self.pool, id).await
+    }
+
+    async fn read_documents(&self) -> anyhow::Result<Vec<Document>> {
+        dml_interface::read_documents(&self.pool).await
+    }
+
+    async fn read_document_trace_links(&self) -> anyhow::Result<Vec<DocumentTraceLink>> {
+        dml_interface::read_document_trace_links(&self.pool).await
+    }
+
+    async fn read_link_checks(&self) -> anyhow::Result<Vec<LinkCheck>> {
+        dml_interface::read_link_checks(&self.pool).await
+    }
+
+    async fn insert_link_checks(&self, checks: Vec<LinkCheck>) -> anyhow::Result<()> {
+        dml_interface::insert_link_checks(&self.pool, checks).await
+    }
+
+    async fn read_document_modified(&self) -> anyhow::Result<HashMap<Uuid, DateTime<Utc>>> {
+        dml_interface::read_document_modified(&self.pool).await
+    }
+}
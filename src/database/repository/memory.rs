@@ -0,0 +1,619 @@
+//! In-memory tables with the keys and references of the Postgres schema,
+//! so a test fails on the same duplicate or dangling rows the database would reject.
+
+use super::Repository;
+use crate::database::models::*;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+struct Tag {
+    id: Uuid,
+    name: String,
+    // None for L1 tags
+    father_id: Option<Uuid>,
+    sort_order: i32,
+    // set through the API, imports keep it
+    sort_order_manual: bool,
+}
+
+#[derive(Debug, Default)]
+struct Tables {
+    documents: Vec<Document>,
+    tags: HashMap<(Dimension, TagLevel), Vec<Tag>>,
+    // (docid, L2 tag id)
+    document_tags: HashMap<Dimension, Vec<(Uuid, Uuid)>>,
+    document_aspice: Vec<DocumentAspiceMapping>,
+    document_requirement: Vec<DocumentRequirement>,
+    document_trace_link: Vec<DocumentTraceLink>,
+    link_checks: HashMap<Uuid, LinkCheck>,
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryRepository {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryRepository {
+    pub fn new() -> MemoryRepository {
+        MemoryRepository::default()
+    }
+
+    // the map as it was when the snapshot was taken, for the `as_of` queries
+    pub async fn from_snapshot(data: SnapshotData) -> anyhow::Result<MemoryRepository> {
+        let repo = MemoryRepository::new();
+        repo.insert_documents(data.documents).await?;
+        repo.insert_tech_l1(data.tech_l1).await?;
+        repo.insert_tech_l2(data.tech_l2).await?;
+        repo.insert_document_tech(data.document_tech).await?;
+        repo.insert_system_l1(data.system_l1).await?;
+        repo.insert_system_l2(data.system_l2).await?;
+        repo.insert_document_system(data.document_system).await?;
+        repo.insert_mf_l1(data.mf_l1).await?;
+        repo.insert_mf_l2(data.mf_l2).await?;
+        repo.insert_document_mf(data.document_mf).await?;
+        repo.insert_project_l1(data.project_l1).await?;
+        repo.insert_project_l2(data.project_l2).await?;
+        repo.insert_document_project(data.document_project).await?;
+        repo.insert_document_aspice(data.document_aspice).await?;
+        repo.insert_document_requirement(data.document_requirement).await?;
+        repo.insert_document_trace_link(data.document_trace_link).await?;
+        Ok(repo)
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        // a panicking test must not hide the data from the others
+        self.tables
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Tables {
+    fn document(&self, id: Uuid) -> Option<&Document> {
+        self.documents.iter().find(|doc| doc.id == id)
+    }
+
+    fn ensure_document(&self, id: Uuid) -> anyhow::Result<()> {
+        match self.document(id) {
+            Some(_) => Ok(()),
+            None => Err(anyhow!("document {} does not exist", id)),
+        }
+    }
+
+    fn tags(&self, dimension: Dimension, level: TagLevel) -> &[Tag] {
+        self.tags
+            .get(&(dimension, level))
+            .map(|tags| tags.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn tag(&self, dimension: Dimension, level: TagLevel, id: Uuid) -> Option<&Tag> {
+        self.tags(dimension, level).iter().find(|tag| tag.id == id)
+    }
+
+    fn insert_document(&mut self, doc: Document) -> anyhow::Result<()> {
+        if self.document(doc.id).is_some() {
+            return Err(anyhow!("duplicate document id {}", doc.id));
+        }
+        if self.documents.iter().any(|exist| exist.name == doc.name) {
+            return Err(anyhow!("duplicate document name '{}'", doc.name));
+        }
+        self.documents.push(doc);
+        Ok(())
+    }
+
+    fn insert_tag(
+        &mut self,
+        dimension: Dimension,
+        level: TagLevel,
+        tag: Tag,
+    ) -> anyhow::Result<()> {
+        let table = dimension.table(level);
+        if let Some(father_id) = tag.father_id {
+            if self.tag(dimension, TagLevel::L1, father_id).is_none() {
+                return Err(anyhow!(
+                    "{}: father {} of '{}' does not exist",
+                    table,
+                    father_id,
+                    tag.name
+                ));
+            }
+        }
+        let tags = self.tags.entry((dimension, level)).or_default();
+        if tags
+            .iter()
+            .any(|exist| exist.id == tag.id || exist.name == tag.name)
+        {
+            return Err(anyhow!("{}: duplicate tag '{}'", table, tag.name));
+        }
+        tags.push(tag);
+        Ok(())
+    }
+
+    fn insert_document_tag(
+        &mut self,
+        dimension: Dimension,
+        doc_id: Uuid,
+        tag_id: Uuid,
+    ) -> anyhow::Result<()> {
+        let table = dimension.link_table();
+        self.ensure_document(doc_id)?;
+        if self.tag(dimension, TagLevel::L2, tag_id).is_none() {
+            return Err(anyhow!("{}: tag {} does not exist", table, tag_id));
+        }
+        let links = self.document_tags.entry(dimension).or_default();
+        if links.contains(&(doc_id, tag_id)) {
+            return Err(anyhow!(
+                "{}: duplicate link of document {} and tag {}",
+                table,
+                doc_id,
+                tag_id
+            ));
+        }
+        links.push((doc_id, tag_id));
+        Ok(())
+    }
+
+    // (docid, L2 tag) of every link in the dimension
+    fn document_tags(&self, dimension: Dimension) -> Vec<(Uuid, &Tag)> {
+        self.document_tags
+            .get(&dimension)
+            .map(|links| links.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|(doc_id, tag_id)| {
+                self.tag(dimension, TagLevel::L2, *tag_id)
+                    .map(|tag| (*doc_id, tag))
+            })
+            .collect()
+    }
+}
+
+fn read_tags(
+    tables: &Tables,
+    dimension: Dimension,
+    level: TagLevel,
+    tags: &mut HashMap<String, Uuid>,
+) {
+    for tag in tables.tags(dimension, level) {
+        tags.insert(tag.name.clone(), tag.id);
+    }
+}
+
+#[async_trait]
+impl Repository for MemoryRepository {
+    async fn read_exist_documents(&self, docs: &mut HashMap<String, Uuid>) -> anyhow::Result<()> {
+        for doc in &self.tables().documents {
+            docs.insert(doc.name.clone(), doc.id);
+        }
+        Ok(())
+    }
+
+    async fn read_exist_tags(
+        &self,
+        dimension: Dimension,
+        level: TagLevel,
+        tags: &mut HashMap<String, Uuid>,
+    ) -> anyhow::Result<()> {
+        read_tags(&self.tables(), dimension, level, tags);
+        Ok(())
+    }
+
+    async fn read_exist_document_tags(
+        &self,
+        dimension: Dimension,
+        doc_tags: &mut HashMap<Uuid, HashSet<String>>,
+    ) -> anyhow::Result<()> {
+        for (doc_id, tag) in self.tables().document_tags(dimension) {
+            doc_tags.entry(doc_id).or_default().insert(tag.name.clone());
+        }
+        Ok(())
+    }
+
+    async fn read_exist_document_aspice(
+        &self,
+        doc_aspices: &mut HashMap<Uuid, HashSet<Aspice>>,
+    ) -> anyhow::Result<()> {
+        for doc_aspice in &self.tables().document_aspice {
+            doc_aspices
+                .entry(doc_aspice.docid)
+                .or_default()
+                .insert(doc_aspice.aspice_step.clone());
+        }
+        Ok(())
+    }
+
+    async fn read_exist_document_requirement(
+        &self,
+        doc_requirements: &mut HashMap<Uuid, HashSet<String>>,
+    ) -> anyhow::Result<()> {
+        let tables = self.tables();
+        for doc_requirement in &tables.document_requirement {
+            if let Some(requirement) = tables.document(doc_requirement.requirement_id) {
+                doc_requirements
+                    .entry(doc_requirement.doc_id)
+                    .or_default()
+                    .insert(requirement.name.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn read_exist_document_trace_link(
+        &self,
+        trace_links: &mut HashMap<Uuid, HashSet<Uuid>>,
+    ) -> anyhow::Result<()> {
+        for trace_link in &self.tables().document_trace_link {
+            trace_links
+                .entry(trace_link.source_docid)
+                .or_default()
+                .insert(trace_link.target_docid);
+        }
+        Ok(())
+    }
+
+    async fn insert_documents(&self, docs: Vec<Document>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for doc in docs {
+            tables.insert_document(doc)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_tech_l1(&self, techs: Vec<TechL1>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for tech in techs {
+            let tag = Tag {
+                id: tech.id,
+                name: tech.name,
+                father_id: None,
+                sort_order: tech.sort_order,
+                sort_order_manual: false,
+            };
+            tables.insert_tag(Dimension::Tech, TagLevel::L1, tag)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_tech_l2(&self, techs: Vec<TechL2>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for tech in techs {
+            let tag = Tag {
+                id: tech.id,
+                name: tech.name,
+                father_id: Some(tech.father_id),
+                sort_order: tech.sort_order,
+                sort_order_manual: false,
+            };
+            tables.insert_tag(Dimension::Tech, TagLevel::L2, tag)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_document_tech(&self, doc_techs: Vec<DocumentTech>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for doc_tech in doc_techs {
+            tables.insert_document_tag(Dimension::Tech, doc_tech.doc_id, doc_tech.tech_id)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_system_l1(&self, systems: Vec<SystemL1>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for system in systems {
+            let tag = Tag {
+                id: system.id,
+                name: system.name,
+                father_id: None,
+                sort_order: system.sort_order,
+                sort_order_manual: false,
+            };
+            tables.insert_tag(Dimension::System, TagLevel::L1, tag)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_system_l2(&self, systems: Vec<SystemL2>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for system in systems {
+            let tag = Tag {
+                id: system.id,
+                name: system.name,
+                father_id: Some(system.father_id),
+                sort_order: system.sort_order,
+                sort_order_manual: false,
+            };
+            tables.insert_tag(Dimension::System, TagLevel::L2, tag)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_document_system(&self, doc_systems: Vec<DocumentSystem>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for doc_system in doc_systems {
+            tables.insert_document_tag(Dimension::System, doc_system.doc_id, doc_system.sys_id)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_mf_l1(&self, mfs: Vec<MfL1>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for mf in mfs {
+            let tag = Tag {
+                id: mf.id,
+                name: mf.name,
+                father_id: None,
+                sort_order: mf.sort_order,
+                sort_order_manual: false,
+            };
+            tables.insert_tag(Dimension::Mf, TagLevel::L1, tag)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_mf_l2(&self, mfs: Vec<MfL2>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for mf in mfs {
+            let tag = Tag {
+                id: mf.id,
+                name: mf.name,
+                father_id: Some(mf.father_id),
+                sort_order: mf.sort_order,
+                sort_order_manual: false,
+            };
+            tables.insert_tag(Dimension::Mf, TagLevel::L2, tag)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_document_mf(&self, doc_mfs: Vec<DocumentMf>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for doc_mf in doc_mfs {
+            tables.insert_document_tag(Dimension::Mf, doc_mf.doc_id, doc_mf.mf_id)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_project_l1(&self, projects: Vec<ProjectL1>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for project in projects {
+            let tag = Tag {
+                id: project.id,
+                name: project.name,
+                father_id: None,
+                sort_order: project.sort_order,
+                sort_order_manual: false,
+            };
+            tables.insert_tag(Dimension::Project, TagLevel::L1, tag)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_project_l2(&self, projects: Vec<ProjectL2>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for project in projects {
+            let tag = Tag {
+                id: project.id,
+                name: project.name,
+                father_id: Some(project.father_id),
+                sort_order: project.sort_order,
+                sort_order_manual: false,
+            };
+            tables.insert_tag(Dimension::Project, TagLevel::L2, tag)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_document_project(
+        &self,
+        doc_projects: Vec<DocumentProject>,
+    ) -> anyhow::Result<()> {
+        // duplicates are dropped like `dml_interface::insert_document_project` does
+        let mut doc_projects = doc_projects;
+        doc_projects.sort();
+        doc_projects.dedup();
+
+        let mut tables = self.tables();
+        for doc_project in doc_projects {
+            tables.insert_document_tag(
+                Dimension::Project,
+                doc_project.doc_id,
+                doc_project.project_id,
+            )?;
+        }
+        Ok(())
+    }
+
+    async fn insert_document_aspice(
+        &self,
+        doc_aspices: Vec<DocumentAspiceMapping>,
+    ) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for doc_aspice in doc_aspices {
+            tables.ensure_document(doc_aspice.docid)?;
+            let duplicate = tables.document_aspice.iter().any(|exist| {
+                exist.docid == doc_aspice.docid && exist.aspice_step == doc_aspice.aspice_step
+            });
+            if duplicate {
+                return Err(anyhow!(
+                    "duplicate aspice step {} of document {}",
+                    doc_aspice.aspice_step.as_str(),
+                    doc_aspice.docid
+                ));
+            }
+            tables.document_aspice.push(doc_aspice);
+        }
+        Ok(())
+    }
+
+    async fn insert_document_requirement(
+        &self,
+        doc_requirements: Vec<DocumentRequirement>,
+    ) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for doc_requirement in doc_requirements {
+            tables.ensure_document(doc_requirement.doc_id)?;
+            tables.ensure_document(doc_requirement.requirement_id)?;
+            let duplicate = tables.document_requirement.iter().any(|exist| {
+                exist.doc_id == doc_requirement.doc_id
+                    && exist.requirement_id == doc_requirement.requirement_id
+            });
+            if duplicate {
+                return Err(anyhow!(
+                    "duplicate requirement {} of document {}",
+                    doc_requirement.requirement_id,
+                    doc_requirement.doc_id
+                ));
+            }
+            tables.document_requirement.push(doc_requirement);
+        }
+        Ok(())
+    }
+
+    async fn insert_document_trace_link(
+        &self,
+        trace_links: Vec<DocumentTraceLink>,
+    ) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for trace_link in trace_links {
+            tables.ensure_document(trace_link.source_docid)?;
+            tables.ensure_document(trace_link.target_docid)?;
+            let duplicate = tables.document_trace_link.iter().any(|exist| {
+                exist.source_docid == trace_link.source_docid
+                    && exist.target_docid == trace_link.target_docid
+            });
+            if duplicate {
+                return Err(anyhow!(
+                    "duplicate trace link from {} to {}",
+                    trace_link.source_docid,
+                    trace_link.target_docid
+                ));
+            }
+            tables.document_trace_link.push(trace_link);
+        }
+        Ok(())
+    }
+
+    async fn update_tag_sort_order(&self, sort_orders: Vec<TagSortOrder>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for sort_order in sort_orders {
+            // like an UPDATE, an unknown id changes nothing
+            if let Some(tags) = tables
+                .tags
+                .get_mut(&(sort_order.dimension, sort_order.level))
+            {
+                for tag in tags
+                    .iter_mut()
+                    .filter(|tag| tag.id == sort_order.id && !tag.sort_order_manual)
+                {
+                    tag.sort_order = sort_order.sort_order;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn set_tag_sort_order(
+        &self,
+        dimension: Dimension,
+        level: TagLevel,
+        name: &str,
+        sort_order: i32,
+    ) -> anyhow::Result<u64> {
+        let mut tables = self.tables();
+        let mut rows = 0;
+        if let Some(tags) = tables.tags.get_mut(&(dimension, level)) {
+            for tag in tags.iter_mut().filter(|tag| tag.name == name) {
+                tag.sort_order = sort_order;
+                tag.sort_order_manual = true;
+                rows += 1;
+            }
+        }
+        Ok(rows)
+    }
+
+    async fn read_tag_pairs(&self, dimension: Dimension) -> anyhow::Result<Vec<TagPair>> {
+        let tables = self.tables();
+        let pairs = tables
+            .tags(dimension, TagLevel::L2)
+            .iter()
+            .filter_map(|l2| {
+                let l1 = tables.tag(dimension, TagLevel::L1, l2.father_id?)?;
+                Some(TagPair {
+                    l1_name: l1.name.clone(),
+                    l2_name: l2.name.clone(),
+                    l1_order: l1.sort_order,
+                    l2_order: l2.sort_order,
+                })
+            })
+            .collect();
+        Ok(pairs)
+    }
+
+    async fn read_document_tags(&self, dimension: Dimension) -> anyhow::Result<Vec<DocumentTag>> {
+        let tags = self
+            .tables()
+            .document_tags(dimension)
+            .into_iter()
+            .map(|(docid, tag)| DocumentTag {
+                docid,
+                name: tag.name.clone(),
+                sort_order: tag.sort_order,
+            })
+            .collect();
+        Ok(tags)
+    }
+
+    async fn read_documents_by_tag(
+        &self,
+        dimension: Dimension,
+        name: &str,
+    ) -> anyhow::Result<Vec<Document>> {
+        let tables = self.tables();
+        let mut docs: Vec<Document> = tables
+            .document_tags(dimension)
+            .into_iter()
+            .filter(|(_, tag)| tag.name == name)
+            .filter_map(|(doc_id, _)| tables.document(doc_id).cloned())
+            .collect();
+        docs.sort_by_key(|doc| doc.id);
+        Ok(docs)
+    }
+
+    async fn read_document(&self, id: Uuid) -> anyhow::Result<Option<Document>> {
+        Ok(self.tables().document(id).cloned())
+    }
+
+    async fn read_documents(&self) -> anyhow::Result<Vec<Document>> {
+        let mut docs = self.tables().documents.clone();
+        docs.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(docs)
+    }
+
+    async fn read_document_trace_links(&self) -> anyhow::Result<Vec<DocumentTraceLink>> {
+        Ok(self.tables().document_trace_link.clone())
+    }
+
+    async fn read_link_checks(&self) -> anyhow::Result<Vec<LinkCheck>> {
+        Ok(self.tables().link_checks.values().cloned().collect())
+    }
+
+    async fn insert_link_checks(&self, checks: Vec<LinkCheck>) -> anyhow::Result<()> {
+        let mut tables = self.tables();
+        for check in checks {
+            tables.ensure_document(check.docid)?;
+            tables.link_checks.insert(check.docid, check);
+        }
+        Ok(())
+    }
+
+    // there is no audit log in memory, documents sort as never changed
+    async fn read_document_modified(&self) -> anyhow::Result<HashMap<Uuid, DateTime<Utc>>> {
+        Ok(HashMap::new())
+    }
+}
@@ -10,8 +10,9 @@ use std::error::Error;
 use std::path;
 use tokio::io::AsyncWriteExt;
 use tokio::fs::File;
-use log::{info, warn, error};
+use tracing::{debug, error, info, instrument};
 
+#[instrument(name = "extract")]
 pub async fn get_feishu_data() -> Result<std::path::PathBuf, Box<dyn Error>> {
     let mut cfg = Config::new();
 
@@ -25,6 +26,7 @@ pub async fn get_feishu_data() -> Result<std::path::PathBuf, Box<dyn Error>> {
     Ok(path)
 }
 
+#[instrument(name = "token", skip_all)]
 async fn get_tenant_access_token(cfg: &mut Config) -> Result<(), Box<dyn Error>> {
     let client = reqwest::Client::new();
     let mut headers = reqwest::header::HeaderMap::new();
@@ -50,6 +52,7 @@ async fn get_tenant_access_token(cfg: &mut Config) -> Result<(), Box<dyn Error>>
     Ok(())
 }
 
+#[instrument(name = "export_task", skip_all)]
 async fn create_export_task(cfg: &mut Config) -> Result<(), Box<dyn Error>> {
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
@@ -89,6 +92,7 @@ enum JobStatus {
     _OtherStatue = 3,
 }
 
+#[instrument(name = "poll", skip_all)]
 async fn poll_export_task_result(cfg: &mut Config) -> Result<(), Box<dyn Error>> {
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
@@ -101,7 +105,7 @@ async fn poll_export_task_result(cfg: &mut Config) -> Result<(), Box<dyn Error>>
         .query_task_result_url
         .replace(":ticket", &cfg.export_task_ticket.as_ref().unwrap());
     let params = [("token", &cfg.doc["token"])];
-    dbg!(&url);
+    debug!(%url, "polling export task");
 
     let client = reqwest::Client::new();
 
@@ -122,15 +126,14 @@ async fn poll_export_task_result(cfg: &mut Config) -> Result<(), Box<dyn Error>>
         {
             break;
         } else {
-            println!("Task is not ready, wait 1s...");
-            dbg!(&parsed);
+            info!(job_status = %parsed["data"]["result"]["job_status"], "task is not ready, wait 2s...");
+            debug!(response = %parsed);
             std::thread::sleep(std::time::Duration::from_secs(2));
         }
     }
 
     if parsed["data"]["result"]["job_status"] != JobStatus::Ready as i32 {
-        dbg!(&parsed);
-        error!("Feishu export task is error");
+        error!(response = %parsed, "Feishu export task is error");
         return Err("Job status error".into());
     }
 
@@ -151,6 +154,7 @@ async fn poll_export_task_result(cfg: &mut Config) -> Result<(), Box<dyn Error>>
     Ok(())
 }
 
+#[instrument(name = "download", skip_all)]
 async fn download_file(cfg: &mut Config) -> Result<(), Box<dyn Error>> {
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
@@ -174,6 +178,7 @@ async fn download_file(cfg: &mut Config) -> Result<(), Box<dyn Error>> {
     path.push(format!("{}.{}", cfg.file_name.as_ref().unwrap(), cfg.doc["file_extension"]));
     let mut file = File::create(&path).await?;
     file.write_all(&bytes).await?;
+    info!(path = %path.display(), size = bytes.len(), "downloaded exported file");
 
     Ok(())
 }
\ No newline at end of file
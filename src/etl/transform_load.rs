@@ -1,13 +1,14 @@
-use crate::database::dml_interface;
+use crate::database::repository::Repository;
 use crate::database::models::*;
 /// Transform data from excel to rust internal data structure.
 /// And, load data to database.
-use anyhow::Context;
+use anyhow::anyhow;
 use calamine::{Reader, Xlsx};
 use sqlx::any;
 use std::collections::{HashMap, HashSet};
 use std::io::{Read, Seek};
 use std::path::Path;
+use tracing::{info, instrument};
 use uuid::Uuid;
 
 pub struct InternalData {
@@ -25,6 +26,10 @@ pub struct InternalData {
     pub project_l2: Vec<ProjectL2>,
     pub document_project: Vec<DocumentProject>,
     pub document_aspice: Vec<DocumentAspiceMapping>,
+    pub document_requirement: Vec<DocumentRequirement>,
+    pub document_trace_link: Vec<DocumentTraceLink>,
+    // sort order of tags already in database
+    pub tag_sort_order: Vec<TagSortOrder>,
 
     pub exist_documents: HashMap<String, Uuid>,
     pub exist_tech_l1: HashMap<String, Uuid>,
@@ -40,6 +45,8 @@ pub struct InternalData {
     pub exist_document_mf: HashMap<Uuid, HashSet<String>>,
     pub exist_document_project: HashMap<Uuid, HashSet<String>>,
     pub exist_document_aspice: HashMap<Uuid, HashSet<Aspice>>,
+    pub exist_document_requirement: HashMap<Uuid, HashSet<String>>,
+    pub exist_document_trace_link: HashMap<Uuid, HashSet<Uuid>>,
 }
 
 impl InternalData {
@@ -59,6 +66,9 @@ impl InternalData {
             project_l2: Vec::new(),
             document_project: Vec::new(),
             document_aspice: Vec::new(),
+            document_requirement: Vec::new(),
+            document_trace_link: Vec::new(),
+            tag_sort_order: Vec::new(),
 
             exist_documents: HashMap::new(),
             exist_tech_l1: HashMap::new(),
@@ -75,39 +85,43 @@ impl InternalData {
             exist_document_mf: HashMap::new(),
             exist_document_project: HashMap::new(),
             exist_document_aspice: HashMap::new(),
+            exist_document_requirement: HashMap::new(),
+            exist_document_trace_link: HashMap::new(),
         }
     }
 
-    pub async fn import_and_load(&mut self, path: &Path) -> anyhow::Result<()> {
-        let pool = dml_interface::get_db_pool().await?;
-
-        self.read_exist_data(&pool).await?;
+    #[instrument(name = "import_and_load", skip_all, fields(path = %path.display()))]
+    pub async fn import_and_load<R: Repository + ?Sized>(&mut self, repo: &R, path: &Path) -> anyhow::Result<()> {
+        self.read_exist_data(repo).await?;
         self.import_from_excel(path)?;
-        self.load_to_database(&pool).await?;
+        self.load_to_database(repo).await?;
 
-        pool.close().await;
         Ok(())
     }
 
-    pub async fn read_exist_data(&mut self, pool: &sqlx::PgPool) -> anyhow::Result<()> {
-        dml_interface::read_exist_documents(&pool, &mut self.exist_documents).await?;
-        dml_interface::read_exist_tech_l1(&pool, &mut self.exist_tech_l1).await?;
-        dml_interface::read_exist_tech_l2(&pool, &mut self.exist_tech_l2).await?;
-        dml_interface::read_exist_system_l1(&pool, &mut self.exist_system_l1).await?;
-        dml_interface::read_exist_system_l2(&pool, &mut self.exist_system_l2).await?;
-        dml_interface::read_exist_mf_l1(&pool, &mut self.exist_mf_l1).await?;
-        dml_interface::read_exist_mf_l2(&pool, &mut self.exist_mf_l2).await?;
-        dml_interface::read_exist_project_l1(&pool, &mut self.exist_project_l1).await?;
-        dml_interface::read_exist_project_l2(&pool, &mut self.exist_project_l2).await?;
-
-        dml_interface::read_exist_document_tech(&pool, &mut self.exist_document_tech).await?;
-        dml_interface::read_exist_document_system(&pool, &mut self.exist_document_system).await?;
-        dml_interface::read_exist_document_mf(&pool, &mut self.exist_document_mf).await?;
-        dml_interface::read_exist_document_project(&pool, &mut self.exist_document_project).await?;
-        dml_interface::read_exist_document_aspice(&pool, &mut self.exist_document_aspice).await?;
+    #[instrument(name = "read_existing", skip_all)]
+    pub async fn read_exist_data<R: Repository + ?Sized>(&mut self, repo: &R) -> anyhow::Result<()> {
+        repo.read_exist_documents(&mut self.exist_documents).await?;
+        repo.read_exist_tags(Dimension::Tech, TagLevel::L1, &mut self.exist_tech_l1).await?;
+        repo.read_exist_tags(Dimension::Tech, TagLevel::L2, &mut self.exist_tech_l2).await?;
+        repo.read_exist_tags(Dimension::System, TagLevel::L1, &mut self.exist_system_l1).await?;
+        repo.read_exist_tags(Dimension::System, TagLevel::L2, &mut self.exist_system_l2).await?;
+        repo.read_exist_tags(Dimension::Mf, TagLevel::L1, &mut self.exist_mf_l1).await?;
+        repo.read_exist_tags(Dimension::Mf, TagLevel::L2, &mut self.exist_mf_l2).await?;
+        repo.read_exist_tags(Dimension::Project, TagLevel::L1, &mut self.exist_project_l1).await?;
+        repo.read_exist_tags(Dimension::Project, TagLevel::L2, &mut self.exist_project_l2).await?;
+
+        repo.read_exist_document_tags(Dimension::Tech, &mut self.exist_document_tech).await?;
+        repo.read_exist_document_tags(Dimension::System, &mut self.exist_document_system).await?;
+        repo.read_exist_document_tags(Dimension::Mf, &mut self.exist_document_mf).await?;
+        repo.read_exist_document_tags(Dimension::Project, &mut self.exist_document_project).await?;
+        repo.read_exist_document_aspice(&mut self.exist_document_aspice).await?;
+        repo.read_exist_document_requirement(&mut self.exist_document_requirement).await?;
+        repo.read_exist_document_trace_link(&mut self.exist_document_trace_link).await?;
         Ok(())
     }
 
+    #[instrument(name = "parse", skip_all)]
     pub fn import_from_excel(&mut self, path: &Path) -> anyhow::Result<()> {
         let mut excel: Xlsx<_> = calamine::open_workbook(path)?;
         self.import_tech_tags(&mut excel)?;
@@ -117,27 +131,37 @@ impl InternalData {
 
         // must after import all tags
         self.import_documents(&mut excel)?;
+        info!(
+            documents = self.documents.len(),
+            trace_links = self.document_trace_link.len(),
+            "parsed workbook"
+        );
         Ok(())
     }
 
-    pub async fn load_to_database(&self, pool: &sqlx::PgPool) -> anyhow::Result<()> {
+    #[instrument(name = "load", skip_all)]
+    pub async fn load_to_database<R: Repository + ?Sized>(&self, repo: &R) -> anyhow::Result<()> {
         // TODO: use transaction
 
         // insert tags
-        dml_interface::insert_tech_l1(&pool, self.tech_l1.clone()).await?;
-        dml_interface::insert_tech_l2(&pool, self.tech_l2.clone()).await?;
-        dml_interface::insert_system_l1(&pool, self.system_l1.clone()).await?;
-        dml_interface::insert_system_l2(&pool, self.system_l2.clone()).await?;
-        dml_interface::insert_mf_l1(&pool, self.mf_l1.clone()).await?;
-        dml_interface::insert_mf_l2(&pool, self.mf_l2.clone()).await?;
-        dml_interface::insert_project_l1(&pool, self.project_l1.clone()).await?;
-        dml_interface::insert_project_l2(&pool, self.project_l2.clone()).await?;
-        dml_interface::insert_documents(&pool, self.documents.clone()).await?;
-        dml_interface::insert_document_tech(&pool, self.document_tech.clone()).await?;
-        dml_interface::insert_document_system(&pool, self.document_system.clone()).await?;
-        dml_interface::insert_document_mf(&pool, self.document_mf.clone()).await?;
-        dml_interface::insert_document_project(&pool, self.document_project.clone()).await?;
-        dml_interface::insert_document_aspice(&pool, self.document_aspice.clone()).await?;
+        repo.insert_tech_l1(self.tech_l1.clone()).await?;
+        repo.insert_tech_l2(self.tech_l2.clone()).await?;
+        repo.insert_system_l1(self.system_l1.clone()).await?;
+        repo.insert_system_l2(self.system_l2.clone()).await?;
+        repo.insert_mf_l1(self.mf_l1.clone()).await?;
+        repo.insert_mf_l2(self.mf_l2.clone()).await?;
+        repo.insert_project_l1(self.project_l1.clone()).await?;
+        repo.insert_project_l2(self.project_l2.clone()).await?;
+        repo.update_tag_sort_order(self.tag_sort_order.clone()).await?;
+        repo.insert_documents(self.documents.clone()).await?;
+        repo.insert_document_tech(self.document_tech.clone()).await?;
+        repo.insert_document_system(self.document_system.clone()).await?;
+        repo.insert_document_mf(self.document_mf.clone()).await?;
+        repo.insert_document_project(self.document_project.clone()).await?;
+        repo.insert_document_aspice(self.document_aspice.clone()).await?;
+        repo.insert_document_requirement(self.document_requirement.clone()).await?;
+        repo.insert_document_trace_link(self.document_trace_link.clone()).await?;
+        info!("loaded workbook into the database");
         Ok(())
     }
 
@@ -146,7 +170,10 @@ impl InternalData {
 
         if let Some(Ok(range)) = excel.worksheet_range(sheet_name) {
             let mut current_tl1 = None;
-            for row in range.rows().skip(1) {
+            let mut ordered = HashSet::new();
+            for (row_num, row) in range.rows().skip(1).enumerate() {
+                // tags are ordered as the rows in the sheet
+                let sort_order = row_num as i32 + 1;
                 // TODO: remove magic number
                 let l1_cell = row.get(1).and_then(|cell| cell.get_string());
                 let l2_cell = row.get(2).and_then(|cell| cell.get_string());
@@ -154,24 +181,42 @@ impl InternalData {
                 if let Some(tl1) = l1_cell {
                     current_tl1 = Some(tl1);
                     if self.exist_tech_l1.contains_key(tl1) == false {
-                        let tag = TechL1::new(tl1.to_string());
+                        let tag = TechL1::new(tl1.to_string(), sort_order);
+                        ordered.insert((TagLevel::L1, tag.id));
                         self.exist_tech_l1.insert(tag.name.clone(), tag.id);
                         self.tech_l1.push(tag);
+                    } else if ordered.insert((TagLevel::L1, self.exist_tech_l1[tl1])) {
+                        self.tag_sort_order.push(TagSortOrder::new(
+                            Dimension::Tech,
+                            TagLevel::L1,
+                            self.exist_tech_l1[tl1],
+                            sort_order,
+                        ));
                     }
                 }
 
                 if let Some(tl2) = l2_cell {
-                    if self.exist_tech_l2.contains_key(tl2) {
+                    if let Some(id) = self.exist_tech_l2.get(tl2) {
+                        if ordered.insert((TagLevel::L2, *id)) {
+                            self.tag_sort_order.push(TagSortOrder::new(
+                                Dimension::Tech,
+                                TagLevel::L2,
+                                *id,
+                                sort_order,
+                            ));
+                        }
                         continue;
                     }
 
                     let tag = TechL2::new(
                         tl2.to_string(),
-                        self.exist_tech_l1
-                            .get(current_tl1.unwrap())
-                            .unwrap()
-                            .to_owned(),
+                        current_tl1
+                            .and_then(|l1| self.exist_tech_l1.get(l1))
+                            .copied()
+                            .ok_or_else(|| anyhow!("tech tag '{}' has no L1 tag in {}", tl2, sheet_name))?,
+                        sort_order,
                     );
+                    ordered.insert((TagLevel::L2, tag.id));
                     self.exist_tech_l2.insert(tag.name.clone(), tag.id);
                     self.tech_l2.push(tag);
                 }
@@ -186,16 +231,26 @@ impl InternalData {
 
         let mut current_sl1 = None;
         if let Some(Ok(range)) = excel.worksheet_range(sheet_name) {
-            for row in range.rows().skip(1) {
+            let mut ordered = HashSet::new();
+            for (row_num, row) in range.rows().skip(1).enumerate() {
+                let sort_order = row_num as i32 + 1;
                 let l1_cell = row.get(1).and_then(|cell| cell.get_string());
                 let l2_cell = row.get(2).and_then(|cell| cell.get_string());
 
                 if let Some(sl1) = l1_cell {
                     current_sl1 = Some(sl1);
                     if self.exist_system_l1.contains_key(sl1) == false {
-                        let tag = SystemL1::new(sl1.to_string());
+                        let tag = SystemL1::new(sl1.to_string(), sort_order);
+                        ordered.insert((TagLevel::L1, tag.id));
                         self.exist_system_l1.insert(tag.name.clone(), tag.id);
                         self.system_l1.push(tag);
+                    } else if ordered.insert((TagLevel::L1, self.exist_system_l1[sl1])) {
+                        self.tag_sort_order.push(TagSortOrder::new(
+                            Dimension::System,
+                            TagLevel::L1,
+                            self.exist_system_l1[sl1],
+                            sort_order,
+                        ));
                     }
                 }
 
@@ -203,13 +258,22 @@ impl InternalData {
                     if self.exist_system_l2.contains_key(sl2) == false {
                         let tag = SystemL2::new(
                             sl2.to_string(),
-                            self.exist_system_l1
-                                .get(current_sl1.unwrap())
-                                .unwrap()
-                                .to_owned(),
+                            current_sl1
+                                .and_then(|l1| self.exist_system_l1.get(l1))
+                                .copied()
+                                .ok_or_else(|| anyhow!("system tag '{}' has no L1 tag in {}", sl2, sheet_name))?,
+                            sort_order,
                         );
+                        ordered.insert((TagLevel::L2, tag.id));
                         self.exist_system_l2.insert(tag.name.clone(), tag.id);
                         self.system_l2.push(tag);
+                    } else if ordered.insert((TagLevel::L2, self.exist_system_l2[sl2])) {
+                        self.tag_sort_order.push(TagSortOrder::new(
+                            Dimension::System,
+                            TagLevel::L2,
+                            self.exist_system_l2[sl2],
+                            sort_order,
+                        ));
                     }
                 }
             }
@@ -223,16 +287,26 @@ impl InternalData {
 
         if let Some(Ok(range)) = excel.worksheet_range(sheet_name) {
             let mut current_mfl1 = None;
-            for row in range.rows().skip(1) {
+            let mut ordered = HashSet::new();
+            for (row_num, row) in range.rows().skip(1).enumerate() {
+                let sort_order = row_num as i32 + 1;
                 let l1_cell = row.get(1).and_then(|cell| cell.get_string());
                 let l2_cell = row.get(2).and_then(|cell| cell.get_string());
 
                 if let Some(mfl1) = l1_cell {
                     current_mfl1 = Some(mfl1);
                     if self.exist_mf_l1.contains_key(mfl1) == false {
-                        let tag = MfL1::new(mfl1.to_string());
+                        let tag = MfL1::new(mfl1.to_string(), sort_order);
+                        ordered.insert((TagLevel::L1, tag.id));
                         self.exist_mf_l1.insert(tag.name.clone(), tag.id);
                         self.mf_l1.push(tag);
+                    } else if ordered.insert((TagLevel::L1, self.exist_mf_l1[mfl1])) {
+                        self.tag_sort_order.push(TagSortOrder::new(
+                            Dimension::Mf,
+                            TagLevel::L1,
+                            self.exist_mf_l1[mfl1],
+                            sort_order,
+                        ));
                     }
                 }
 
@@ -240,13 +314,22 @@ impl InternalData {
                     if self.exist_mf_l2.contains_key(mfl2) == false {
                         let tag = MfL2::new(
                             mfl2.to_string(),
-                            self.exist_mf_l1
-                                .get(current_mfl1.unwrap())
-                                .unwrap()
-                                .to_owned(),
+                            current_mfl1
+                                .and_then(|l1| self.exist_mf_l1.get(l1))
+                                .copied()
+                                .ok_or_else(|| anyhow!("mf tag '{}' has no L1 tag in {}", mfl2, sheet_name))?,
+                            sort_order,
                         );
+                        ordered.insert((TagLevel::L2, tag.id));
                         self.exist_mf_l2.insert(tag.name.clone(), tag.id);
                         self.mf_l2.push(tag);
+                    } else if ordered.insert((TagLevel::L2, self.exist_mf_l2[mfl2])) {
+                        self.tag_sort_order.push(TagSortOrder::new(
+                            Dimension::Mf,
+                            TagLevel::L2,
+                            self.exist_mf_l2[mfl2],
+                            sort_order,
+                        ));
                     }
                 }
             }
@@ -260,16 +343,26 @@ impl InternalData {
 
         if let Some(Ok(range)) = excel.worksheet_range(sheet_name) {
             let mut current_pl1 = None;
-            for row in range.rows().skip(1) {
+            let mut ordered = HashSet::new();
+            for (row_num, row) in range.rows().skip(1).enumerate() {
+                let sort_order = row_num as i32 + 1;
                 let l1_cell = row.get(1).and_then(|cell| cell.get_string());
                 let l2_cell = row.get(2).and_then(|cell| cell.get_string());
 
                 if let Some(pl1) = l1_cell {
                     current_pl1 = Some(pl1);
                     if self.exist_project_l1.contains_key(pl1) == false {
-                        let tag = ProjectL1::new(pl1.to_string());
+                        let tag = ProjectL1::new(pl1.to_string(), sort_order);
+                        ordered.insert((TagLevel::L1, tag.id));
                         self.exist_project_l1.insert(tag.name.clone(), tag.id);
                         self.project_l1.push(tag);
+                    } else if ordered.insert((TagLevel::L1, self.exist_project_l1[pl1])) {
+                        self.tag_sort_order.push(TagSortOrder::new(
+                            Dimension::Project,
+                            TagLevel::L1,
+                            self.exist_project_l1[pl1],
+                            sort_order,
+                        ));
                     }
                 }
 
@@ -277,13 +370,22 @@ impl InternalData {
                     if self.exist_project_l2.contains_key(pl2) == false {
                         let tag = ProjectL2::new(
                             pl2.to_string(),
-                            self.exist_project_l1
-                                .get(current_pl1.unwrap())
-                                .unwrap()
-                                .to_owned(),
+                            current_pl1
+                                .and_then(|l1| self.exist_project_l1.get(l1))
+                                .copied()
+                                .ok_or_else(|| anyhow!("project tag '{}' has no L1 tag in {}", pl2, sheet_name))?,
+                            sort_order,
                         );
+                        ordered.insert((TagLevel::L2, tag.id));
                         self.exist_project_l2.insert(tag.name.clone(), tag.id);
                         self.project_l2.push(tag);
+                    } else if ordered.insert((TagLevel::L2, self.exist_project_l2[pl2])) {
+                        self.tag_sort_order.push(TagSortOrder::new(
+                            Dimension::Project,
+                            TagLevel::L2,
+                            self.exist_project_l2[pl2],
+                            sort_order,
+                        ));
                     }
                 }
             }
@@ -297,10 +399,11 @@ impl InternalData {
 
         if let Some(Ok(range)) = excel.worksheet_range(sheet_name) {
             for row in range.rows().skip(1) {
-                let name = row.get(0).and_then(|cell| cell.get_string());
-                if let Some("") = name {
-                    continue;
-                }
+                // rows without a document name are skipped
+                let name = match row.first().and_then(|cell| cell.get_string()) {
+                    Some(name) if !name.is_empty() => name,
+                    _ => continue,
+                };
 
                 // get hyperlink
                 let link = row.get(11).and_then(|cell| cell.get_string());
@@ -314,6 +417,7 @@ impl InternalData {
                             .trim()
                             .split(",")
                             .map(|s| s.trim())
+                            .filter(|s| !s.is_empty())
                             .collect::<Vec<&str>>()
                     })
                 });
@@ -324,6 +428,7 @@ impl InternalData {
                             .trim()
                             .split(",")
                             .map(|s| s.trim())
+                            .filter(|s| !s.is_empty())
                             .collect::<Vec<&str>>()
                     })
                 });
@@ -334,6 +439,7 @@ impl InternalData {
                             .trim()
                             .split(",")
                             .map(|s| s.trim())
+                            .filter(|s| !s.is_empty())
                             .collect::<Vec<&str>>()
                     })
                 });
@@ -346,6 +452,7 @@ impl InternalData {
                             .trim()
                             .split(",")
                             .map(|s| s.trim())
+                            .filter(|s| !s.is_empty())
                             .collect::<Vec<&str>>()
                     })
                 });
@@ -356,92 +463,141 @@ impl InternalData {
                             .trim()
                             .split(",")
                             .map(|s| s.trim())
+                            .filter(|s| !s.is_empty())
                             .collect::<Vec<&str>>()
                     })
                 });
 
-                let mut current_doc_id = None;
-                if let Some(name) = name {
-                    // FIXME: fix link
-                    if self.exist_documents.contains_key(name) == false {
-                        let doc = Document::new(
-                            name.to_string(),
-                            link.unwrap().to_string(),
-                            description.map(|s| s.to_string()),
-                        );
-                        current_doc_id = Some(doc.id);
-                        self.exist_documents.insert(doc.name.clone(), doc.id);
-                        self.documents.push(doc);
-                    } else {
-                        current_doc_id = Some(self.exist_documents.get(name).unwrap().to_owned());
-                    }
-                }
+                // FIXME: fix link
+                let current_doc_id = if !self.exist_documents.contains_key(name) {
+                    let doc = Document::new(
+                        name.to_string(),
+                        link.unwrap_or_default().to_string(),
+                        description.map(|s| s.to_string()),
+                    );
+                    let id = doc.id;
+                    self.exist_documents.insert(doc.name.clone(), doc.id);
+                    self.documents.push(doc);
+                    id
+                } else {
+                    self.exist_documents[name]
+                };
 
                 if let Some(techl2_vec) = techl2_vec {
-                    let exist_tag_set = self.exist_document_tech.entry(current_doc_id.unwrap()).or_insert(HashSet::new());
+                    let exist_tag_set = self.exist_document_tech.entry(current_doc_id).or_default();
                     for techl2 in techl2_vec {
-                        let techl2_id = self.exist_tech_l2.get(techl2).unwrap().to_owned();
-                        if exist_tag_set.contains(techl2) == false {
-                            self.document_tech.push(DocumentTech::new(current_doc_id.unwrap(), techl2_id));
+                        let techl2_id = *self.exist_tech_l2.get(techl2).ok_or_else(|| {
+                            anyhow!("tech tag '{}' of document '{}' is not in the tag sheet", techl2, name)
+                        })?;
+                        if !exist_tag_set.contains(techl2) {
+                            self.document_tech.push(DocumentTech::new(current_doc_id, techl2_id));
                             exist_tag_set.insert(techl2.to_string());
                         }
                     }
                 }
 
                 if let Some(systeml2_vec) = systeml2_vec {
-                    let exist_tag_set = self.exist_document_system.entry(current_doc_id.unwrap()).or_insert(HashSet::new());
+                    let exist_tag_set = self.exist_document_system.entry(current_doc_id).or_default();
                     for systeml2 in systeml2_vec {
-                        let systeml2_id = self.exist_system_l2.get(systeml2).unwrap().to_owned();
-                        if exist_tag_set.contains(systeml2) == false {
-                            self.document_system.push(DocumentSystem::new(current_doc_id.unwrap(), systeml2_id));
+                        let systeml2_id = *self.exist_system_l2.get(systeml2).ok_or_else(|| {
+                            anyhow!("system tag '{}' of document '{}' is not in the tag sheet", systeml2, name)
+                        })?;
+                        if !exist_tag_set.contains(systeml2) {
+                            self.document_system.push(DocumentSystem::new(current_doc_id, systeml2_id));
                             exist_tag_set.insert(systeml2.to_string());
                         }
                     }
                 }
 
                 if let Some(mfl2_vec) = mfl2_vec {
-                    let exist_tag_set = self.exist_document_mf.entry(current_doc_id.unwrap()).or_insert(HashSet::new());
+                    let exist_tag_set = self.exist_document_mf.entry(current_doc_id).or_default();
                     for mfl2 in mfl2_vec {
-                        let mfl2_id = self.exist_mf_l2.get(mfl2).unwrap().to_owned();
-                        if exist_tag_set.contains(mfl2) == false {
-                            self.document_mf.push(DocumentMf::new(current_doc_id.unwrap(), mfl2_id));
+                        let mfl2_id = *self.exist_mf_l2.get(mfl2).ok_or_else(|| {
+                            anyhow!("mf tag '{}' of document '{}' is not in the tag sheet", mfl2, name)
+                        })?;
+                        if !exist_tag_set.contains(mfl2) {
+                            self.document_mf.push(DocumentMf::new(current_doc_id, mfl2_id));
                             exist_tag_set.insert(mfl2.to_string());
                         }
                     }
                 }
 
                 if let Some(projectl2_vec) = projectl2_vec {
-                    let exist_tag_set = self.exist_document_project.entry(current_doc_id.unwrap()).or_insert(HashSet::new());
+                    let exist_tag_set = self.exist_document_project.entry(current_doc_id).or_default();
                     for projectl2 in projectl2_vec {
-                        let projectl2_id = self.exist_project_l2.get(projectl2).unwrap().to_owned();
-                        if exist_tag_set.contains(projectl2) == false {
-                            self.document_project.push(DocumentProject::new(current_doc_id.unwrap(), projectl2_id));
+                        let projectl2_id = *self.exist_project_l2.get(projectl2).ok_or_else(|| {
+                            anyhow!("project tag '{}' of document '{}' is not in the tag sheet", projectl2, name)
+                        })?;
+                        if !exist_tag_set.contains(projectl2) {
+                            self.document_project.push(DocumentProject::new(current_doc_id, projectl2_id));
                             exist_tag_set.insert(projectl2.to_string());
                         }
                     }
                 }
 
                 if let Some(aspice_vec) = aspice_vec {
-                    let exist_aspice_set = self.exist_document_aspice.entry(current_doc_id.unwrap()).or_insert(HashSet::new());
+                    let exist_aspice_set = self.exist_document_aspice.entry(current_doc_id).or_default();
                     for aspice in aspice_vec {
-                        let aspice = Aspice::from_str(aspice).unwrap();
-                        if exist_aspice_set.contains(&aspice) == false {
-                            self.document_aspice.push(DocumentAspiceMapping::new(current_doc_id.unwrap(), aspice.clone()));
+                        let aspice = Aspice::from_str(aspice).ok_or_else(|| {
+                            anyhow!("unknown aspice step '{}' of document '{}'", aspice, name)
+                        })?;
+                        if !exist_aspice_set.contains(&aspice) {
+                            self.document_aspice.push(DocumentAspiceMapping::new(current_doc_id, aspice.clone()));
                             exist_aspice_set.insert(aspice.clone());
                         }
                     }
                 }
             }
             
-            // handle associate_requirement
+            // handle associate requirements, must after all documents are imported
             for row in range.rows().skip(1) {
-                let name = row.get(0).and_then(|cell| cell.get_string()).unwrap();
-                let associate_requirement = row.get(8).and_then(|cell| cell.get_string());
-                self.documents.iter_mut().find(|doc| doc.name == name).map(|doc| {
-                    if let Some(associate_requirement) = associate_requirement {
-                        doc.associate_requirement = self.exist_documents.get(associate_requirement).unwrap().to_owned();
-                    }
+                let name = row.first().and_then(|cell| cell.get_string());
+                let requirement_vec = row.get(8).and_then(|cell| {
+                    cell.get_string().map(|str_val| {
+                        str_val
+                            .trim()
+                            .split(',')
+                            .map(|s| s.trim())
+                            .filter(|s| !s.is_empty())
+                            .collect::<Vec<&str>>()
+                    })
                 });
+
+                let (name, doc_id) = match name.and_then(|name| self.exist_documents.get(name).map(|id| (name, *id))) {
+                    Some(doc) => doc,
+                    None => continue,
+                };
+
+                for requirement in requirement_vec.unwrap_or_default() {
+                    let requirement_id = *self.exist_documents.get(requirement).ok_or_else(|| {
+                        anyhow!("associate requirement '{}' of document '{}' is not a document", requirement, name)
+                    })?;
+                    if requirement_id == doc_id {
+                        continue;
+                    }
+
+                    let exist_requirement_set = self.exist_document_requirement.entry(doc_id).or_default();
+                    if !exist_requirement_set.contains(requirement) {
+                        self.document_requirement.push(DocumentRequirement::new(doc_id, requirement_id));
+                        exist_requirement_set.insert(requirement.to_string());
+                    }
+
+                    // the associate requirement is also the first link of the trace chain
+                    let exist_target_set = self.exist_document_trace_link.entry(doc_id).or_default();
+                    if !exist_target_set.contains(&requirement_id) {
+                        let is_verification = self
+                            .exist_document_aspice
+                            .get(&doc_id)
+                            .is_some_and(|steps| steps.iter().any(|step| step.is_verification()));
+                        let link_type = if is_verification {
+                            TraceLinkType::Verifies
+                        } else {
+                            TraceLinkType::Refines
+                        };
+                        self.document_trace_link.push(DocumentTraceLink::new(doc_id, requirement_id, link_type));
+                        exist_target_set.insert(requirement_id);
+                    }
+                }
             }
         }
 
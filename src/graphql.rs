@@ -0,0 +1,727 @@
+//! GraphQL schema over documents, tags, requirements and ASPICE steps.
+//! Relations are resolved through `MapLoader`, which batches the keys requested at one
+//! depth of a query into a single read of each table, so a query costs a few reads per level
+//! however many documents and tags it touches.
+
+use crate::database::models::*;
+use crate::database::repository::Repository;
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Enum, Object, Schema};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
+use uuid::Uuid;
+
+pub type MapSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+// nested relations grow fast, deeper queries are rejected
+const MAX_DEPTH: usize = 12;
+
+pub fn schema() -> &'static MapSchema {
+    static SCHEMA: OnceLock<MapSchema> = OnceLock::new();
+    SCHEMA.get_or_init(|| {
+        Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+            .limit_depth(MAX_DEPTH)
+            .finish()
+    })
+}
+
+// answer `request` from `repo`, the loaders live as long as the request
+pub async fn execute(
+    repo: Arc<dyn Repository>,
+    request: async_graphql::Request,
+) -> async_graphql::Response {
+    let loader = DataLoader::new(MapLoader { repo: repo.clone() }, tokio::spawn);
+    schema().execute(request.data(repo).data(loader)).await
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(name = "Dimension")]
+pub enum DimensionName {
+    Arch,
+    Component,
+    Mf,
+    Project,
+}
+
+impl From<DimensionName> for Dimension {
+    fn from(name: DimensionName) -> Self {
+        match name {
+            DimensionName::Arch => Dimension::Tech,
+            DimensionName::Component => Dimension::System,
+            DimensionName::Mf => Dimension::Mf,
+            DimensionName::Project => Dimension::Project,
+        }
+    }
+}
+
+impl From<Dimension> for DimensionName {
+    fn from(dimension: Dimension) -> Self {
+        match dimension {
+            Dimension::Tech => DimensionName::Arch,
+            Dimension::System => DimensionName::Component,
+            Dimension::Mf => DimensionName::Mf,
+            Dimension::Project => DimensionName::Project,
+        }
+    }
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(name = "TagLevel")]
+pub enum LevelName {
+    L1,
+    L2,
+}
+
+impl From<LevelName> for TagLevel {
+    fn from(name: LevelName) -> Self {
+        match name {
+            LevelName::L1 => TagLevel::L1,
+            LevelName::L2 => TagLevel::L2,
+        }
+    }
+}
+
+// a tag as the loaders return it
+#[derive(Debug, Clone)]
+pub struct Tag {
+    dimension: Dimension,
+    level: TagLevel,
+    name: String,
+    sort_order: i32,
+    // name and order of the L1 tag of an L2 tag
+    parent: Option<(String, i32)>,
+}
+
+impl Tag {
+    fn l1(dimension: Dimension, pair: &TagPair) -> Tag {
+        Tag {
+            dimension,
+            level: TagLevel::L1,
+            name: pair.l1_name.clone(),
+            sort_order: pair.l1_order,
+            parent: None,
+        }
+    }
+
+    fn l2(dimension: Dimension, pair: &TagPair) -> Tag {
+        Tag {
+            dimension,
+            level: TagLevel::L2,
+            name: pair.l2_name.clone(),
+            sort_order: pair.l2_order,
+            parent: Some((pair.l1_name.clone(), pair.l1_order)),
+        }
+    }
+}
+
+// keys of `MapLoader`, one per relation
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DocumentKey(pub Uuid);
+
+// the L2 tags of a document in a dimension
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DocumentTagsKey(pub Dimension, pub Uuid);
+
+// the documents of a tag, of all its L2 tags for an L1 tag
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TagDocumentsKey(pub Dimension, pub TagLevel, pub String);
+
+// the tag pairs of a dimension in tree order
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DimensionKey(pub Dimension);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DocumentAspiceKey(pub Uuid);
+
+// the requirements a document is associated with
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequirementsKey(pub Uuid);
+
+// the documents associated with a requirement
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequiredByKey(pub Uuid);
+
+type LoadError = Arc<anyhow::Error>;
+
+pub struct MapLoader {
+    repo: Arc<dyn Repository>,
+}
+
+impl MapLoader {
+    async fn pairs(&self, dimension: Dimension) -> anyhow::Result<Vec<TagPair>> {
+        let mut pairs = self.repo.read_tag_pairs(dimension).await?;
+        pairs.sort_by(|a, b| {
+            (a.l1_order, &a.l1_name, a.l2_order, &a.l2_name)
+                .cmp(&(b.l1_order, &b.l1_name, b.l2_order, &b.l2_name))
+        });
+        Ok(pairs)
+    }
+
+    // document id -> ids of the requirements it is associated with
+    async fn requirements(&self) -> anyhow::Result<HashMap<Uuid, BTreeSet<Uuid>>> {
+        let mut docs = HashMap::new();
+        self.repo.read_exist_documents(&mut docs).await?;
+        let mut doc_requirements = HashMap::new();
+        self.repo
+            .read_exist_document_requirement(&mut doc_requirements)
+            .await?;
+        Ok(doc_requirements
+            .into_iter()
+            .map(|(docid, names)| {
+                let ids = names
+                    .iter()
+                    .filter_map(|name| docs.get(name).copied())
+                    .collect();
+                (docid, ids)
+            })
+            .collect())
+    }
+}
+
+fn dimensions<'a, K: 'a>(keys: &'a [K], dimension: impl Fn(&K) -> Dimension) -> HashSet<Dimension> {
+    keys.iter().map(dimension).collect()
+}
+
+impl Loader<DocumentKey> for MapLoader {
+    type Value = Document;
+    type Error = LoadError;
+
+    async fn load(
+        &self,
+        keys: &[DocumentKey],
+    ) -> Result<HashMap<DocumentKey, Document>, LoadError> {
+        let wanted: HashSet<&Uuid> = keys.iter().map(|key| &key.0).collect();
+        let docs = self.repo.read_documents().await?;
+        Ok(docs
+            .into_iter()
+            .filter(|doc| wanted.contains(&doc.id))
+            .map(|doc| (DocumentKey(doc.id), doc))
+            .collect())
+    }
+}
+
+impl Loader<DocumentTagsKey> for MapLoader {
+    type Value = Vec<Tag>;
+    type Error = LoadError;
+
+    async fn load(
+        &self,
+        keys: &[DocumentTagsKey],
+    ) -> Result<HashMap<DocumentTagsKey, Vec<Tag>>, LoadError> {
+        let mut result: HashMap<DocumentTagsKey, Vec<Tag>> = HashMap::new();
+        for dimension in dimensions(keys, |key| key.0) {
+            let pairs = self.pairs(dimension).await?;
+            let tags: HashMap<&str, Tag> = pairs
+                .iter()
+                .map(|pair| (pair.l2_name.as_str(), Tag::l2(dimension, pair)))
+                .collect();
+            for link in self.repo.read_document_tags(dimension).await? {
+                if let Some(tag) = tags.get(link.name.as_str()) {
+                    let key = DocumentTagsKey(dimension, link.docid);
+                    result.entry(key).or_default().push(tag.clone());
+                }
+            }
+        }
+        for tags in result.values_mut() {
+            tags.sort_by(|a, b| {
+                (&a.parent, a.sort_order, &a.name).cmp(&(&b.parent, b.sort_order, &b.name))
+            });
+        }
+        Ok(result)
+    }
+}
+
+impl Loader<TagDocumentsKey> for MapLoader {
+    type Value = Vec<Uuid>;
+    type Error = LoadError;
+
+    async fn load(
+        &self,
+        keys: &[TagDocumentsKey],
+    ) -> Result<HashMap<TagDocumentsKey, Vec<Uuid>>, LoadError> {
+        let mut result: HashMap<TagDocumentsKey, BTreeSet<Uuid>> = HashMap::new();
+        for dimension in dimensions(keys, |key| key.0) {
+            let pairs = self.pairs(dimension).await?;
+            let l1_of: HashMap<&str, &str> = pairs
+                .iter()
+                .map(|pair| (pair.l2_name.as_str(), pair.l1_name.as_str()))
+                .collect();
+            for link in self.repo.read_document_tags(dimension).await? {
+                let l2 = TagDocumentsKey(dimension, TagLevel::L2, link.name.clone());
+                result.entry(l2).or_default().insert(link.docid);
+                if let Some(l1) = l1_of.get(link.name.as_str()) {
+                    let l1 = TagDocumentsKey(dimension, TagLevel::L1, l1.to_string());
+                    result.entry(l1).or_default().insert(link.docid);
+                }
+            }
+        }
+        Ok(keys
+            .iter()
+            .map(|key| {
+                let ids = result.remove(key).unwrap_or_default();
+                (key.clone(), ids.into_iter().collect())
+            })
+            .collect())
+    }
+}
+
+impl Loader<DimensionKey> for MapLoader {
+    type Value = Vec<TagPair>;
+    type Error = LoadError;
+
+    async fn load(
+        &self,
+        keys: &[DimensionKey],
+    ) -> Result<HashMap<DimensionKey, Vec<TagPair>>, LoadError> {
+        let mut result = HashMap::new();
+        for dimension in dimensions(keys, |key| key.0) {
+            result.insert(DimensionKey(dimension), self.pairs(dimension).await?);
+        }
+        Ok(result)
+    }
+}
+
+impl Loader<DocumentAspiceKey> for MapLoader {
+    type Value = Vec<Aspice>;
+    type Error = LoadError;
+
+    async fn load(
+        &self,
+        keys: &[DocumentAspiceKey],
+    ) -> Result<HashMap<DocumentAspiceKey, Vec<Aspice>>, LoadError> {
+        let mut doc_aspices = HashMap::new();
+        self.repo
+            .read_exist_document_aspice(&mut doc_aspices)
+            .await?;
+        Ok(keys
+            .iter()
+            .map(|key| {
+                let mut steps: Vec<Aspice> = doc_aspices
+                    .remove(&key.0)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect();
+                steps.sort();
+                (key.clone(), steps)
+            })
+            .collect())
+    }
+}
+
+impl Loader<RequirementsKey> for MapLoader {
+    type Value = Vec<Uuid>;
+    type Error = LoadError;
+
+    async fn load(
+        &self,
+        keys: &[RequirementsKey],
+    ) -> Result<HashMap<RequirementsKey, Vec<Uuid>>, LoadError> {
+        let mut requirements = self.requirements().await?;
+        Ok(keys
+            .iter()
+            .map(|key| {
+                let ids = requirements.remove(&key.0).unwrap_or_default();
+                (key.clone(), ids.into_iter().collect())
+            })
+            .collect())
+    }
+}
+
+impl Loader<RequiredByKey> for MapLoader {
+    type Value = Vec<Uuid>;
+    type Error = LoadError;
+
+    async fn load(
+        &self,
+        keys: &[RequiredByKey],
+    ) -> Result<HashMap<RequiredByKey, Vec<Uuid>>, LoadError> {
+        let mut required_by: HashMap<Uuid, BTreeSet<Uuid>> = HashMap::new();
+        for (docid, requirements) in self.requirements().await? {
+            for requirement in requirements {
+                required_by.entry(requirement).or_default().insert(docid);
+            }
+        }
+        Ok(keys
+            .iter()
+            .map(|key| {
+                let ids = required_by.remove(&key.0).unwrap_or_default();
+                (key.clone(), ids.into_iter().collect())
+            })
+            .collect())
+    }
+}
+
+fn loader<'a>(ctx: &Context<'a>) -> &'a DataLoader<MapLoader> {
+    ctx.data_unchecked::<DataLoader<MapLoader>>()
+}
+
+fn repo<'a>(ctx: &Context<'a>) -> &'a Arc<dyn Repository> {
+    ctx.data_unchecked::<Arc<dyn Repository>>()
+}
+
+// the documents of `ids`, ordered by name
+async fn documents(
+    ctx: &Context<'_>,
+    ids: Vec<Uuid>,
+) -> async_graphql::Result<Vec<DocumentObject>> {
+    let mut docs: Vec<Document> = loader(ctx)
+        .load_many(ids.into_iter().map(DocumentKey))
+        .await?
+        .into_values()
+        .collect();
+    docs.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+    Ok(docs.into_iter().map(DocumentObject).collect())
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn document(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+    ) -> async_graphql::Result<Option<DocumentObject>> {
+        Ok(loader(ctx)
+            .load_one(DocumentKey(id))
+            .await?
+            .map(DocumentObject))
+    }
+
+    /// All documents ordered by name, or the documents of an L2 tag. Without `dimension` the
+    /// tag is looked up in the order arch, component, mf, project as in filter_and_classify.
+    async fn documents(
+        &self,
+        ctx: &Context<'_>,
+        tag: Option<String>,
+        dimension: Option<DimensionName>,
+    ) -> async_graphql::Result<Vec<DocumentObject>> {
+        let tag = match tag {
+            Some(tag) => tag,
+            None => {
+                let docs = repo(ctx).read_documents().await?;
+                return Ok(docs.into_iter().map(DocumentObject).collect());
+            }
+        };
+        let dimensions = match dimension {
+            Some(dimension) => vec![dimension.into()],
+            None => Dimension::all(),
+        };
+        for dimension in dimensions {
+            let key = TagDocumentsKey(dimension, TagLevel::L2, tag.clone());
+            let ids = loader(ctx).load_one(key).await?.unwrap_or_default();
+            if !ids.is_empty() {
+                return documents(ctx, ids).await;
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    /// The tags of a dimension in tree order, the L1 tags unless `level` is L2.
+    async fn tags(
+        &self,
+        ctx: &Context<'_>,
+        dimension: DimensionName,
+        #[graphql(default_with = "LevelName::L1")] level: LevelName,
+    ) -> async_graphql::Result<Vec<Tag>> {
+        let dimension = dimension.into();
+        let pairs = loader(ctx)
+            .load_one(DimensionKey(dimension))
+            .await?
+            .unwrap_or_default();
+        Ok(match level {
+            LevelName::L1 => {
+                let mut seen = HashSet::new();
+                pairs
+                    .iter()
+                    .filter(|pair| seen.insert(pair.l1_name.clone()))
+                    .map(|pair| Tag::l1(dimension, pair))
+                    .collect()
+            }
+            LevelName::L2 => pairs.iter().map(|pair| Tag::l2(dimension, pair)).collect(),
+        })
+    }
+
+    /// A tag by name, L1 tags are found before L2 tags of the same name.
+    async fn tag(
+        &self,
+        ctx: &Context<'_>,
+        dimension: DimensionName,
+        name: String,
+    ) -> async_graphql::Result<Option<Tag>> {
+        let dimension = dimension.into();
+        let pairs = loader(ctx)
+            .load_one(DimensionKey(dimension))
+            .await?
+            .unwrap_or_default();
+        let l1 = pairs
+            .iter()
+            .find(|pair| pair.l1_name == name)
+            .map(|pair| Tag::l1(dimension, pair));
+        let l2 = || {
+            pairs
+                .iter()
+                .find(|pair| pair.l2_name == name)
+                .map(|pair| Tag::l2(dimension, pair))
+        };
+        Ok(l1.or_else(l2))
+    }
+
+    /// Documents with the 需求 step or associated as a requirement by another document, as
+    /// `/requirements`.
+    async fn requirements(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Vec<RequirementObject>> {
+        let docs = repo(ctx).read_documents().await?;
+        let mut doc_aspices = HashMap::new();
+        repo(ctx)
+            .read_exist_document_aspice(&mut doc_aspices)
+            .await?;
+        let mut associated = HashSet::new();
+        let mut doc_requirements = HashMap::new();
+        repo(ctx)
+            .read_exist_document_requirement(&mut doc_requirements)
+            .await?;
+        for names in doc_requirements.into_values() {
+            associated.extend(names);
+        }
+        Ok(docs
+            .into_iter()
+            .filter(|doc| {
+                associated.contains(&doc.name)
+                    || doc_aspices
+                        .get(&doc.id)
+                        .is_some_and(|steps| steps.contains(&Aspice::需求))
+            })
+            .map(RequirementObject)
+            .collect())
+    }
+
+    /// The V-model steps with their documents.
+    async fn aspice_steps(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<AspiceStep>> {
+        let mut doc_aspices = HashMap::new();
+        repo(ctx)
+            .read_exist_document_aspice(&mut doc_aspices)
+            .await?;
+        Ok(Aspice::all()
+            .into_iter()
+            .map(|step| {
+                let documents = doc_aspices
+                    .iter()
+                    .filter(|(_, steps)| steps.contains(&step))
+                    .map(|(id, _)| *id)
+                    .collect();
+                AspiceStep { step, documents }
+            })
+            .collect())
+    }
+}
+
+pub struct DocumentObject(Document);
+
+#[Object(name = "Document")]
+impl DocumentObject {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn address(&self) -> &str {
+        &self.0.link
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    /// The L2 tags of the document, of one dimension or of all.
+    async fn tags(
+        &self,
+        ctx: &Context<'_>,
+        dimension: Option<DimensionName>,
+    ) -> async_graphql::Result<Vec<Tag>> {
+        let dimensions = match dimension {
+            Some(dimension) => vec![dimension.into()],
+            None => Dimension::all(),
+        };
+        let keys: Vec<DocumentTagsKey> = dimensions
+            .iter()
+            .map(|dimension| DocumentTagsKey(*dimension, self.0.id))
+            .collect();
+        let mut tags = loader(ctx).load_many(keys.clone()).await?;
+        Ok(keys
+            .iter()
+            .flat_map(|key| tags.remove(key).unwrap_or_default())
+            .collect())
+    }
+
+    /// ASPICE steps, short names such as 需求 or 单测, in V-model order.
+    async fn aspice_steps(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<String>> {
+        let steps = loader(ctx)
+            .load_one(DocumentAspiceKey(self.0.id))
+            .await?
+            .unwrap_or_default();
+        Ok(steps.iter().map(|step| step.as_str().to_string()).collect())
+    }
+
+    /// The requirements the document is associated with.
+    async fn requirements(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Vec<RequirementObject>> {
+        let ids = loader(ctx)
+            .load_one(RequirementsKey(self.0.id))
+            .await?
+            .unwrap_or_default();
+        let docs = documents(ctx, ids).await?;
+        Ok(docs
+            .into_iter()
+            .map(|doc| RequirementObject(doc.0))
+            .collect())
+    }
+}
+
+#[Object(name = "Tag")]
+impl Tag {
+    async fn dimension(&self) -> DimensionName {
+        self.dimension.into()
+    }
+
+    async fn level(&self) -> LevelName {
+        match self.level {
+            TagLevel::L1 => LevelName::L1,
+            TagLevel::L2 => LevelName::L2,
+        }
+    }
+
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn sort_order(&self) -> i32 {
+        self.sort_order
+    }
+
+    /// The L1 tag of an L2 tag.
+    async fn parent(&self) -> Option<Tag> {
+        self.parent.as_ref().map(|(name, sort_order)| Tag {
+            dimension: self.dimension,
+            level: TagLevel::L1,
+            name: name.clone(),
+            sort_order: *sort_order,
+            parent: None,
+        })
+    }
+
+    /// The L2 tags of an L1 tag, in tree order.
+    async fn children(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Tag>> {
+        if self.level == TagLevel::L2 {
+            return Ok(Vec::new());
+        }
+        let pairs = loader(ctx)
+            .load_one(DimensionKey(self.dimension))
+            .await?
+            .unwrap_or_default();
+        Ok(pairs
+            .iter()
+            .filter(|pair| pair.l1_name == self.name)
+            .map(|pair| Tag::l2(self.dimension, pair))
+            .collect())
+    }
+
+    /// The documents of the tag, for an L1 tag those of all its L2 tags, ordered by name.
+    async fn documents(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<DocumentObject>> {
+        let key = TagDocumentsKey(self.dimension, self.level, self.name.clone());
+        let ids = loader(ctx).load_one(key).await?.unwrap_or_default();
+        documents(ctx, ids).await
+    }
+
+    async fn document_count(&self, ctx: &Context<'_>) -> async_graphql::Result<usize> {
+        let key = TagDocumentsKey(self.dimension, self.level, self.name.clone());
+        Ok(loader(ctx).load_one(key).await?.unwrap_or_default().len())
+    }
+}
+
+pub struct RequirementObject(Document);
+
+#[Object(name = "Requirement")]
+impl RequirementObject {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn address(&self) -> &str {
+        &self.0.link
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    /// The requirement as a document, for its tags and steps.
+    async fn document(&self) -> DocumentObject {
+        DocumentObject(self.0.clone())
+    }
+
+    /// The documents associated with the requirement.
+    async fn documents(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<DocumentObject>> {
+        let ids = loader(ctx)
+            .load_one(RequiredByKey(self.0.id))
+            .await?
+            .unwrap_or_default();
+        documents(ctx, ids).await
+    }
+
+    /// The requirement and its associated documents by V-model step, as `/requirements/:id/trace`.
+    async fn aspice_steps(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<AspiceStep>> {
+        let mut ids = loader(ctx)
+            .load_one(RequiredByKey(self.0.id))
+            .await?
+            .unwrap_or_default();
+        ids.push(self.0.id);
+        let steps = loader(ctx)
+            .load_many(ids.iter().cloned().map(DocumentAspiceKey))
+            .await?;
+        Ok(Aspice::all()
+            .into_iter()
+            .map(|step| {
+                let documents = steps
+                    .iter()
+                    .filter(|(_, steps)| steps.contains(&step))
+                    .map(|(key, _)| key.0)
+                    .collect();
+                AspiceStep { step, documents }
+            })
+            .collect())
+    }
+}
+
+pub struct AspiceStep {
+    step: Aspice,
+    documents: Vec<Uuid>,
+}
+
+#[Object]
+impl AspiceStep {
+    /// Short name such as 需求 or 单测.
+    async fn step(&self) -> &str {
+        self.step.as_str()
+    }
+
+    /// Name of the ASPICE process, e.g. 软件架构设计.
+    async fn name(&self) -> String {
+        self.step.to_string()
+    }
+
+    async fn documents(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<DocumentObject>> {
+        documents(ctx, self.documents.clone()).await
+    }
+}
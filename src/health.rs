@@ -0,0 +1,118 @@
+// Liveness and readiness probes.
+use arch_map::database::{dml_interface, migration};
+use sqlx::PgPool;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct Readiness {
+    pub pool: PgPool,
+    // seconds, 0 to skip the sync check
+    pub max_sync_age: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    ok: bool,
+    detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadyReport {
+    ready: bool,
+    checks: BTreeMap<&'static str, Check>,
+}
+
+impl Check {
+    fn ok(detail: impl Into<String>) -> Check {
+        Check {
+            ok: true,
+            detail: detail.into(),
+        }
+    }
+
+    fn failed(detail: impl Into<String>) -> Check {
+        Check {
+            ok: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+// the process is up and serving requests
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+async fn check_database(pool: &PgPool) -> Result<(), String> {
+    // do not let a probe wait for the whole pool acquire timeout
+    match tokio::time::timeout(Duration::from_secs(2), sqlx::query("SELECT 1").execute(pool)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(_) => Err("timed out".to_string()),
+    }
+}
+
+async fn check_migrations(pool: &PgPool) -> Check {
+    let schema = match migration::status(pool).await {
+        Ok(schema) => schema,
+        Err(err) => return Check::failed(format!("{:#}", err)),
+    };
+    if let Err(err) = schema.ensure_compatible() {
+        return Check::failed(format!("{:#}", err));
+    }
+    match schema.pending() {
+        pending if pending.is_empty() => Check::ok("all migrations applied"),
+        pending => Check::failed(format!("pending migrations: {:?}", pending)),
+    }
+}
+
+async fn check_sync(pool: &PgPool, max_sync_age: u64) -> Check {
+    if max_sync_age == 0 {
+        return Check::ok("not checked");
+    }
+    match dml_interface::read_sync_stats(pool).await {
+        Ok(stats) => match stats.last_success_at {
+            Some(last_success_at) => {
+                let age = (chrono::Utc::now() - last_success_at).num_seconds();
+                if age <= max_sync_age as i64 {
+                    Check::ok(format!("last successful sync {}s ago", age))
+                } else {
+                    Check::failed(format!(
+                        "last successful sync {}s ago, older than {}s",
+                        age, max_sync_age
+                    ))
+                }
+            }
+            None => Check::failed("no successful sync recorded"),
+        },
+        Err(err) => Check::failed(format!("{:#}", err)),
+    }
+}
+
+// database reachable, schema up to date and data fresh
+pub async fn readyz(State(readiness): State<Readiness>) -> (StatusCode, Json<ReadyReport>) {
+    let mut checks = BTreeMap::new();
+    match check_database(&readiness.pool).await {
+        Ok(()) => {
+            checks.insert("database", Check::ok("reachable"));
+            checks.insert("migrations", check_migrations(&readiness.pool).await);
+            checks.insert("sync", check_sync(&readiness.pool, readiness.max_sync_age).await);
+        }
+        Err(err) => {
+            checks.insert("database", Check::failed(err));
+        }
+    }
+
+    let ready = checks.values().all(|check| check.ok);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(ReadyReport { ready, checks }))
+}
@@ -1,2 +1,8 @@
+pub mod auth;
+pub mod config;
 pub mod database;
-pub mod etl;
\ No newline at end of file
+pub mod etl;
+pub mod graphql;
+pub mod link_check;
+pub mod logging;
+pub mod report;
\ No newline at end of file
@@ -0,0 +1,147 @@
+//! Checks the links of all documents and keeps the latest result of each in `document_link_check`.
+//! Requests run concurrently, but requests to the same host are spaced out.
+
+use crate::database::models::*;
+use crate::database::repository::Repository;
+use chrono::Utc;
+use reqwest::{Client, Url};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone)]
+pub struct LinkCheckOptions {
+    // requests in flight at once
+    pub concurrency: usize,
+    // least time between two requests to the same host
+    pub host_interval: Duration,
+    // per request, redirects included
+    pub timeout: Duration,
+}
+
+impl Default for LinkCheckOptions {
+    fn default() -> Self {
+        LinkCheckOptions {
+            concurrency: 8,
+            host_interval: Duration::from_millis(500),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LinkCheckSummary {
+    pub checked: usize,
+    pub broken: usize,
+}
+
+// host -> when its next request may start
+#[derive(Debug)]
+struct HostLimiter {
+    interval: Duration,
+    next: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostLimiter {
+    async fn wait(&self, host: &str) {
+        let at = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let at = next.get(host).map_or(now, |&at| at.max(now));
+            next.insert(host.to_string(), at + self.interval);
+            at
+        };
+        tokio::time::sleep_until(at).await;
+    }
+}
+
+// (ok, status code, final url, error) of one link
+type Outcome = (bool, Option<i32>, Option<String>, Option<String>);
+
+async fn request(client: &Client, limiter: &HostLimiter, method: reqwest::Method, url: &Url) -> Outcome {
+    limiter.wait(url.host_str().unwrap_or_default()).await;
+    match client.request(method, url.clone()).send().await {
+        Ok(response) => (
+            response.status().is_success(),
+            Some(response.status().as_u16() as i32),
+            Some(response.url().to_string()),
+            None,
+        ),
+        Err(err) => (false, None, None, Some(err.to_string())),
+    }
+}
+
+// HEAD first, GET if the server does not answer HEAD with success (many refuse it)
+async fn check_link(client: &Client, limiter: &HostLimiter, link: &str) -> Outcome {
+    let url = match Url::parse(link) {
+        Ok(url) => url,
+        Err(err) => return (false, None, None, Some(format!("invalid link: {}", err))),
+    };
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return (false, None, None, Some(format!("not an http link: {}", link)));
+    }
+
+    let head = request(client, limiter, reqwest::Method::HEAD, &url).await;
+    if head.0 {
+        return head;
+    }
+    request(client, limiter, reqwest::Method::GET, &url).await
+}
+
+// documents without a link are skipped
+pub async fn check_links<R: Repository + ?Sized>(
+    repo: &R,
+    options: &LinkCheckOptions,
+) -> anyhow::Result<LinkCheckSummary> {
+    let client = Client::builder()
+        .timeout(options.timeout)
+        .user_agent(concat!("arch_map-link-check/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let limiter = Arc::new(HostLimiter {
+        interval: options.host_interval,
+        next: Mutex::new(HashMap::new()),
+    });
+    let permits = Arc::new(Semaphore::new(options.concurrency.max(1)));
+
+    let mut tasks = JoinSet::new();
+    for doc in repo.read_documents().await? {
+        if doc.link.trim().is_empty() {
+            continue;
+        }
+        let permit = permits.clone().acquire_owned().await?;
+        let client = client.clone();
+        let limiter = limiter.clone();
+        tasks.spawn(async move {
+            let (ok, status_code, final_url, error) = check_link(&client, &limiter, doc.link.trim()).await;
+            drop(permit);
+            if !ok {
+                tracing::warn!("broken link of {}: {} {:?} {:?}", doc.name, doc.link, status_code, error);
+            }
+            LinkCheck {
+                docid: doc.id,
+                link: doc.link,
+                ok,
+                status_code,
+                final_url,
+                error,
+                checked_at: Utc::now(),
+            }
+        });
+    }
+
+    let mut checks = Vec::new();
+    while let Some(check) = tasks.join_next().await {
+        checks.push(check?);
+    }
+    let summary = LinkCheckSummary {
+        checked: checks.len(),
+        broken: checks.iter().filter(|check| !check.ok).count(),
+    };
+    repo.insert_link_checks(checks).await?;
+
+    Ok(summary)
+}
@@ -0,0 +1,59 @@
+//! Tracing subscriber shared by the binaries.
+//! Events of the `log` crate (sqlx, reqwest, ...) are forwarded to it as well.
+
+use anyhow::{anyhow, Context};
+use clap::ValueEnum;
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    // one json object per line, with the fields of the current span (e.g. request_id)
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_name(s: &str) -> Option<LogFormat> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+// `filter` is a level ("info") or a list of directives ("info,sqlx=warn")
+pub fn parse_filter(filter: &str) -> anyhow::Result<EnvFilter> {
+    EnvFilter::try_new(filter).with_context(|| format!("invalid log filter '{}'", filter))
+}
+
+pub fn init(filter: &str, format: LogFormat) -> anyhow::Result<()> {
+    // stdout is left to the output of the command line tools
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(parse_filter(filter)?)
+        .with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .try_init(),
+    }
+    .map_err(|err| anyhow!("failed to initialise logging: {}", err))
+}
+
+// for the command line tools: ARCH_MAP_LOG_LEVEL (default info) and ARCH_MAP_LOG_FORMAT (default text)
+pub fn init_from_env() -> anyhow::Result<()> {
+    let filter = dotenvy::var("ARCH_MAP_LOG_LEVEL").unwrap_or_else(|_| "info".to_string());
+    let format = match dotenvy::var("ARCH_MAP_LOG_FORMAT") {
+        Ok(format) => LogFormat::from_name(&format)
+            .ok_or_else(|| anyhow!("invalid ARCH_MAP_LOG_FORMAT '{}'", format))?,
+        Err(_) => LogFormat::Text,
+    };
+    init(&filter, format)
+}
@@ -1,54 +1,262 @@
 mod backend;
+mod crud;
+mod health;
+mod metrics;
+mod tls;
 
-use axum::{routing::get, Router, Server};
+use anyhow::Context;
+use arch_map::auth::{require_role, AuthConfig, Authenticator, RequireRole, Role};
+use arch_map::config::{CorsConfig, ServerArgs, ServerCommand, ServerConfig};
+use arch_map::database::dml_interface::{self, DbPoolOptions};
+use arch_map::database::migration;
+use arch_map::logging;
+use axum::{middleware, routing::delete, routing::get, routing::post, routing::put, Router};
 use backend::arch_tree;
+use backend::aspice_coverage_report;
+use backend::broken_links;
+use backend::check_trace_links;
+use backend::classified_documents;
+use backend::data_quality_report;
 use backend::component_tree;
+use backend::cooccurrence_matrix;
+use backend::document_history;
 use backend::filter_and_classify;
+use backend::graph_export;
+use backend::graphql_query;
+use backend::history_diff;
+use backend::impact_analysis;
 use backend::mf_tree;
 use backend::project_arch_tree;
-use http::Method;
-use std::error::Error;
-use std::net::SocketAddr;
-use tower_http::cors::{Any, CorsLayer};
+use backend::requirement_trace;
+use backend::requirements;
+use backend::snapshots;
+use backend::trace_downstream;
+use backend::trace_upstream;
+use backend::update_tag_order;
+use clap::Parser;
+use http::{HeaderValue, Method, Request};
+use tower::ServiceBuilder;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::{Level, Span};
 use axum_server::tls_rustls::RustlsConfig;
-use std::path::PathBuf;
+use std::sync::Arc;
+use sqlx::PgPool;
+use std::time::Duration;
 
 async fn hello_world() -> &'static str {
     "Hello, World!"
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
-
-    // configure certificate and private key used by https
-    let config = RustlsConfig::from_pem_file(
-        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("self_signed_certs")
-            .join("cert.pem"),
-        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("self_signed_certs")
-            .join("key.pem"),
-    )
+// "*" allows any origin, origins are validated by `ServerConfig::validate`
+fn cors_layer(config: &CorsConfig) -> CorsLayer {
+    let allow_origin = if config.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(
+            config
+                .allowed_origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
+    CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::OPTIONS])
+        .allow_origin(allow_origin)
+        .allow_headers(Any)
+}
+
+// every log line of a request carries its x-request-id, taken from the client or generated
+fn request_span<B>(req: &Request<B>) -> Span {
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!("request", method = %req.method(), uri = %req.uri(), request_id)
+}
+
+async fn connect_db(config: &ServerConfig) -> anyhow::Result<PgPool> {
+    let database_url = config
+        .database
+        .url
+        .clone()
+        .context("database.url is not set (config file, DATABASE_URL or --database-url)")?;
+    let statement_timeout = match config.database.statement_timeout_ms {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
+    };
+    dml_interface::connect(&DbPoolOptions {
+        database_url,
+        max_connections: config.database.max_connections,
+        acquire_timeout: Duration::from_millis(config.database.acquire_timeout_ms),
+        statement_timeout,
+        slow_statement: Duration::from_millis(config.database.slow_query_ms),
+        // api requests name their user per transaction
+        changed_by: None,
+        sync_run: None,
+    })
     .await
-    .unwrap();
+    .context("database is not reachable")
+}
 
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    // make .env visible to the env fallbacks of the command line flags
+    dotenvy::dotenv().ok();
+    let args = ServerArgs::parse();
 
-    // Create a CORS middleware.
-    let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
-        .allow_origin(Any)
-        .allow_headers(Any);
+    // `arch_map migrate ...` only needs the database settings
+    if let Some(ServerCommand::Migrate { command }) = &args.command {
+        let config = ServerConfig::resolve(&args)?;
+        logging::init(&config.log_level, config.log_format)?;
+        let pool = connect_db(&config).await?;
+        return migration::run_command(&pool, command).await;
+    }
 
-    let app = Router::new()
-        .route("/hello", get(hello_world))
+    let config = ServerConfig::load(&args)?;
+    logging::init(&config.log_level, config.log_format)?;
+    let pool = connect_db(&config).await?;
+    migration::prepare(&pool, config.database.migrate).await?;
+
+    let authenticator = Arc::new(Authenticator::new(AuthConfig::from_env().await?));
+
+    // viewers can read the map, anonymous requests are viewers unless configured otherwise
+    let viewer_routes = Router::new()
         .route("/filter_and_classify/:tag_name", get(filter_and_classify))
+        .route(
+            "/filter_and_classify/:tag_name/documents",
+            get(classified_documents),
+        )
         .route("/arch_tree/:cross_module", get(arch_tree))
         .route("/component_tree/:cross_module", get(component_tree))
         .route("/mf_tree/:cross_module", get(mf_tree))
         .route("/project_tree/:project", get(project_arch_tree))
-        .layer(cors);
-    axum_server::bind_rustls(addr, config).serve(app.into_make_service()).await?;
+        .route("/cooccurrence/:rows/:columns", get(cooccurrence_matrix))
+        .route("/graph", get(graph_export))
+        .route("/impact", get(impact_analysis))
+        .route("/requirements", get(requirements))
+        .route("/requirements/:id/trace", get(requirement_trace))
+        .route("/aspice_coverage", get(aspice_coverage_report))
+        .route("/documents/:id/trace/upstream", get(trace_upstream))
+        .route("/documents/:id/trace/downstream", get(trace_downstream))
+        .route("/trace_links/check", get(check_trace_links))
+        .route("/links/broken", get(broken_links))
+        .route("/data_quality", get(data_quality_report))
+        .route("/documents/:id/history", get(document_history))
+        .route("/history/diff", get(history_diff))
+        .route("/snapshots", get(snapshots))
+        // read only, POST only because of the size of the queries
+        .route("/graphql", post(graphql_query))
+        .route_layer(middleware::from_fn_with_state(
+            RequireRole::new(authenticator.clone(), Role::Viewer),
+            require_role,
+        ))
+        .with_state(pool.clone());
+
+    // editors correct documents, tags and links
+    let editor_routes = Router::new()
+        .route("/tags/:dimension/:level/sort_order", put(update_tag_order))
+        .route("/documents", post(crud::create_document))
+        .route("/documents/:id", put(crud::update_document))
+        .route("/documents/:id/tags/:dimension", post(crud::link_document_tag))
+        .route(
+            "/documents/:id/tags/:dimension/:name",
+            delete(crud::unlink_document_tag),
+        )
+        .route(
+            "/documents/:id/aspice/:step",
+            post(crud::link_document_aspice).delete(crud::unlink_document_aspice),
+        )
+        .route(
+            "/documents/:id/requirements/:requirement_id",
+            post(crud::link_document_requirement).delete(crud::unlink_document_requirement),
+        )
+        .route("/documents/:id/trace_links", post(crud::link_document_trace))
+        .route(
+            "/documents/:id/trace_links/:target",
+            delete(crud::unlink_document_trace),
+        )
+        .route("/tags/:dimension/:level", post(crud::create_tag))
+        .route("/tags/:dimension/:level/:id", put(crud::update_tag))
+        .route("/snapshots", post(crud::create_snapshot))
+        .route_layer(middleware::from_fn_with_state(
+            RequireRole::new(authenticator.clone(), Role::Editor),
+            require_role,
+        ))
+        .with_state(pool.clone());
+
+    // deleting documents and tags is for admins only
+    let admin_routes = Router::new()
+        .route("/documents/:id", delete(crud::delete_document))
+        .route("/tags/:dimension/:level/:id", delete(crud::delete_tag))
+        .route("/snapshots/:name", delete(crud::delete_snapshot_by_name))
+        .route_layer(middleware::from_fn_with_state(
+            RequireRole::new(authenticator.clone(), Role::Admin),
+            require_role,
+        ))
+        .with_state(pool.clone());
+
+    // probes and metrics are public like /hello
+    let metrics = Arc::new(metrics::Metrics::new(pool.clone())?);
+    let probe_routes = Router::new()
+        .route("/healthz", get(health::healthz))
+        .route(
+            "/readyz",
+            get(health::readyz).with_state(health::Readiness {
+                pool: pool.clone(),
+                max_sync_age: config.health.max_sync_age,
+            }),
+        )
+        .route("/metrics", get(metrics::metrics).with_state(metrics.clone()));
+
+    let app = Router::new()
+        .route("/hello", get(hello_world))
+        .merge(probe_routes)
+        .merge(viewer_routes)
+        .merge(editor_routes)
+        .merge(admin_routes)
+        .layer(middleware::from_fn_with_state(metrics, metrics::track_requests))
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(request_span)
+                        .on_response(DefaultOnResponse::new().level(Level::INFO)),
+                )
+                .layer(PropagateRequestIdLayer::x_request_id()),
+        )
+        .layer(cors_layer(&config.cors));
+
+    if config.tls.enabled {
+        // configure certificate and private key used by https
+        let tls_config = RustlsConfig::from_pem_file(&config.tls.cert, &config.tls.key)
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to load tls certificate {} and key {}",
+                    config.tls.cert.display(),
+                    config.tls.key.display()
+                )
+            })?;
+        tls::spawn_reloader(tls_config.clone(), config.tls.clone());
+
+        tracing::info!("listening on https://{}", config.listen);
+        let https = axum_server::bind_rustls(config.listen, tls_config).serve(app.into_make_service());
+        match config.tls.redirect_listen {
+            Some(redirect_listen) => {
+                tokio::try_join!(https, tls::serve_redirect(redirect_listen, config.listen.port()))?;
+            }
+            None => https.await?,
+        }
+    } else {
+        tracing::info!("listening on http://{}", config.listen);
+        axum_server::bind(config.listen)
+            .serve(app.into_make_service())
+            .await?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,139 @@
+// Prometheus metrics: http requests per route, database pool and data sync runs.
+use arch_map::database::dml_interface;
+use axum::extract::{MatchedPath, State};
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sqlx::PgPool;
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::sync::Arc;
+use std::time::Instant;
+
+pub struct Metrics {
+    registry: Registry,
+    // sampled when scraped
+    pool: PgPool,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    db_pool_connections: IntGauge,
+    db_pool_idle_connections: IntGauge,
+    sync_runs: IntGaugeVec,
+    sync_last_success: Gauge,
+    sync_last_duration: Gauge,
+}
+
+impl Metrics {
+    pub fn new(pool: PgPool) -> prometheus::Result<Metrics> {
+        let registry = Registry::new_custom(Some("arch_map".to_string()), None)?;
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route and status"),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route"),
+            &["method", "route"],
+        )?;
+        let db_pool_connections =
+            IntGauge::new("db_pool_connections", "Open connections in the database pool")?;
+        let db_pool_idle_connections =
+            IntGauge::new("db_pool_idle_connections", "Idle connections in the database pool")?;
+        let sync_runs = IntGaugeVec::new(
+            Opts::new("sync_runs", "Recorded data sync runs by result"),
+            &["result"],
+        )?;
+        let sync_last_success = Gauge::new(
+            "sync_last_success_timestamp_seconds",
+            "Unix time the last successful data sync finished",
+        )?;
+        let sync_last_duration = Gauge::new(
+            "sync_last_duration_seconds",
+            "Duration of the latest data sync run",
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(db_pool_connections.clone()))?;
+        registry.register(Box::new(db_pool_idle_connections.clone()))?;
+        registry.register(Box::new(sync_runs.clone()))?;
+        registry.register(Box::new(sync_last_success.clone()))?;
+        registry.register(Box::new(sync_last_duration.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            pool,
+            http_requests,
+            http_request_duration,
+            db_pool_connections,
+            db_pool_idle_connections,
+            sync_runs,
+            sync_last_success,
+            sync_last_duration,
+        })
+    }
+
+    // pool and sync figures are read when scraped
+    async fn refresh(&self) -> anyhow::Result<()> {
+        self.db_pool_connections.set(self.pool.size() as i64);
+        self.db_pool_idle_connections.set(self.pool.num_idle() as i64);
+
+        let stats = dml_interface::read_sync_stats(&self.pool).await?;
+        self.sync_runs
+            .with_label_values(&["success"])
+            .set(stats.success_total);
+        self.sync_runs
+            .with_label_values(&["failure"])
+            .set(stats.failure_total);
+        if let Some(last_success_at) = stats.last_success_at {
+            self.sync_last_success
+                .set(last_success_at.timestamp_millis() as f64 / 1000.0);
+        }
+        if let Some(duration) = stats.last_duration_seconds {
+            self.sync_last_duration.set(duration);
+        }
+        Ok(())
+    }
+}
+
+// label requests with the route pattern, not the raw path, to keep the label set small
+pub async fn track_requests<B>(
+    State(metrics): State<Arc<Metrics>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = req.method().to_string();
+
+    let start = Instant::now();
+    let response = next.run(req).await;
+
+    metrics
+        .http_request_duration
+        .with_label_values(&[&method, &route])
+        .observe(start.elapsed().as_secs_f64());
+    metrics
+        .http_requests
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    response
+}
+
+pub async fn metrics(State(metrics): State<Arc<Metrics>>) -> Response {
+    if let Err(err) = metrics.refresh().await {
+        // still serve the request metrics when the database is down
+        tracing::warn!("failed to refresh metrics: {:#}", err);
+    }
+
+    let mut buffer = Vec::new();
+    if let Err(err) = TextEncoder::new().encode(&metrics.registry.gather(), &mut buffer) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+    }
+    ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], buffer).into_response()
+}
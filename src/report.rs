@@ -0,0 +1,9 @@
+pub mod cooccurrence;
+pub mod coverage;
+pub mod graph;
+pub mod history;
+pub mod impact;
+pub mod page;
+pub mod quality;
+pub mod trace;
+pub mod tree;
@@ -0,0 +1,103 @@
+//! Co-occurrence of the tags of two dimensions: how many documents carry both tags of a pair.
+//! The full matrix, so the frontend can draw it as a heatmap.
+
+use crate::database::models::*;
+use crate::database::repository::Repository;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Axis {
+    pub dimension: &'static str,
+    pub level: u8,
+    // in tag order, L2 tags grouped by their L1 tag
+    pub labels: Vec<String>,
+    // documents of each label
+    pub totals: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CooccurrenceMatrix {
+    pub rows: Axis,
+    pub columns: Axis,
+    // counts[i][j]: documents tagged with both rows.labels[i] and columns.labels[j]
+    pub counts: Vec<Vec<usize>>,
+    pub max: usize,
+}
+
+// the labels of one axis and the documents of each label
+async fn axis<R: Repository + ?Sized>(
+    repo: &R,
+    dimension: Dimension,
+    level: TagLevel,
+) -> anyhow::Result<(Axis, Vec<HashSet<Uuid>>)> {
+    let mut pairs = repo.read_tag_pairs(dimension).await?;
+    pairs.sort_by(|a, b| {
+        (a.l1_order, &a.l1_name, a.l2_order, &a.l2_name)
+            .cmp(&(b.l1_order, &b.l1_name, b.l2_order, &b.l2_name))
+    });
+
+    let mut labels: Vec<String> = Vec::new();
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    // L2 tag name -> index of its label
+    let mut label_of_tag: HashMap<&str, usize> = HashMap::new();
+    for pair in &pairs {
+        let name = match level {
+            TagLevel::L1 => &pair.l1_name,
+            TagLevel::L2 => &pair.l2_name,
+        };
+        let i = *index.entry(name).or_insert_with(|| {
+            labels.push(name.clone());
+            labels.len() - 1
+        });
+        label_of_tag.entry(&pair.l2_name).or_insert(i);
+    }
+
+    let mut docs = vec![HashSet::new(); labels.len()];
+    for link in repo.read_document_tags(dimension).await? {
+        if let Some(&i) = label_of_tag.get(link.name.as_str()) {
+            docs[i].insert(link.docid);
+        }
+    }
+
+    let axis = Axis {
+        dimension: dimension.as_str(),
+        level: match level {
+            TagLevel::L1 => 1,
+            TagLevel::L2 => 2,
+        },
+        labels,
+        totals: docs.iter().map(|docs| docs.len()).collect(),
+    };
+    Ok((axis, docs))
+}
+
+// rows and columns may be the same dimension, the diagonal then holds the totals
+pub async fn cooccurrence<R: Repository + ?Sized>(
+    repo: &R,
+    rows: Dimension,
+    columns: Dimension,
+    level: TagLevel,
+) -> anyhow::Result<CooccurrenceMatrix> {
+    let (rows, row_docs) = axis(repo, rows, level).await?;
+    let (columns, column_docs) = axis(repo, columns, level).await?;
+
+    let counts: Vec<Vec<usize>> = row_docs
+        .iter()
+        .map(|row| {
+            column_docs
+                .iter()
+                .map(|column| row.intersection(column).count())
+                .collect()
+        })
+        .collect();
+    let max = counts.iter().flatten().copied().max().unwrap_or(0);
+
+    Ok(CooccurrenceMatrix {
+        rows,
+        columns,
+        counts,
+        max,
+    })
+}
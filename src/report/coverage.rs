@@ -0,0 +1,242 @@
+//! ASPICE coverage report.
+//! For every MF L2 tag of the requirements (optionally split by their project), list
+//! the requirements and which V-model steps already have documents.
+
+use crate::database::models::Aspice;
+use anyhow::Context;
+use rust_xlsxwriter::Workbook;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use uuid::Uuid;
+
+#[derive(Debug, Default, Clone)]
+pub struct CoverageFilter {
+    // select requirements by their own MfL2 / ProjectL2 tag, not the documents linked to them
+    pub mf: Option<String>,
+    pub project: Option<String>,
+    // split every MF module by ProjectL2 tag
+    pub by_project: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequirementCoverage {
+    pub id: Uuid,
+    pub name: String,
+    pub covered: Vec<Aspice>,
+    pub missing: Vec<Aspice>,
+    pub coverage: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MfCoverage {
+    pub mf: String,
+    pub project: Option<String>,
+    pub requirements: Vec<RequirementCoverage>,
+    pub covered_steps: usize,
+    pub total_steps: usize,
+    pub coverage: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CoverageReport {
+    pub modules: Vec<MfCoverage>,
+    pub covered_steps: usize,
+    pub total_steps: usize,
+    pub coverage: f64,
+}
+
+fn percent(covered: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    covered as f64 * 100.0 / total as f64
+}
+
+pub async fn aspice_coverage(
+    pool: &sqlx::PgPool,
+    filter: &CoverageFilter,
+) -> anyhow::Result<CoverageReport> {
+    // mf_name | project_name | requirement | aspice_step of a downstream document.
+    // Requirements are grouped by their own tags; one without downstream documents
+    // still has a row (with a null step), so its missing steps are reported.
+    // Requirements without an MF tag belong to no module.
+    let records = sqlx::query!(
+        r#"
+        SELECT mfl2.name AS "mf_name!", projectl2.name AS "project_name?",
+            requirements.id AS "requirement!",
+            document_aspice_mapping.aspice_step AS "aspice_step?: Aspice"
+        FROM documents AS requirements
+        INNER JOIN documentmf ON requirements.id = documentmf.docid
+        INNER JOIN mfl2 ON documentmf.mfid = mfl2.id
+        LEFT JOIN documentproject ON requirements.id = documentproject.docid
+        LEFT JOIN projectl2 ON documentproject.projectid = projectl2.id
+        LEFT JOIN document_requirement ON requirements.id = document_requirement.requirementid
+        LEFT JOIN document_aspice_mapping ON document_requirement.docid = document_aspice_mapping.docid
+        WHERE (requirements.id IN (
+                SELECT docid FROM document_aspice_mapping WHERE aspice_step = '需求'
+            )
+            OR requirements.id IN (SELECT requirementid FROM document_requirement))
+        AND ($1::text IS NULL OR mfl2.name = $1)
+        AND ($2::text IS NULL OR projectl2.name = $2)
+    "#,
+        filter.mf,
+        filter.project,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to query aspice coverage")?;
+
+    let mut grouped: BTreeMap<(String, Option<String>), BTreeMap<Uuid, BTreeSet<Aspice>>> =
+        BTreeMap::new();
+    for record in records {
+        let project = if filter.by_project {
+            record.project_name
+        } else {
+            None
+        };
+        let steps = grouped
+            .entry((record.mf_name, project))
+            .or_default()
+            .entry(record.requirement)
+            .or_default();
+        if let Some(step) = record.aspice_step {
+            steps.insert(step);
+        }
+    }
+
+    // the requirement document covers its own steps (usually 需求)
+    let requirement_ids = grouped
+        .values()
+        .flat_map(|requirements| requirements.keys().cloned())
+        .collect::<BTreeSet<Uuid>>()
+        .into_iter()
+        .collect::<Vec<Uuid>>();
+    let requirement_records = sqlx::query!(
+        r#"
+        SELECT documents.id, documents.name,
+            document_aspice_mapping.aspice_step AS "aspice_step?: Aspice"
+        FROM documents
+        LEFT JOIN document_aspice_mapping ON documents.id = document_aspice_mapping.docid
+        WHERE documents.id = ANY($1)
+    "#,
+        &requirement_ids,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to query requirement documents")?;
+
+    let mut requirement_names = HashMap::new();
+    let mut requirement_steps: HashMap<Uuid, BTreeSet<Aspice>> = HashMap::new();
+    for record in requirement_records {
+        requirement_names.insert(record.id, record.name);
+        if let Some(step) = record.aspice_step {
+            requirement_steps.entry(record.id).or_default().insert(step);
+        }
+    }
+
+    let mut modules = Vec::new();
+    for ((mf, project), requirements) in grouped.into_iter() {
+        let mut requirement_vec = Vec::new();
+        for (id, mut steps) in requirements.into_iter() {
+            if let Some(own_steps) = requirement_steps.get(&id) {
+                steps.extend(own_steps.iter().cloned());
+            }
+            let (covered, missing): (Vec<Aspice>, Vec<Aspice>) =
+                Aspice::all().into_iter().partition(|step| steps.contains(step));
+            requirement_vec.push(RequirementCoverage {
+                id,
+                name: requirement_names
+                    .get(&id)
+                    .cloned()
+                    .unwrap_or_else(|| id.to_string()),
+                coverage: percent(covered.len(), Aspice::all().len()),
+                covered,
+                missing,
+            });
+        }
+        requirement_vec.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let covered_steps = requirement_vec.iter().map(|r| r.covered.len()).sum();
+        let total_steps = requirement_vec.len() * Aspice::all().len();
+        modules.push(MfCoverage {
+            mf,
+            project,
+            requirements: requirement_vec,
+            covered_steps,
+            total_steps,
+            coverage: percent(covered_steps, total_steps),
+        });
+    }
+
+    let covered_steps = modules.iter().map(|m| m.covered_steps).sum();
+    let total_steps = modules.iter().map(|m| m.total_steps).sum();
+    Ok(CoverageReport {
+        modules,
+        covered_steps,
+        total_steps,
+        coverage: percent(covered_steps, total_steps),
+    })
+}
+
+impl CoverageReport {
+    // mf | project | requirement | 需求 | 架构 | 详设 | 单测 | 集测 | 路测 | coverage
+    fn rows(&self) -> Vec<Vec<String>> {
+        let mut header = vec![
+            "mf".to_string(),
+            "project".to_string(),
+            "requirement".to_string(),
+        ];
+        header.extend(Aspice::all().iter().map(|step| step.as_str().to_string()));
+        header.push("coverage".to_string());
+
+        let mut rows = vec![header];
+        for module in &self.modules {
+            for requirement in &module.requirements {
+                let mut row = vec![
+                    module.mf.clone(),
+                    module.project.clone().unwrap_or_default(),
+                    requirement.name.clone(),
+                ];
+                row.extend(Aspice::all().iter().map(|step| {
+                    if requirement.covered.contains(step) {
+                        "Y".to_string()
+                    } else {
+                        String::new()
+                    }
+                }));
+                row.push(format!("{:.1}%", requirement.coverage));
+                rows.push(row);
+            }
+        }
+        let mut total_row = vec!["total".to_string()];
+        total_row.resize(rows[0].len() - 1, String::new());
+        total_row.push(format!("{:.1}%", self.coverage));
+        rows.push(total_row);
+        rows
+    }
+
+    pub fn to_csv(&self) -> anyhow::Result<Vec<u8>> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        for row in self.rows() {
+            writer.write_record(&row)?;
+        }
+        writer.flush()?;
+        writer
+            .into_inner()
+            .context("failed to export coverage report as csv")
+    }
+
+    pub fn to_xlsx(&self) -> anyhow::Result<Vec<u8>> {
+        let mut workbook = Workbook::new();
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name("ASPICE覆盖率")?;
+        for (row_num, row) in self.rows().iter().enumerate() {
+            for (col_num, cell) in row.iter().enumerate() {
+                worksheet.write_string(row_num as u32, col_num as u16, cell)?;
+            }
+        }
+        workbook
+            .save_to_buffer()
+            .context("failed to export coverage report as xlsx")
+    }
+}
@@ -0,0 +1,398 @@
+//! The architecture map as a graph of documents and tags, and its export to
+//! Graphviz DOT, GraphML and Mermaid for design reviews.
+
+use crate::database::models::*;
+use crate::database::repository::Repository;
+use anyhow::anyhow;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NodeKind {
+    // documents with the 需求 step, or required by other documents
+    Requirement,
+    Document,
+    Tag(Dimension, TagLevel),
+}
+
+impl NodeKind {
+    // requirement, document, arch_l1, component_l2, ...
+    pub fn name(&self) -> String {
+        match self {
+            NodeKind::Requirement => "requirement".to_string(),
+            NodeKind::Document => "document".to_string(),
+            NodeKind::Tag(dimension, level) => format!(
+                "{}_{}",
+                dimension.as_str(),
+                match level {
+                    TagLevel::L1 => "l1",
+                    TagLevel::L2 => "l2",
+                }
+            ),
+        }
+    }
+
+    // also takes the dimension names, e.g. system_l2 for component_l2
+    pub fn from_name(s: &str) -> Option<NodeKind> {
+        match s {
+            "requirement" => Some(NodeKind::Requirement),
+            "document" => Some(NodeKind::Document),
+            _ => {
+                let (dimension, level) = s.rsplit_once('_')?;
+                Some(NodeKind::Tag(
+                    Dimension::from_name(dimension)?,
+                    TagLevel::from_name(level)?,
+                ))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EdgeKind {
+    // L2 tag -> its L1 tag
+    BelongsTo,
+    // document -> L2 tag
+    Tagged,
+    // document -> requirement
+    Requires,
+    Refines,
+    Verifies,
+    Implements,
+}
+
+impl EdgeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EdgeKind::BelongsTo => "belongs_to",
+            EdgeKind::Tagged => "tagged",
+            EdgeKind::Requires => "requires",
+            EdgeKind::Refines => "refines",
+            EdgeKind::Verifies => "verifies",
+            EdgeKind::Implements => "implements",
+        }
+    }
+}
+
+impl From<TraceLinkType> for EdgeKind {
+    fn from(link_type: TraceLinkType) -> EdgeKind {
+        match link_type {
+            TraceLinkType::Refines => EdgeKind::Refines,
+            TraceLinkType::Verifies => EdgeKind::Verifies,
+            TraceLinkType::Implements => EdgeKind::Implements,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Node {
+    // n0, n1, ... in node order, usable as identifier in every format
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub label: String,
+    // link of a document
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Edge {
+    pub source: String,
+    pub target: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+// nodes are keyed by kind and name, tags are looked up by name everywhere else too
+type NodeKey = (NodeKind, String);
+
+#[derive(Default)]
+struct Builder {
+    nodes: BTreeMap<NodeKey, Option<String>>,
+    edges: BTreeSet<(NodeKey, NodeKey, EdgeKind)>,
+}
+
+impl Builder {
+    fn node(&mut self, kind: NodeKind, name: &str) -> NodeKey {
+        let key = (kind, name.to_string());
+        self.nodes.entry(key.clone()).or_default();
+        key
+    }
+
+    fn edge(&mut self, source: NodeKey, target: NodeKey, kind: EdgeKind) {
+        self.edges.insert((source, target, kind));
+    }
+}
+
+pub async fn graph<R: Repository + ?Sized>(repo: &R) -> anyhow::Result<Graph> {
+    let mut builder = Builder::default();
+
+    let docs = repo.read_documents().await?;
+    let mut doc_aspices = HashMap::new();
+    repo.read_exist_document_aspice(&mut doc_aspices).await?;
+    let mut doc_requirements: HashMap<Uuid, HashSet<String>> = HashMap::new();
+    repo.read_exist_document_requirement(&mut doc_requirements)
+        .await?;
+    let required: HashSet<&str> = doc_requirements.values().flatten().map(|name| name.as_str()).collect();
+
+    let mut doc_nodes = HashMap::new();
+    for doc in &docs {
+        let is_requirement = doc_aspices
+            .get(&doc.id)
+            .is_some_and(|steps| steps.contains(&Aspice::需求))
+            || required.contains(doc.name.as_str());
+        let kind = match is_requirement {
+            true => NodeKind::Requirement,
+            false => NodeKind::Document,
+        };
+        let key = builder.node(kind, &doc.name);
+        let address = Some(doc.link.clone()).filter(|link| !link.is_empty());
+        builder.nodes.insert(key.clone(), address);
+        doc_nodes.insert(doc.id, key);
+    }
+    let doc_node = |id: &Uuid| {
+        doc_nodes
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("document {} does not exist", id))
+    };
+
+    for dimension in Dimension::all() {
+        for pair in repo.read_tag_pairs(dimension).await? {
+            let l1 = builder.node(NodeKind::Tag(dimension, TagLevel::L1), &pair.l1_name);
+            let l2 = builder.node(NodeKind::Tag(dimension, TagLevel::L2), &pair.l2_name);
+            builder.edge(l2, l1, EdgeKind::BelongsTo);
+        }
+        for link in repo.read_document_tags(dimension).await? {
+            let tag = builder.node(NodeKind::Tag(dimension, TagLevel::L2), &link.name);
+            builder.edge(doc_node(&link.docid)?, tag, EdgeKind::Tagged);
+        }
+    }
+
+    let requirement_nodes: HashMap<&str, &NodeKey> = doc_nodes
+        .values()
+        .filter(|(kind, _)| *kind == NodeKind::Requirement)
+        .map(|key| (key.1.as_str(), key))
+        .collect();
+    for (doc_id, requirements) in &doc_requirements {
+        for requirement in requirements {
+            let target = requirement_nodes
+                .get(requirement.as_str())
+                .map(|key| (*key).clone())
+                .ok_or_else(|| anyhow!("requirement '{}' does not exist", requirement))?;
+            builder.edge(doc_node(doc_id)?, target, EdgeKind::Requires);
+        }
+    }
+    for link in repo.read_document_trace_links().await? {
+        let source = doc_node(&link.source_docid)?;
+        let target = doc_node(&link.target_docid)?;
+        builder.edge(source, target, link.link_type.into());
+    }
+
+    Ok(finish(builder))
+}
+
+fn finish(builder: Builder) -> Graph {
+    let ids: HashMap<&NodeKey, String> = builder
+        .nodes
+        .keys()
+        .enumerate()
+        .map(|(i, key)| (key, format!("n{}", i)))
+        .collect();
+    let nodes = builder
+        .nodes
+        .iter()
+        .map(|(key, address)| Node {
+            id: ids[key].clone(),
+            kind: key.0.name(),
+            label: key.1.clone(),
+            address: address.clone(),
+        })
+        .collect();
+    let edges = builder
+        .edges
+        .iter()
+        .map(|(source, target, kind)| Edge {
+            source: ids[source].clone(),
+            target: ids[target].clone(),
+            kind: kind.as_str(),
+        })
+        .collect();
+    Graph { nodes, edges }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// mermaid labels are quoted, quotes inside are written as entity codes
+fn mermaid_escape(text: &str) -> String {
+    text.replace('"', "#quot;")
+}
+
+fn dot_shape(kind: &str) -> &'static str {
+    match kind {
+        "requirement" => "note",
+        "document" => "box",
+        kind if kind.ends_with("_l1") => "folder",
+        _ => "ellipse",
+    }
+}
+
+impl Graph {
+    // around: `<type>:<name>`, e.g. `component_l2:激光雷达`; `document` also finds requirements
+    pub fn find(&self, around: &str) -> anyhow::Result<&Node> {
+        let (kind, name) = around
+            .split_once(':')
+            .ok_or_else(|| anyhow!("expected <type>:<name>, got '{}'", around))?;
+        let kind = NodeKind::from_name(kind).ok_or_else(|| anyhow!("unknown node type '{}'", kind))?;
+        self.nodes
+            .iter()
+            .find(|node| {
+                node.label == name
+                    && (node.kind == kind.name()
+                        || (kind == NodeKind::Document && node.kind == "requirement"))
+            })
+            .ok_or_else(|| anyhow!("no {} named '{}'", kind.name(), name))
+    }
+
+    // the nodes at most `hops` edges away from `around`, in either direction, keeping their ids
+    pub fn around(&self, around: &str, hops: usize) -> anyhow::Result<Graph> {
+        let start = self.find(around)?;
+        let mut neighbours: HashMap<&str, Vec<&str>> = HashMap::new();
+        for edge in &self.edges {
+            neighbours.entry(&edge.source).or_default().push(&edge.target);
+            neighbours.entry(&edge.target).or_default().push(&edge.source);
+        }
+
+        let mut reached = HashSet::from([start.id.as_str()]);
+        let mut queue = VecDeque::from([(start.id.as_str(), 0)]);
+        while let Some((node, hop)) = queue.pop_front() {
+            if hop == hops {
+                continue;
+            }
+            for next in neighbours.get(node).into_iter().flatten() {
+                if reached.insert(next) {
+                    queue.push_back((next, hop + 1));
+                }
+            }
+        }
+
+        Ok(Graph {
+            nodes: self
+                .nodes
+                .iter()
+                .filter(|node| reached.contains(node.id.as_str()))
+                .cloned()
+                .collect(),
+            edges: self
+                .edges
+                .iter()
+                .filter(|edge| reached.contains(edge.source.as_str()) && reached.contains(edge.target.as_str()))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph arch_map {\n    rankdir=LR;\n");
+        for node in &self.nodes {
+            writeln!(
+                out,
+                "    {} [label=\"{}\", type=\"{}\", shape={}];",
+                node.id,
+                dot_escape(&node.label),
+                node.kind,
+                dot_shape(&node.kind)
+            )
+            .unwrap();
+        }
+        for edge in &self.edges {
+            writeln!(
+                out,
+                "    {} -> {} [label=\"{}\", type=\"{}\"];",
+                edge.source, edge.target, edge.kind, edge.kind
+            )
+            .unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"type\" for=\"node\" attr.name=\"type\" attr.type=\"string\"/>\n",
+            "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+            "  <key id=\"address\" for=\"node\" attr.name=\"address\" attr.type=\"string\"/>\n",
+            "  <key id=\"edge_type\" for=\"edge\" attr.name=\"type\" attr.type=\"string\"/>\n",
+            "  <graph id=\"arch_map\" edgedefault=\"directed\">\n",
+        ));
+        for node in &self.nodes {
+            writeln!(out, "    <node id=\"{}\">", node.id).unwrap();
+            writeln!(out, "      <data key=\"type\">{}</data>", node.kind).unwrap();
+            writeln!(out, "      <data key=\"label\">{}</data>", xml_escape(&node.label)).unwrap();
+            if let Some(address) = &node.address {
+                writeln!(out, "      <data key=\"address\">{}</data>", xml_escape(address)).unwrap();
+            }
+            out.push_str("    </node>\n");
+        }
+        for (i, edge) in self.edges.iter().enumerate() {
+            writeln!(
+                out,
+                "    <edge id=\"e{}\" source=\"{}\" target=\"{}\">",
+                i, edge.source, edge.target
+            )
+            .unwrap();
+            writeln!(out, "      <data key=\"edge_type\">{}</data>", edge.kind).unwrap();
+            out.push_str("    </edge>\n");
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart LR\n");
+        for node in &self.nodes {
+            writeln!(
+                out,
+                "    {}[\"{}\"]:::{}",
+                node.id,
+                mermaid_escape(&node.label),
+                node.kind
+            )
+            .unwrap();
+        }
+        for edge in &self.edges {
+            writeln!(out, "    {} -->|{}| {}", edge.source, edge.kind, edge.target).unwrap();
+        }
+        // the node type as class, so every type gets its own style
+        let kinds: BTreeSet<&str> = self.nodes.iter().map(|node| node.kind.as_str()).collect();
+        for kind in kinds {
+            let fill = match kind {
+                "requirement" => "#fde2e2",
+                "document" => "#e2ecfd",
+                kind if kind.ends_with("_l1") => "#e2fde8",
+                _ => "#fdf6e2",
+            };
+            writeln!(out, "    classDef {} fill:{}", kind, fill).unwrap();
+        }
+        out
+    }
+}
@@ -0,0 +1,63 @@
+//! Changes of the map between two points in time, folded from the audit log.
+
+use crate::database::models::AuditEntry;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+// net change of one row: its value before the first and after the last entry
+#[derive(Debug, Clone, Serialize)]
+pub struct RowChange {
+    pub table_name: String,
+    pub row_key: String,
+    pub document_id: Option<Uuid>,
+    pub change: ChangeKind,
+    pub old_row: Option<JsonValue>,
+    pub new_row: Option<JsonValue>,
+    // everyone and every sync run that touched the row in between
+    pub changed_by: BTreeSet<String>,
+    pub sync_runs: BTreeSet<Uuid>,
+}
+
+// `entries` in audit log order; rows changed back to their old value are left out
+pub fn diff(entries: &[AuditEntry]) -> Vec<RowChange> {
+    let mut rows: BTreeMap<(&str, &str), RowChange> = BTreeMap::new();
+    for entry in entries {
+        let row = rows
+            .entry((&entry.table_name, &entry.row_key))
+            .or_insert_with(|| RowChange {
+                table_name: entry.table_name.clone(),
+                row_key: entry.row_key.clone(),
+                document_id: entry.document_id,
+                change: ChangeKind::Changed,
+                old_row: entry.old_row.clone(),
+                new_row: None,
+                changed_by: BTreeSet::new(),
+                sync_runs: BTreeSet::new(),
+            });
+        row.new_row = entry.new_row.clone();
+        row.changed_by.extend(entry.changed_by.clone());
+        row.sync_runs.extend(entry.sync_run_id);
+    }
+
+    rows.into_values()
+        .filter_map(|mut row| {
+            row.change = match (&row.old_row, &row.new_row) {
+                (None, Some(_)) => ChangeKind::Added,
+                (Some(_), None) => ChangeKind::Removed,
+                (old_row, new_row) if old_row == new_row => return None,
+                _ => ChangeKind::Changed,
+            };
+            Some(row)
+        })
+        .collect()
+}
@@ -0,0 +1,208 @@
+//! Impact analysis: everything connected to a tag or document through shared documents,
+//! found by walking the graph of `graph::graph` outwards from it.
+
+use super::graph::{Graph, Node, NodeKind};
+use crate::database::models::*;
+use crate::database::repository::Repository;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+#[derive(Debug, Clone)]
+pub struct ImpactOptions {
+    // edges walked from the start, a tag reaches its documents at 1 and their tags at 2
+    pub depth: usize,
+    // also walk requirement and trace links between documents
+    pub trace_links: bool,
+}
+
+impl Default for ImpactOptions {
+    fn default() -> Self {
+        ImpactOptions {
+            depth: 2,
+            trace_links: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Affected {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub label: String,
+    // L1 tag of an L2 tag
+    pub l1: Option<String>,
+    pub address: Option<String>,
+    pub distance: usize,
+    // `<type>:<label>` of every node from the start to this one
+    pub path: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StepImpact {
+    // None for documents without ASPICE step
+    pub step: Option<Aspice>,
+    pub documents: Vec<Affected>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImpactReport {
+    pub start: String,
+    pub depth: usize,
+    // arch, component, mf, project -> affected tags
+    pub dimensions: BTreeMap<&'static str, Vec<Affected>>,
+    // affected documents by V-model step, a document with several steps is listed under each
+    pub aspice: Vec<StepImpact>,
+}
+
+// document name -> its ASPICE steps
+pub async fn read_aspice_steps<R: Repository + ?Sized>(
+    repo: &R,
+) -> anyhow::Result<HashMap<String, BTreeSet<Aspice>>> {
+    let mut docs = HashMap::new();
+    repo.read_exist_documents(&mut docs).await?;
+    let mut doc_aspices = HashMap::new();
+    repo.read_exist_document_aspice(&mut doc_aspices).await?;
+
+    Ok(docs
+        .into_iter()
+        .map(|(name, id)| {
+            let steps = doc_aspices.remove(&id).unwrap_or_default();
+            (name, steps.into_iter().collect())
+        })
+        .collect())
+}
+
+fn walks(edge_kind: &str, options: &ImpactOptions) -> bool {
+    match edge_kind {
+        "tagged" => true,
+        "requires" | "refines" | "verifies" | "implements" => options.trace_links,
+        _ => false,
+    }
+}
+
+// `<type>:<label>` of the nodes from the start to `id`
+fn path<'a>(
+    nodes: &HashMap<&'a str, &Node>,
+    reached_from: &HashMap<&'a str, Option<&'a str>>,
+    mut id: &'a str,
+) -> Vec<String> {
+    let mut path = Vec::new();
+    loop {
+        let node = nodes[id];
+        path.push(format!("{}:{}", node.kind, node.label));
+        match reached_from[id] {
+            Some(from) => id = from,
+            None => break,
+        }
+    }
+    path.reverse();
+    path
+}
+
+// start: `<type>:<name>` as for `Graph::around`
+pub fn impact(
+    graph: &Graph,
+    aspice_steps: &HashMap<String, BTreeSet<Aspice>>,
+    start: &str,
+    options: &ImpactOptions,
+) -> anyhow::Result<ImpactReport> {
+    let nodes: HashMap<&str, &Node> = graph.nodes.iter().map(|node| (node.id.as_str(), node)).collect();
+    let start_node = graph.find(start)?;
+    let start_id = start_node.id.as_str();
+
+    let mut neighbours: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut l1_of: HashMap<&str, &str> = HashMap::new();
+    for edge in &graph.edges {
+        if edge.kind == "belongs_to" {
+            l1_of.insert(&edge.source, &nodes[edge.target.as_str()].label);
+            // an L1 tag starts from all of its L2 tags, the hierarchy is not walked otherwise
+            if edge.target == start_id {
+                neighbours.entry(start_id).or_default().push(&edge.source);
+            }
+        } else if walks(edge.kind, options) {
+            neighbours.entry(&edge.source).or_default().push(&edge.target);
+            neighbours.entry(&edge.target).or_default().push(&edge.source);
+        }
+    }
+
+    // node -> the node it was reached from, breadth first so every path is a shortest one
+    let mut reached_from: HashMap<&str, Option<&str>> = HashMap::from([(start_id, None)]);
+    let mut distance: HashMap<&str, usize> = HashMap::from([(start_id, 0)]);
+    let mut queue = VecDeque::from([start_id]);
+    while let Some(node) = queue.pop_front() {
+        if distance[node] == options.depth {
+            continue;
+        }
+        for &next in neighbours.get(node).into_iter().flatten() {
+            if !reached_from.contains_key(next) {
+                reached_from.insert(next, Some(node));
+                distance.insert(next, distance[node] + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let mut dimensions: BTreeMap<&'static str, Vec<Affected>> = Dimension::all()
+        .into_iter()
+        .map(|dimension| (dimension.as_str(), Vec::new()))
+        .collect();
+    let mut steps: BTreeMap<Option<Aspice>, Vec<Affected>> = BTreeMap::new();
+    for &id in reached_from.keys() {
+        if id == start_id {
+            continue;
+        }
+        let node = nodes[id];
+        let affected = Affected {
+            kind: node.kind.clone(),
+            label: node.label.clone(),
+            l1: l1_of.get(id).map(|l1| l1.to_string()),
+            address: node.address.clone(),
+            distance: distance[id],
+            path: path(&nodes, &reached_from, id),
+        };
+        match NodeKind::from_name(&node.kind) {
+            Some(NodeKind::Tag(dimension, _)) => {
+                dimensions.entry(dimension.as_str()).or_default().push(affected);
+            }
+            _ => {
+                let doc_steps = aspice_steps.get(&node.label).cloned().unwrap_or_default();
+                if doc_steps.is_empty() {
+                    steps.entry(None).or_default().push(affected);
+                } else {
+                    for step in doc_steps {
+                        steps.entry(Some(step)).or_default().push(affected.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    let by_distance = |a: &Affected, b: &Affected| (a.distance, &a.label).cmp(&(b.distance, &b.label));
+    for affected in dimensions.values_mut() {
+        affected.sort_by(by_distance);
+    }
+    // V-model order, documents without step last
+    let mut aspice: Vec<StepImpact> = Aspice::all()
+        .into_iter()
+        .map(|step| StepImpact {
+            documents: steps.remove(&Some(step.clone())).unwrap_or_default(),
+            step: Some(step),
+        })
+        .collect();
+    if let Some(documents) = steps.remove(&None) {
+        aspice.push(StepImpact {
+            step: None,
+            documents,
+        });
+    }
+    for step in aspice.iter_mut() {
+        step.documents.sort_by(by_distance);
+    }
+
+    Ok(ImpactReport {
+        start: format!("{}:{}", start_node.kind, start_node.label),
+        depth: options.depth,
+        dimensions,
+        aspice,
+    })
+}
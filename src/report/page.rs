@@ -0,0 +1,293 @@
+//! Cursor pagination, sorting and field selection of document lists.
+//! A list is sorted completely and cut after the cursor, which holds the sort key of the last
+//! document of the previous page, so a page neither repeats nor skips documents when others
+//! are added or removed in between.
+
+use crate::database::models::*;
+use crate::database::repository::Repository;
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub const MAX_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Name,
+    // the latest change in the audit log
+    Modified,
+    // the earliest V-model step
+    Aspice,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+impl Sort {
+    // name, modified or aspice, with a leading `-` for descending
+    pub fn from_name(s: &str) -> Option<Sort> {
+        let (descending, field) = match s.strip_prefix('-') {
+            Some(field) => (true, field),
+            None => (false, s),
+        };
+        let field = match field {
+            "name" => SortField::Name,
+            "modified" => SortField::Modified,
+            "aspice" => SortField::Aspice,
+            _ => return None,
+        };
+        Some(Sort { field, descending })
+    }
+
+    pub fn as_string(&self) -> String {
+        let field = match self.field {
+            SortField::Name => "name",
+            SortField::Modified => "modified",
+            SortField::Aspice => "aspice",
+        };
+        if self.descending {
+            format!("-{}", field)
+        } else {
+            field.to_string()
+        }
+    }
+}
+
+// where the previous page ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    sort: String,
+    #[serde(flatten)]
+    key: ItemKey,
+}
+
+impl Cursor {
+    // hex of the json, opaque to clients and safe in a query string
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        json.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub fn decode(s: &str) -> anyhow::Result<Cursor> {
+        let bytes = (0..s.len())
+            .step_by(2)
+            .map(|i| s.get(i..i + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| anyhow!("invalid cursor"))?;
+        serde_json::from_slice(&bytes).context("invalid cursor")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ItemKey {
+    // 0 for related documents, 1 for the others, so a group keeps its related documents first
+    rank: u8,
+    // the sort field, None if the document has none (no change logged, no ASPICE step)
+    key: Option<String>,
+    name: String,
+    id: Uuid,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    // None lists everything
+    pub limit: Option<usize>,
+    pub cursor: Option<Cursor>,
+    pub sort: Option<Sort>,
+    // keys kept in every document, all if None
+    pub fields: Option<Vec<String>>,
+}
+
+impl ListOptions {
+    // `allowed`: the fields of the documents of the list
+    pub fn parse(
+        limit: Option<usize>,
+        cursor: Option<&str>,
+        sort: Option<&str>,
+        fields: Option<&str>,
+        allowed: &[&str],
+    ) -> anyhow::Result<ListOptions> {
+        if let Some(limit) = limit {
+            if limit == 0 || limit > MAX_LIMIT {
+                return Err(anyhow!("limit must be between 1 and {}", MAX_LIMIT));
+            }
+        }
+        let sort = match sort {
+            Some(s) => Some(Sort::from_name(s).ok_or_else(|| {
+                anyhow!("unknown sort '{}', use name, modified or aspice with an optional '-'", s)
+            })?),
+            None => None,
+        };
+        let cursor = cursor.map(Cursor::decode).transpose()?;
+        let fields = match fields {
+            Some(fields) => {
+                let fields: Vec<String> = fields
+                    .split(',')
+                    .map(|field| field.trim().to_string())
+                    .filter(|field| !field.is_empty())
+                    .collect();
+                if let Some(unknown) = fields.iter().find(|field| !allowed.contains(&field.as_str())) {
+                    return Err(anyhow!("unknown field '{}', use {}", unknown, allowed.join(", ")));
+                }
+                Some(fields)
+            }
+            None => None,
+        };
+        let options = ListOptions {
+            limit,
+            cursor,
+            sort,
+            fields,
+        };
+        if let Some(cursor) = &options.cursor {
+            let sort = options.effective_sort().map(|sort| sort.as_string());
+            if sort.as_deref() != Some(cursor.sort.as_str()) {
+                return Err(anyhow!("the cursor belongs to sort '{}'", cursor.sort));
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn is_paged(&self) -> bool {
+        self.limit.is_some() || self.cursor.is_some()
+    }
+
+    // pages are sorted by name unless asked otherwise, whole lists keep their order
+    fn effective_sort(&self) -> Option<Sort> {
+        match self.sort {
+            Some(sort) => Some(sort),
+            None if self.is_paged() => Some(Sort {
+                field: SortField::Name,
+                descending: false,
+            }),
+            None => None,
+        }
+    }
+}
+
+// what documents are sorted by besides their name
+#[derive(Debug, Clone, Default)]
+pub struct SortKeys {
+    modified: HashMap<Uuid, DateTime<Utc>>,
+    aspice: HashMap<Uuid, Aspice>,
+}
+
+impl SortKeys {
+    // only reads what the sort of `options` needs
+    pub async fn load<R: Repository + ?Sized>(repo: &R, options: &ListOptions) -> anyhow::Result<SortKeys> {
+        let mut keys = SortKeys::default();
+        match options.effective_sort().map(|sort| sort.field) {
+            Some(SortField::Modified) => keys.modified = repo.read_document_modified().await?,
+            Some(SortField::Aspice) => {
+                let mut doc_aspices = HashMap::new();
+                repo.read_exist_document_aspice(&mut doc_aspices).await?;
+                keys.aspice = doc_aspices
+                    .into_iter()
+                    .filter_map(|(id, steps)| steps.into_iter().min().map(|step| (id, step)))
+                    .collect();
+            }
+            _ => {}
+        }
+        Ok(keys)
+    }
+
+    fn item_key(&self, item: &JsonValue, field: SortField) -> anyhow::Result<ItemKey> {
+        let id: Uuid = item["id"]
+            .as_str()
+            .and_then(|id| id.parse().ok())
+            .ok_or_else(|| anyhow!("listed document without id: {}", item))?;
+        let key = match field {
+            SortField::Name => None,
+            // fixed width, so the strings sort like the times
+            SortField::Modified => self
+                .modified
+                .get(&id)
+                .map(|at| at.to_rfc3339_opts(SecondsFormat::Micros, true)),
+            SortField::Aspice => self.aspice.get(&id).map(|step| {
+                let index = Aspice::all().iter().position(|s| s == step).unwrap_or_default();
+                index.to_string()
+            }),
+        };
+        Ok(ItemKey {
+            rank: if item["isRelated"] == JsonValue::Bool(false) { 1 } else { 0 },
+            key,
+            name: item["name"].as_str().unwrap_or_default().to_string(),
+            id,
+        })
+    }
+}
+
+fn compare(a: &ItemKey, b: &ItemKey, sort: Sort) -> Ordering {
+    let direction = |ordering: Ordering| if sort.descending { ordering.reverse() } else { ordering };
+    // documents without the sort field come last in both directions
+    let key = match (&a.key, &b.key) {
+        (Some(a), Some(b)) => direction(a.cmp(b)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+    let name = match sort.field {
+        SortField::Name => direction(a.name.cmp(&b.name)),
+        _ => a.name.cmp(&b.name),
+    };
+    a.rank.cmp(&b.rank).then(key).then(name).then(a.id.cmp(&b.id))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Page {
+    pub items: Vec<JsonValue>,
+    // documents of the whole list
+    pub total: usize,
+    // None on the last page
+    pub next_cursor: Option<String>,
+}
+
+// `items`: document objects with `id` and `name`, `isRelated` false ones are kept last
+pub fn page(items: Vec<JsonValue>, options: &ListOptions, keys: &SortKeys) -> anyhow::Result<Page> {
+    let total = items.len();
+    let mut page = Page {
+        items,
+        total,
+        next_cursor: None,
+    };
+
+    if let Some(sort) = options.effective_sort() {
+        let mut keyed = page
+            .items
+            .into_iter()
+            .map(|item| keys.item_key(&item, sort.field).map(|key| (key, item)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        keyed.sort_by(|(a, _), (b, _)| compare(a, b, sort));
+        if let Some(cursor) = &options.cursor {
+            keyed.retain(|(key, _)| compare(key, &cursor.key, sort) == Ordering::Greater);
+        }
+        if let Some(limit) = options.limit {
+            if keyed.len() > limit {
+                keyed.truncate(limit);
+                let (key, _) = &keyed[limit - 1];
+                let cursor = Cursor {
+                    sort: sort.as_string(),
+                    key: key.clone(),
+                };
+                page.next_cursor = Some(cursor.encode());
+            }
+        }
+        page.items = keyed.into_iter().map(|(_, item)| item).collect();
+    }
+
+    if let Some(fields) = &options.fields {
+        for item in page.items.iter_mut() {
+            if let JsonValue::Object(object) = item {
+                object.retain(|key, _| fields.iter().any(|field| field == key));
+            }
+        }
+    }
+    Ok(page)
+}
@@ -0,0 +1,382 @@
+//! Data quality problems of the map: unused and empty tags, documents missing tags or ASPICE
+//! steps, near-duplicate names and links, and requirement links to non-requirement documents.
+
+use crate::database::models::*;
+use crate::database::repository::Repository;
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct QualityOptions {
+    // names at least this similar (0..1, after normalising) are reported as near-duplicates
+    pub similarity: f64,
+}
+
+impl Default for QualityOptions {
+    fn default() -> Self {
+        QualityOptions { similarity: 0.9 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DocRef {
+    pub id: Uuid,
+    pub name: String,
+    pub address: String,
+}
+
+impl From<&Document> for DocRef {
+    fn from(doc: &Document) -> Self {
+        DocRef {
+            id: doc.id,
+            name: doc.name.clone(),
+            address: doc.link.clone(),
+        }
+    }
+}
+
+// an L2 tag no document carries
+#[derive(Debug, Clone, Serialize)]
+pub struct EmptyTag {
+    pub dimension: &'static str,
+    pub l1: String,
+    pub l2: String,
+}
+
+// an L1 tag without L2 tags
+#[derive(Debug, Clone, Serialize)]
+pub struct ChildlessTag {
+    pub dimension: &'static str,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UntaggedDocument {
+    #[serde(flatten)]
+    pub document: DocRef,
+    // dimensions the document has no tag in
+    pub dimensions: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimilarNames {
+    pub documents: Vec<DocRef>,
+    // 1 for names equal after normalising
+    pub similarity: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateLink {
+    pub link: String,
+    pub documents: Vec<DocRef>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NonRequirementLink {
+    pub document: DocRef,
+    pub requirement: DocRef,
+    // the steps the associated document has instead of 需求
+    pub requirement_steps: Vec<Aspice>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityReport {
+    // number of problems per list
+    pub summary: BTreeMap<&'static str, usize>,
+    pub empty_tags: Vec<EmptyTag>,
+    pub childless_tags: Vec<ChildlessTag>,
+    pub untagged_documents: Vec<UntaggedDocument>,
+    pub documents_without_aspice: Vec<DocRef>,
+    pub similar_names: Vec<SimilarNames>,
+    pub duplicate_links: Vec<DuplicateLink>,
+    pub non_requirement_links: Vec<NonRequirementLink>,
+}
+
+// lower case, full-width forms folded to ASCII, without whitespace and punctuation,
+// so "UT-目标检测" and "ut_目标检测 " compare equal
+pub fn normalize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            c => c,
+        })
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// pairs of documents whose normalised names are at least `threshold` similar
+fn similar_names(docs: &[Document], threshold: f64) -> Vec<SimilarNames> {
+    let names: Vec<Vec<char>> = docs
+        .iter()
+        .map(|doc| normalize_name(&doc.name).chars().collect())
+        .collect();
+    let mut pairs = Vec::new();
+    for i in 0..docs.len() {
+        for j in i + 1..docs.len() {
+            let (a, b) = (&names[i], &names[j]);
+            let longest = a.len().max(b.len());
+            if a.is_empty() || b.is_empty() {
+                continue;
+            }
+            // the distance is at least the difference of the lengths
+            if (a.len().min(b.len()) as f64) < threshold * longest as f64 {
+                continue;
+            }
+            let similarity = 1.0 - levenshtein(a, b) as f64 / longest as f64;
+            if similarity >= threshold {
+                pairs.push(SimilarNames {
+                    documents: vec![DocRef::from(&docs[i]), DocRef::from(&docs[j])],
+                    similarity: (similarity * 1000.0).round() / 1000.0,
+                });
+            }
+        }
+    }
+    pairs
+}
+
+// documents are compared by their links without surrounding whitespace and trailing slash
+fn duplicate_links(docs: &[Document]) -> Vec<DuplicateLink> {
+    let mut by_link: BTreeMap<&str, Vec<DocRef>> = BTreeMap::new();
+    for doc in docs {
+        let link = doc.link.trim().trim_end_matches('/');
+        if !link.is_empty() {
+            by_link.entry(link).or_default().push(DocRef::from(doc));
+        }
+    }
+    by_link
+        .into_iter()
+        .filter(|(_, documents)| documents.len() > 1)
+        .map(|(link, documents)| DuplicateLink {
+            link: link.to_string(),
+            documents,
+        })
+        .collect()
+}
+
+pub async fn data_quality<R: Repository + ?Sized>(
+    repo: &R,
+    options: &QualityOptions,
+) -> anyhow::Result<QualityReport> {
+    // ordered by name, so are all lists of documents below
+    let docs = repo.read_documents().await?;
+    let doc_by_id: HashMap<Uuid, &Document> = docs.iter().map(|doc| (doc.id, doc)).collect();
+
+    let mut empty_tags = Vec::new();
+    let mut childless_tags = Vec::new();
+    let mut missing: HashMap<Uuid, Vec<&'static str>> = HashMap::new();
+    for dimension in Dimension::all() {
+        let mut pairs = repo.read_tag_pairs(dimension).await?;
+        pairs.sort_by(|a, b| {
+            (a.l1_order, &a.l1_name, a.l2_order, &a.l2_name)
+                .cmp(&(b.l1_order, &b.l1_name, b.l2_order, &b.l2_name))
+        });
+        let links = repo.read_document_tags(dimension).await?;
+        let used: HashSet<&str> = links.iter().map(|link| link.name.as_str()).collect();
+        let tagged: HashSet<Uuid> = links.iter().map(|link| link.docid).collect();
+
+        empty_tags.extend(
+            pairs
+                .iter()
+                .filter(|pair| !used.contains(pair.l2_name.as_str()))
+                .map(|pair| EmptyTag {
+                    dimension: dimension.as_str(),
+                    l1: pair.l1_name.clone(),
+                    l2: pair.l2_name.clone(),
+                }),
+        );
+
+        let mut l1_tags = HashMap::new();
+        repo.read_exist_tags(dimension, TagLevel::L1, &mut l1_tags).await?;
+        let parents: HashSet<&str> = pairs.iter().map(|pair| pair.l1_name.as_str()).collect();
+        let mut childless: Vec<String> = l1_tags
+            .into_keys()
+            .filter(|name| !parents.contains(name.as_str()))
+            .collect();
+        childless.sort();
+        childless_tags.extend(childless.into_iter().map(|name| ChildlessTag {
+            dimension: dimension.as_str(),
+            name,
+        }));
+
+        for doc in &docs {
+            if !tagged.contains(&doc.id) {
+                missing.entry(doc.id).or_default().push(dimension.as_str());
+            }
+        }
+    }
+    let untagged_documents: Vec<UntaggedDocument> = docs
+        .iter()
+        .filter_map(|doc| {
+            missing.remove(&doc.id).map(|dimensions| UntaggedDocument {
+                document: DocRef::from(doc),
+                dimensions,
+            })
+        })
+        .collect();
+
+    let mut doc_aspices = HashMap::new();
+    repo.read_exist_document_aspice(&mut doc_aspices).await?;
+    let documents_without_aspice: Vec<DocRef> = docs
+        .iter()
+        .filter(|doc| doc_aspices.get(&doc.id).is_none_or(|steps| steps.is_empty()))
+        .map(DocRef::from)
+        .collect();
+
+    let mut doc_requirements = HashMap::new();
+    repo.read_exist_document_requirement(&mut doc_requirements).await?;
+    let doc_by_name: HashMap<&str, &Document> = docs.iter().map(|doc| (doc.name.as_str(), doc)).collect();
+    let mut non_requirement_links = Vec::new();
+    for (docid, requirements) in doc_requirements {
+        let doc = doc_by_id
+            .get(&docid)
+            .with_context(|| format!("document {} of a requirement link does not exist", docid))?;
+        for name in requirements {
+            let requirement = doc_by_name
+                .get(name.as_str())
+                .with_context(|| format!("requirement {} does not exist", name))?;
+            let mut steps: Vec<Aspice> = doc_aspices
+                .get(&requirement.id)
+                .map(|steps| steps.iter().cloned().collect())
+                .unwrap_or_default();
+            if steps.contains(&Aspice::需求) {
+                continue;
+            }
+            steps.sort();
+            non_requirement_links.push(NonRequirementLink {
+                document: DocRef::from(*doc),
+                requirement: DocRef::from(*requirement),
+                requirement_steps: steps,
+            });
+        }
+    }
+    non_requirement_links.sort_by(|a, b| {
+        (&a.document.name, &a.requirement.name).cmp(&(&b.document.name, &b.requirement.name))
+    });
+
+    let similar_names = similar_names(&docs, options.similarity);
+    let duplicate_links = duplicate_links(&docs);
+
+    let summary = BTreeMap::from([
+        ("empty_tags", empty_tags.len()),
+        ("childless_tags", childless_tags.len()),
+        ("untagged_documents", untagged_documents.len()),
+        ("documents_without_aspice", documents_without_aspice.len()),
+        ("similar_names", similar_names.len()),
+        ("duplicate_links", duplicate_links.len()),
+        ("non_requirement_links", non_requirement_links.len()),
+    ]);
+    Ok(QualityReport {
+        summary,
+        empty_tags,
+        childless_tags,
+        untagged_documents,
+        documents_without_aspice,
+        similar_names,
+        duplicate_links,
+        non_requirement_links,
+    })
+}
+
+impl QualityReport {
+    // one row per problem: problem, dimension, subject, detail
+    fn rows(&self) -> Vec<Vec<String>> {
+        let names = |docs: &[DocRef]| {
+            docs.iter()
+                .map(|doc| doc.name.as_str())
+                .collect::<Vec<_>>()
+                .join(" | ")
+        };
+        let mut rows = vec![vec![
+            "problem".to_string(),
+            "dimension".to_string(),
+            "subject".to_string(),
+            "detail".to_string(),
+        ]];
+        for tag in &self.empty_tags {
+            rows.push(vec![
+                "empty_tag".to_string(),
+                tag.dimension.to_string(),
+                format!("{}/{}", tag.l1, tag.l2),
+                String::new(),
+            ]);
+        }
+        for tag in &self.childless_tags {
+            rows.push(vec![
+                "childless_tag".to_string(),
+                tag.dimension.to_string(),
+                tag.name.clone(),
+                String::new(),
+            ]);
+        }
+        for doc in &self.untagged_documents {
+            rows.push(vec![
+                "untagged_document".to_string(),
+                doc.dimensions.join(","),
+                doc.document.name.clone(),
+                doc.document.address.clone(),
+            ]);
+        }
+        for doc in &self.documents_without_aspice {
+            rows.push(vec![
+                "document_without_aspice".to_string(),
+                String::new(),
+                doc.name.clone(),
+                doc.address.clone(),
+            ]);
+        }
+        for pair in &self.similar_names {
+            rows.push(vec![
+                "similar_names".to_string(),
+                String::new(),
+                names(&pair.documents),
+                format!("{:.3}", pair.similarity),
+            ]);
+        }
+        for link in &self.duplicate_links {
+            rows.push(vec![
+                "duplicate_link".to_string(),
+                String::new(),
+                names(&link.documents),
+                link.link.clone(),
+            ]);
+        }
+        for link in &self.non_requirement_links {
+            let steps: Vec<&str> = link.requirement_steps.iter().map(Aspice::as_str).collect();
+            rows.push(vec![
+                "non_requirement_link".to_string(),
+                String::new(),
+                link.document.name.clone(),
+                format!("{} ({})", link.requirement.name, steps.join(",")),
+            ]);
+        }
+        rows
+    }
+
+    pub fn to_csv(&self) -> anyhow::Result<Vec<u8>> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        for row in self.rows() {
+            writer.write_record(&row)?;
+        }
+        writer.flush()?;
+        writer
+            .into_inner()
+            .context("failed to export data quality report as csv")
+    }
+}
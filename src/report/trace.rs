@@ -0,0 +1,307 @@
+//! Trace links between documents along the V-model: walking a chain upward or downward
+//! and checking every link for broken V-model order and cycles.
+
+use crate::database::models::*;
+use crate::database::repository::Repository;
+use serde_json::json;
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use uuid::Uuid;
+
+// 文档追溯图，数据量比较小，一次全部读入内存
+#[derive(Debug, Clone, Default)]
+pub struct TraceGraph {
+    docs: HashMap<Uuid, Document>,
+    steps: HashMap<Uuid, BTreeSet<Aspice>>,
+    // source -> [(target, link_type)]
+    upstream: HashMap<Uuid, Vec<(Uuid, TraceLinkType)>>,
+    // target -> [(source, link_type)]
+    downstream: HashMap<Uuid, Vec<(Uuid, TraceLinkType)>>,
+}
+
+impl TraceGraph {
+    // links may point at documents missing from `docs`, they are reported as broken
+    pub fn new(
+        docs: Vec<Document>,
+        steps: HashMap<Uuid, HashSet<Aspice>>,
+        links: Vec<DocumentTraceLink>,
+    ) -> TraceGraph {
+        let mut graph = TraceGraph {
+            docs: docs.into_iter().map(|doc| (doc.id, doc)).collect(),
+            steps: steps
+                .into_iter()
+                .map(|(docid, steps)| (docid, steps.into_iter().collect()))
+                .collect(),
+            ..TraceGraph::default()
+        };
+        for link in links {
+            graph
+                .upstream
+                .entry(link.source_docid)
+                .or_default()
+                .push((link.target_docid, link.link_type));
+            graph
+                .downstream
+                .entry(link.target_docid)
+                .or_default()
+                .push((link.source_docid, link.link_type));
+        }
+        // the walk follows links in a fixed order
+        for edges in graph.upstream.values_mut().chain(graph.downstream.values_mut()) {
+            edges.sort_by_key(|(id, _)| *id);
+        }
+        graph
+    }
+
+    pub async fn load<R: Repository + ?Sized>(repo: &R) -> anyhow::Result<TraceGraph> {
+        let mut steps = HashMap::new();
+        repo.read_exist_document_aspice(&mut steps).await?;
+        Ok(TraceGraph::new(
+            repo.read_documents().await?,
+            steps,
+            repo.read_document_trace_links().await?,
+        ))
+    }
+
+    pub fn contains(&self, id: &Uuid) -> bool {
+        self.docs.contains_key(id)
+    }
+
+    fn doc_json(&self, id: &Uuid) -> JsonValue {
+        let steps = self
+            .steps
+            .get(id)
+            .map(|steps| steps.iter().map(|step| step.as_str()).collect::<Vec<&str>>())
+            .unwrap_or_default();
+        match self.docs.get(id) {
+            Some(doc) => json!({
+                "id": doc.id.to_string(),
+                "name": doc.name,
+                "address": doc.link,
+                "steps": steps,
+            }),
+            None => json!({
+                "id": id.to_string(),
+                "steps": steps,
+            }),
+        }
+    }
+
+    // the chain above (`upward`) or below a document. Every document is expanded once:
+    // a document reached again is marked `repeated`, its links are listed where it first
+    // appeared, and one on the current path is marked `circular`
+    pub fn walk(&self, id: &Uuid, upward: bool) -> JsonValue {
+        self.walk_from(id, upward, &mut Vec::new(), &mut HashSet::new())
+    }
+
+    fn walk_from(&self, id: &Uuid, upward: bool, path: &mut Vec<Uuid>, expanded: &mut HashSet<Uuid>) -> JsonValue {
+        let mut node = self.doc_json(id);
+        path.push(*id);
+        expanded.insert(*id);
+
+        let edges = if upward {
+            self.upstream.get(id)
+        } else {
+            self.downstream.get(id)
+        };
+        let mut links = Vec::new();
+        for (next, link_type) in edges.into_iter().flatten() {
+            let document = if path.contains(next) {
+                let mut document = self.doc_json(next);
+                document["circular"] = json!(true);
+                document
+            } else if expanded.contains(next) {
+                let mut document = self.doc_json(next);
+                document["repeated"] = json!(true);
+                document
+            } else {
+                self.walk_from(next, upward, path, expanded)
+            };
+            links.push(json!({
+                "type": link_type.as_str(),
+                "document": document,
+            }));
+        }
+        node["links"] = JsonValue::Array(links);
+
+        path.pop();
+        node
+    }
+
+    // the documents a document links to (`upward`) or is linked from, each once
+    fn neighbours(&self, id: &Uuid, upward: bool) -> BTreeSet<Uuid> {
+        let edges = if upward {
+            self.upstream.get(id)
+        } else {
+            self.downstream.get(id)
+        };
+        edges.into_iter().flatten().map(|(next, _)| *next).collect()
+    }
+
+    // documents reachable from `start` without passing documents before `start`
+    fn reachable(&self, start: Uuid, upward: bool) -> HashSet<Uuid> {
+        let mut seen = HashSet::from([start]);
+        let mut todo = vec![start];
+        while let Some(id) = todo.pop() {
+            for next in self.neighbours(&id, upward) {
+                if next >= start && seen.insert(next) {
+                    todo.push(next);
+                }
+            }
+        }
+        seen
+    }
+
+    // every elementary cycle once, starting with its smallest document id (Johnson's algorithm)
+    pub fn cycles(&self) -> Vec<Vec<Uuid>> {
+        struct Search<'a> {
+            graph: &'a TraceGraph,
+            start: Uuid,
+            // the strongly connected component of `start` among the documents from `start` on
+            component: HashSet<Uuid>,
+            stack: Vec<Uuid>,
+            blocked: HashSet<Uuid>,
+            // document -> documents to unblock with it
+            blocking: HashMap<Uuid, HashSet<Uuid>>,
+            cycles: Vec<Vec<Uuid>>,
+        }
+
+        impl Search<'_> {
+            fn unblock(&mut self, id: Uuid) {
+                self.blocked.remove(&id);
+                for next in self.blocking.remove(&id).unwrap_or_default() {
+                    if self.blocked.contains(&next) {
+                        self.unblock(next);
+                    }
+                }
+            }
+
+            fn circuit(&mut self, id: Uuid) -> bool {
+                let mut found = false;
+                self.stack.push(id);
+                self.blocked.insert(id);
+                let nexts: Vec<Uuid> = self
+                    .graph
+                    .neighbours(&id, true)
+                    .into_iter()
+                    .filter(|next| self.component.contains(next))
+                    .collect();
+                for next in nexts.iter() {
+                    if *next == self.start {
+                        self.cycles.push(self.stack.clone());
+                        found = true;
+                    } else if !self.blocked.contains(next) && self.circuit(*next) {
+                        found = true;
+                    }
+                }
+                if found {
+                    self.unblock(id);
+                } else {
+                    for next in nexts {
+                        self.blocking.entry(next).or_default().insert(id);
+                    }
+                }
+                self.stack.pop();
+                found
+            }
+        }
+
+        let mut ids = self
+            .upstream
+            .iter()
+            .flat_map(|(source, edges)| {
+                std::iter::once(*source).chain(edges.iter().map(|(target, _)| *target))
+            })
+            .collect::<Vec<Uuid>>();
+        ids.sort();
+        ids.dedup();
+
+        let mut cycles = Vec::new();
+        for start in ids {
+            let below = self.reachable(start, false);
+            let component = self
+                .reachable(start, true)
+                .into_iter()
+                .filter(|id| below.contains(id))
+                .collect();
+            let mut search = Search {
+                graph: self,
+                start,
+                component,
+                stack: Vec::new(),
+                blocked: HashSet::new(),
+                blocking: HashMap::new(),
+                cycles: Vec::new(),
+            };
+            search.circuit(start);
+            cycles.extend(search.cycles);
+        }
+        cycles.sort();
+        cycles
+    }
+
+    // reason why the link breaks the V-model order, if any
+    pub fn broken_reason(&self, source: &Uuid, target: &Uuid, link_type: TraceLinkType) -> Option<&'static str> {
+        if !self.docs.contains_key(target) {
+            return Some("target document does not exist");
+        }
+        let (source_steps, target_steps) = match (self.steps.get(source), self.steps.get(target)) {
+            (Some(source_steps), Some(target_steps)) => (source_steps, target_steps),
+            _ => return Some("missing aspice step"),
+        };
+        if link_type == TraceLinkType::Verifies && !source_steps.iter().any(|step| step.is_verification()) {
+            return Some("verifies link from a non-verification document");
+        }
+        if source_steps.iter().max() <= target_steps.iter().min() {
+            return Some("source step is not downstream of target step");
+        }
+        None
+    }
+
+    /*
+    {
+        'broken': [
+            { 'source': doc, 'target': doc, 'type': 'refines', 'reason': '...' },
+        ],
+        'untraced': [doc, ...], // 非需求文档，但没有任何向上的追溯
+        'circular': [[doc_a, doc_b, ...], ...], // every cycle once, see `cycles`
+    }
+    */
+    pub fn check(&self) -> JsonValue {
+        let mut broken = Vec::new();
+        let mut sources = self.upstream.keys().cloned().collect::<Vec<Uuid>>();
+        sources.sort();
+        for source in sources.iter() {
+            for (target, link_type) in self.upstream[source].iter() {
+                if let Some(reason) = self.broken_reason(source, target, *link_type) {
+                    broken.push(json!({
+                        "source": self.doc_json(source),
+                        "target": self.doc_json(target),
+                        "type": link_type.as_str(),
+                        "reason": reason,
+                    }));
+                }
+            }
+        }
+
+        let mut untraced = self
+            .steps
+            .iter()
+            .filter(|(id, steps)| !steps.contains(&Aspice::需求) && !self.upstream.contains_key(id))
+            .map(|(id, _)| *id)
+            .collect::<Vec<Uuid>>();
+        untraced.sort();
+
+        let circular = self
+            .cycles()
+            .iter()
+            .map(|cycle| cycle.iter().map(|id| self.doc_json(id)).collect::<Vec<JsonValue>>())
+            .collect::<Vec<Vec<JsonValue>>>();
+
+        json!({
+            "broken": broken,
+            "untraced": untraced.iter().map(|id| self.doc_json(id)).collect::<Vec<JsonValue>>(),
+            "circular": circular,
+        })
+    }
+}
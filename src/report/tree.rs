@@ -0,0 +1,533 @@
+//! Tag trees and the classification of the documents of a tag.
+//! Everything is read through a `Repository`, the http handlers only pick the arguments.
+
+use crate::database::models::*;
+use super::page::{self, ListOptions, SortKeys};
+use crate::database::repository::Repository;
+use anyhow::anyhow;
+use serde_json::json;
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use uuid::Uuid;
+
+// the documents of some projects, or the whole map
+#[derive(Debug, Clone, Default)]
+pub struct ProjectScope {
+    docs: Option<HashSet<Uuid>>,
+}
+
+impl ProjectScope {
+    pub fn all() -> ProjectScope {
+        ProjectScope::default()
+    }
+
+    // `projects`: names of project L1 or L2 tags, an L1 tag stands for all of its L2 tags.
+    // The whole map if empty, an error for unknown names
+    pub async fn load<R: Repository + ?Sized>(repo: &R, projects: &[String]) -> anyhow::Result<ProjectScope> {
+        if projects.is_empty() {
+            return Ok(ProjectScope::all());
+        }
+        let pairs = repo.read_tag_pairs(Dimension::Project).await?;
+        let mut l2_names = HashSet::new();
+        for project in projects {
+            let l2: Vec<&str> = pairs
+                .iter()
+                .filter(|pair| &pair.l1_name == project || &pair.l2_name == project)
+                .map(|pair| pair.l2_name.as_str())
+                .collect();
+            if l2.is_empty() {
+                return Err(anyhow!("unknown project '{}'", project));
+            }
+            l2_names.extend(l2);
+        }
+        let docs = repo
+            .read_document_tags(Dimension::Project)
+            .await?
+            .into_iter()
+            .filter(|link| l2_names.contains(link.name.as_str()))
+            .map(|link| link.docid)
+            .collect();
+        Ok(ProjectScope { docs: Some(docs) })
+    }
+
+    pub fn contains(&self, docid: &Uuid) -> bool {
+        self.docs.as_ref().is_none_or(|docs| docs.contains(docid))
+    }
+}
+
+/*
+[
+    {
+        'label': 'techl1_tag1',
+        'level': 1,
+        'children': [
+            {
+                'label': 'techl2_tag1',
+                'level': 2,
+                'content': [], # names of the `cross` L2 tags sharing documents with techl2_tag1
+                'textNum': $num, # documents of techl2_tag1 in the scope
+            }
+        ]
+    },
+    ...
+]
+*/
+// every tag of the dimension is listed, counts and `content` only cover the documents of `scope`
+pub async fn tag_tree<R: Repository + ?Sized>(
+    repo: &R,
+    dimension: Dimension,
+    cross: Dimension,
+    scope: &ProjectScope,
+) -> anyhow::Result<JsonValue> {
+    if cross == dimension {
+        return Err(anyhow!("invalid cross module '{}'", cross.as_str()));
+    }
+
+    let pairs = repo.read_tag_pairs(dimension).await?;
+    let links = repo.read_document_tags(dimension).await?;
+    let cross_links = repo.read_document_tags(cross).await?;
+
+    let mut docs_by_tag: HashMap<&str, HashSet<Uuid>> = HashMap::new();
+    for link in links.iter().filter(|link| scope.contains(&link.docid)) {
+        docs_by_tag
+            .entry(&link.name)
+            .or_default()
+            .insert(link.docid);
+    }
+    let mut cross_by_doc: HashMap<Uuid, Vec<&str>> = HashMap::new();
+    for link in &cross_links {
+        cross_by_doc.entry(link.docid).or_default().push(&link.name);
+    }
+
+    let mut layered_tags = BTreeMap::new();
+    let mut l1_orders = HashMap::new();
+    let mut l2_orders = HashMap::new();
+    for pair in &pairs {
+        l1_orders.insert(pair.l1_name.clone(), pair.l1_order);
+        l2_orders.insert(pair.l2_name.clone(), pair.l2_order);
+        layered_tags
+            .entry(pair.l1_name.as_str())
+            .or_insert_with(Vec::new)
+            .push(pair.l2_name.as_str());
+    }
+
+    let no_docs = HashSet::new();
+    let mut result_vec = Vec::new();
+    for (l1name, l2names) in layered_tags {
+        let mut l2_vec = Vec::new();
+        for l2name in l2names {
+            let docs = docs_by_tag.get(l2name).unwrap_or(&no_docs);
+            let content: BTreeSet<&str> = docs
+                .iter()
+                .filter_map(|docid| cross_by_doc.get(docid))
+                .flatten()
+                .copied()
+                .collect();
+            l2_vec.push(json!({
+                "label": l2name,
+                "level": 2,
+                "textNum": docs.len(),
+                "content": content,
+            }));
+        }
+        sort_by_tag_order(&mut l2_vec, "label", &l2_orders);
+
+        result_vec.push(json!({
+            "label": l1name,
+            "level": 1,
+            "children": l2_vec,
+        }));
+    }
+    sort_by_tag_order(&mut result_vec, "label", &l1_orders);
+
+    Ok(JsonValue::Array(result_vec))
+}
+
+/*
+       text:[
+               {
+                   name: '传感器方案',
+                   level: 1,
+                   children: [
+                       {
+                           name: '传感器布局',
+                           level: 2,
+                           children:[
+                               {
+                                   name: 'Rocky AB面传感器配置',
+                                   address: 'https://momenta.feishu.cn/wiki/ORuBw5pT8iAUmPkkaEYcRsc1nLh'
+                               },
+                           ]
+                       },
+                   ]
+               },
+       ]
+*/
+// the tech tree restricted to the documents of a project
+pub async fn project_tree<R: Repository + ?Sized>(
+    repo: &R,
+    project_name: &str,
+) -> anyhow::Result<JsonValue> {
+    let docs = repo
+        .read_documents_by_tag(Dimension::Project, project_name)
+        .await?;
+    let pairs = repo.read_tag_pairs(Dimension::Tech).await?;
+    let links = repo.read_document_tags(Dimension::Tech).await?;
+
+    let l1_by_l2: HashMap<&str, &str> = pairs
+        .iter()
+        .map(|pair| (pair.l2_name.as_str(), pair.l1_name.as_str()))
+        .collect();
+    let l1_orders: HashMap<String, i32> = pairs
+        .iter()
+        .map(|pair| (pair.l1_name.clone(), pair.l1_order))
+        .collect();
+    let l2_orders: HashMap<String, i32> = pairs
+        .iter()
+        .map(|pair| (pair.l2_name.clone(), pair.l2_order))
+        .collect();
+
+    let mut layered_tags: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    let mut correspond_docs: HashMap<&str, Vec<JsonValue>> = HashMap::new();
+    for doc in &docs {
+        for link in links.iter().filter(|link| link.docid == doc.id) {
+            let l1name = match l1_by_l2.get(link.name.as_str()) {
+                Some(l1name) => *l1name,
+                None => continue,
+            };
+            layered_tags.entry(l1name).or_default().insert(&link.name);
+            correspond_docs.entry(&link.name).or_default().push(json!({
+                "name": doc.name,
+                "address": doc.link,
+            }));
+        }
+    }
+
+    let mut arch_vec = Vec::new();
+    for (l1name, l2names) in layered_tags {
+        let mut l2_vec: Vec<JsonValue> = l2names
+            .into_iter()
+            .map(|l2name| {
+                json!({
+                    "name": l2name,
+                    "level": 2,
+                    "children": correspond_docs.get(l2name).cloned().unwrap_or_default(),
+                })
+            })
+            .collect();
+        sort_by_tag_order(&mut l2_vec, "name", &l2_orders);
+
+        arch_vec.push(json!({
+            "name": l1name,
+            "level": 1,
+            "children": l2_vec,
+        }));
+    }
+    sort_by_tag_order(&mut arch_vec, "name", &l1_orders);
+
+    Ok(JsonValue::Array(arch_vec))
+}
+
+/*
+{
+    'tag': 'filter_tag_name',
+    'text': {
+        'all': [doc1, doc2],
+        'allCount': 2,
+        'allNextCursor': null,
+        'arch': [
+            {
+                'name': 'techl2_tag1',
+                'text': [
+                    {
+                        'name': doca_name,
+                        'address': doca_address,
+                        'isRelated': true or false,
+                        'linkStatus': null or {'ok', 'statusCode', 'finalUrl', 'error', 'checkedAt'},
+                    },
+                    doc_b,
+                    ...
+                 ],
+                'count': 12, // documents of the group, `text` may be a page of them
+                'nextCursor': null, // cursor of the next page
+            },
+            {
+                ...
+            },
+        ],
+        'component': {},
+        'mf': {},
+        'project': {},
+    }
+}
+*/
+// the dimension of an L2 tag and its documents in `scope`, tag names are looked up in the
+// order arch, component, mf, project
+async fn tag_documents<R: Repository + ?Sized>(
+    repo: &R,
+    tag_name: &str,
+    scope: &ProjectScope,
+) -> anyhow::Result<Option<(Dimension, Vec<Document>)>> {
+    for dimension in Dimension::all() {
+        let mut docs = repo.read_documents_by_tag(dimension, tag_name).await?;
+        if !docs.is_empty() {
+            docs.retain(|doc| scope.contains(&doc.id));
+            if docs.is_empty() {
+                return Ok(None);
+            }
+            return Ok(Some((dimension, docs)));
+        }
+    }
+    Ok(None)
+}
+
+async fn read_link_checks<R: Repository + ?Sized>(repo: &R) -> anyhow::Result<HashMap<Uuid, LinkCheck>> {
+    Ok(repo
+        .read_link_checks()
+        .await?
+        .into_iter()
+        .map(|check| (check.docid, check))
+        .collect())
+}
+
+// the documents of an L2 tag, grouped by the tags of the other dimensions, all of them
+// restricted to `scope`. `list` pages `all` and every group, `count` and `nextCursor` lead to the rest
+pub async fn filter_and_classify<R: Repository + ?Sized>(
+    repo: &R,
+    tag_name: &str,
+    scope: &ProjectScope,
+    list: &ListOptions,
+) -> anyhow::Result<JsonValue> {
+    let (dimension, docs) = match tag_documents(repo, tag_name, scope).await? {
+        Some(found) => found,
+        None => return Ok(json!("No documents")),
+    };
+    let checks = read_link_checks(repo).await?;
+    let keys = SortKeys::load(repo, list).await?;
+
+    let mut text = serde_json::Map::new();
+    let all = docs
+        .iter()
+        .map(|doc| doc.to_json_value(true, checks.get(&doc.id)))
+        .collect();
+    let all = page::page(all, list, &keys)?;
+    text.insert("all".to_string(), JsonValue::Array(all.items));
+    text.insert("allCount".to_string(), json!(all.total));
+    text.insert("allNextCursor".to_string(), json!(all.next_cursor));
+    for other in Dimension::all() {
+        if other == dimension {
+            continue;
+        }
+        let mut groups = group_by_tag(repo, other, &docs, scope, &checks).await?;
+        for group in groups.iter_mut() {
+            let docs = match group["text"].take() {
+                JsonValue::Array(docs) => docs,
+                _ => Vec::new(),
+            };
+            let docs = page::page(docs, list, &keys)?;
+            group["text"] = JsonValue::Array(docs.items);
+            group["count"] = json!(docs.total);
+            group["nextCursor"] = json!(docs.next_cursor);
+        }
+        if other == Dimension::Project {
+            // the main line is listed apart from the projects
+            let (mainline, projects) = groups
+                .drain(..)
+                .partition::<Vec<JsonValue>, _>(|group| group["name"] == "主线");
+            text.insert("project".to_string(), JsonValue::Array(projects));
+            text.insert("mainLine".to_string(), JsonValue::Array(mainline));
+        } else {
+            text.insert(other.as_str().to_string(), JsonValue::Array(groups));
+        }
+    }
+    if dimension == Dimension::Mf {
+        text.insert("request".to_string(), aspice_graph(repo, &docs).await?);
+    }
+
+    Ok(json!({
+        "tag": tag_name,
+        "text": text,
+    }))
+}
+
+// one list of `filter_and_classify` in full, for its further pages: `all` if `group` is None,
+// else the group of that tag. None if there is no such tag or group
+pub async fn classified_documents<R: Repository + ?Sized>(
+    repo: &R,
+    tag_name: &str,
+    scope: &ProjectScope,
+    group: Option<(Dimension, &str)>,
+) -> anyhow::Result<Option<Vec<JsonValue>>> {
+    let (dimension, docs) = match tag_documents(repo, tag_name, scope).await? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let checks = read_link_checks(repo).await?;
+
+    let (other, name) = match group {
+        Some((other, _)) if other == dimension => return Ok(None),
+        Some(group) => group,
+        None => {
+            let all = docs
+                .iter()
+                .map(|doc| doc.to_json_value(true, checks.get(&doc.id)))
+                .collect();
+            return Ok(Some(all));
+        }
+    };
+    let mut groups = group_by_tag(repo, other, &docs, scope, &checks).await?;
+    Ok(groups
+        .iter_mut()
+        .find(|group| group["name"] == name)
+        .and_then(|group| match group["text"].take() {
+            JsonValue::Array(docs) => Some(docs),
+            _ => None,
+        }))
+}
+
+/*
+[
+    {
+        'name': 'techl2_tag1',
+        'text': [
+            {
+                'name': doca_name,
+                'address': doca_address,
+                'isRelated': true or false,//该文档是否和当前系统部件关联
+            },
+            doc_b
+        ],
+    },
+    {
+        ...
+    },
+],
+*/
+// tags having at least one of `docs`; their other documents in `scope` follow, marked as not related
+async fn group_by_tag<R: Repository + ?Sized>(
+    repo: &R,
+    dimension: Dimension,
+    docs: &[Document],
+    scope: &ProjectScope,
+    checks: &HashMap<Uuid, LinkCheck>,
+) -> anyhow::Result<Vec<JsonValue>> {
+    let links = repo.read_document_tags(dimension).await?;
+
+    let mut orders = HashMap::new();
+    let mut tag_docs: HashMap<&str, BTreeSet<Uuid>> = HashMap::new();
+    for link in links.iter().filter(|link| scope.contains(&link.docid)) {
+        orders.insert(link.name.clone(), link.sort_order);
+        tag_docs.entry(&link.name).or_default().insert(link.docid);
+    }
+
+    let mut result_vec = Vec::new();
+    for (name, docids) in tag_docs {
+        let related: Vec<&Document> = docs.iter().filter(|doc| docids.contains(&doc.id)).collect();
+        if related.is_empty() {
+            continue;
+        }
+        let mut text: Vec<JsonValue> = related
+            .iter()
+            .map(|doc| doc.to_json_value(true, checks.get(&doc.id)))
+            .collect();
+        for docid in docids {
+            if docs.iter().any(|doc| doc.id == docid) {
+                continue;
+            }
+            if let Some(doc) = repo.read_document(docid).await? {
+                text.push(doc.to_json_value(false, checks.get(&doc.id)));
+            }
+        }
+
+        result_vec.push(json!({
+            "name": name,
+            "text": text,
+        }));
+    }
+    sort_by_tag_order(&mut result_vec, "name", &orders);
+
+    Ok(result_vec)
+}
+
+// request:[
+//     {
+//         name: '需求1',
+//         content:[
+//             {
+//                 name: '软件需求分析',
+//                 content:[
+//                     {
+//                         name:'文档名称1',
+//                         address: 'https://momenta.feishu.cn/wiki/ORuBw5pT8iAUmPkkaEYcRsc1nLh'
+//                     }
+//                 ]
+//             },
+//         ]
+//     },
+//     {
+//         name: '需求2',
+//     }
+// ],
+// a doc serving several requirements is placed under each of them
+async fn aspice_graph<R: Repository + ?Sized>(
+    repo: &R,
+    docs: &[Document],
+) -> anyhow::Result<JsonValue> {
+    let mut doc_aspices = HashMap::new();
+    repo.read_exist_document_aspice(&mut doc_aspices).await?;
+    let mut doc_requirements = HashMap::new();
+    repo.read_exist_document_requirement(&mut doc_requirements)
+        .await?;
+
+    let mut map: BTreeMap<&str, BTreeMap<&Aspice, Vec<JsonValue>>> = BTreeMap::new();
+    for doc in docs {
+        let (requirements, aspices) =
+            match (doc_requirements.get(&doc.id), doc_aspices.get(&doc.id)) {
+                (Some(requirements), Some(aspices)) => (requirements, aspices),
+                _ => continue,
+            };
+        for requirement in requirements {
+            for aspice_step in aspices {
+                map.entry(requirement.as_str())
+                    .or_default()
+                    .entry(aspice_step)
+                    .or_default()
+                    .push(json!({
+                        "name": doc.name,
+                        "address": doc.link,
+                    }));
+            }
+        }
+    }
+
+    let result_vec = map
+        .into_iter()
+        .map(|(requirement, aspice_map)| {
+            let aspice_vec: Vec<JsonValue> = aspice_map
+                .into_iter()
+                .map(|(aspice_step, docs)| {
+                    json!({
+                        "name": aspice_step.to_string(),
+                        "content": docs,
+                    })
+                })
+                .collect();
+            json!({
+                "name": requirement,
+                "content": aspice_vec,
+            })
+        })
+        .collect();
+    Ok(JsonValue::Array(result_vec))
+}
+
+// sort tree nodes by the sort_order of their tag, then by name
+pub fn sort_by_tag_order(nodes: &mut [JsonValue], key: &str, orders: &HashMap<String, i32>) {
+    nodes.sort_by(|a, b| {
+        let a_label = a[key].as_str().unwrap_or("");
+        let b_label = b[key].as_str().unwrap_or("");
+        let a_order = orders.get(a_label).unwrap_or(&i32::MAX);
+        let b_order = orders.get(b_label).unwrap_or(&i32::MAX);
+        (a_order, a_label).cmp(&(b_order, b_label))
+    });
+}
@@ -0,0 +1,213 @@
+// Certificate hot-reload and the http -> https redirect listener.
+use arch_map::config::TlsConfig;
+use axum::extract::Host;
+use axum::http::{StatusCode, Uri};
+use axum::response::{IntoResponse, Redirect};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// rustls accepts an empty chain, so a half written or garbage file has to be caught here
+async fn read_pem_pair(tls: &TlsConfig) -> std::io::Result<(Vec<u8>, Vec<u8>)> {
+    let cert = tokio::fs::read(&tls.cert).await?;
+    let key = tokio::fs::read(&tls.key).await?;
+    let contains = |haystack: &[u8], needle: &str| {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle.as_bytes())
+    };
+    if !contains(&cert, "-----BEGIN CERTIFICATE-----") {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "no certificate found"));
+    }
+    if !contains(&key, "PRIVATE KEY-----") {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "no private key found"));
+    }
+    Ok((cert, key))
+}
+
+async fn reload(rustls_config: &RustlsConfig, tls: &TlsConfig) {
+    // a broken cert/key pair keeps the previous one, open connections are never dropped
+    let result = match read_pem_pair(tls).await {
+        Ok((cert, key)) => rustls_config.reload_from_pem(cert, key).await,
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(()) => tracing::info!("reloaded tls certificate {}", tls.cert.display()),
+        Err(err) => tracing::error!(
+            "failed to reload tls certificate {}, keeping the old one: {}",
+            tls.cert.display(),
+            err
+        ),
+    }
+}
+
+// SIGHUP, listened to once for the whole process, so a signal arriving while a reload
+// runs is kept for the next recv instead of being lost
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    #[cfg(unix)]
+    fn new() -> Hangup {
+        use tokio::signal::unix::{signal, SignalKind};
+        let signal = match signal(SignalKind::hangup()) {
+            Ok(signal) => Some(signal),
+            Err(err) => {
+                tracing::warn!("can not listen for SIGHUP: {}", err);
+                None
+            }
+        };
+        Hangup { signal }
+    }
+
+    #[cfg(not(unix))]
+    fn new() -> Hangup {
+        Hangup {}
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = self.signal.as_mut() {
+            if signal.recv().await.is_some() {
+                return;
+            }
+            self.signal = None;
+        }
+        std::future::pending::<()>().await;
+    }
+}
+
+// reload the certificate on SIGHUP and whenever cert or key change on disk
+pub fn spawn_reloader(rustls_config: RustlsConfig, tls: TlsConfig) {
+    tokio::spawn(async move {
+        let mut hangup = Hangup::new();
+        let mut last_modified = (modified(&tls.cert), modified(&tls.key));
+        let interval = Duration::from_secs(tls.reload_interval);
+        loop {
+            if tls.reload_interval == 0 {
+                hangup.recv().await;
+            } else {
+                tokio::select! {
+                    _ = hangup.recv() => {}
+                    _ = tokio::time::sleep(interval) => {
+                        let current = (modified(&tls.cert), modified(&tls.key));
+                        if current == last_modified {
+                            continue;
+                        }
+                        // give the writer of cert and key a moment to finish both files
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+            last_modified = (modified(&tls.cert), modified(&tls.key));
+            reload(&rustls_config, &tls).await;
+        }
+    });
+}
+
+// same host and path on the https port
+fn https_uri(host: &str, https_port: u16, uri: &Uri) -> Option<String> {
+    let authority: axum::http::uri::Authority = host.parse().ok()?;
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    Some(if https_port == 443 {
+        format!("https://{}{}", authority.host(), path)
+    } else {
+        format!("https://{}:{}{}", authority.host(), https_port, path)
+    })
+}
+
+fn redirect_app(https_port: u16) -> Router {
+    let redirect = move |Host(host): Host, uri: Uri| async move {
+        match https_uri(&host, https_port, &uri) {
+            Some(location) => Redirect::permanent(&location).into_response(),
+            None => (StatusCode::BAD_REQUEST, "invalid host").into_response(),
+        }
+    };
+    Router::new().fallback(redirect)
+}
+
+pub async fn serve_redirect(addr: SocketAddr, https_port: u16) -> std::io::Result<()> {
+    let app = redirect_app(https_port);
+
+    tracing::info!("redirecting http://{} to https port {}", addr, https_port);
+    axum_server::bind(addr).serve(app.into_make_service()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Request};
+    use tower::ServiceExt;
+
+    fn target(host: &str, https_port: u16, uri: &str) -> Option<String> {
+        https_uri(host, https_port, &uri.parse().unwrap())
+    }
+
+    #[test]
+    fn redirect_keeps_host_path_and_query() {
+        // the port of the http listener is replaced by the https one
+        assert_eq!(
+            target("example.com:8080", 8443, "/arch_tree/mf?as_of=M1&project=ProjA").as_deref(),
+            Some("https://example.com:8443/arch_tree/mf?as_of=M1&project=ProjA")
+        );
+        assert_eq!(
+            target("example.com", 443, "/healthz").as_deref(),
+            Some("https://example.com/healthz")
+        );
+        assert_eq!(
+            target("example.com:80", 443, "/").as_deref(),
+            Some("https://example.com/")
+        );
+    }
+
+    #[test]
+    fn redirect_keeps_ipv6_brackets() {
+        assert_eq!(
+            target("[::1]:8080", 8443, "/graph?format=dot").as_deref(),
+            Some("https://[::1]:8443/graph?format=dot")
+        );
+        assert_eq!(target("[fe80::1]", 443, "/").as_deref(), Some("https://[fe80::1]/"));
+    }
+
+    #[test]
+    fn redirect_rejects_invalid_hosts() {
+        assert_eq!(target("exa mple.com", 443, "/"), None);
+        assert_eq!(target("[::1", 443, "/"), None);
+    }
+
+    #[tokio::test]
+    async fn redirect_listener_answers_with_a_permanent_redirect() {
+        let request = |host: &str| {
+            Request::builder()
+                .uri("/requirements?limit=10")
+                .header(header::HOST, host)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = redirect_app(3000)
+            .oneshot(request("[::1]:80"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://[::1]:3000/requirements?limit=10"
+        );
+
+        let response = redirect_app(3000)
+            .oneshot(request("exa mple.com"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
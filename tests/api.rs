@@ -0,0 +1,889 @@
+// Runs the arch_map binary against a throwaway database loaded from the workbooks in
+// tests/fixtures/api and compares the responses of every route with the golden json files
+// next to them. Needs the postgres server of DATABASE_URL, the user must be allowed to
+// create databases. Refresh the golden files with UPDATE_GOLDEN=1 and review the diff.
+use arch_map::database::dml_interface;
+use arch_map::database::migration;
+use arch_map::database::models::{Dimension, LinkCheck, TagLevel};
+use arch_map::database::repository::{PgRepository, Repository};
+use arch_map::etl::transform_load::InternalData;
+use reqwest::Method;
+use serde_json::{json, Value as JsonValue};
+use sqlx::{PgPool, Row};
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use uuid::Uuid;
+
+const EDITOR: &str = "editor-token";
+const ADMIN: &str = "admin-token";
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("api")
+        .join(name)
+}
+
+// the same server and credentials, another database
+fn database_url(base: &str, database: &str) -> String {
+    let (url, query) = match base.split_once('?') {
+        Some((url, query)) => (url, format!("?{}", query)),
+        None => (base, String::new()),
+    };
+    let server = &url[..url.rfind('/').expect("DATABASE_URL has no database name")];
+    format!("{}/{}{}", server, database, query)
+}
+
+struct TestDb {
+    base_url: String,
+    name: String,
+    url: String,
+    pool: PgPool,
+}
+
+impl TestDb {
+    async fn create() -> TestDb {
+        dotenvy::dotenv().ok();
+        let base_url = std::env::var("DATABASE_URL")
+            .expect("DATABASE_URL must point to the postgres server used by the tests");
+        let admin = PgPool::connect(&base_url).await.unwrap();
+        let name = format!("arch_map_test_{}", Uuid::new_v4().simple());
+        sqlx::query(&format!("CREATE DATABASE {}", name))
+            .execute(&admin)
+            .await
+            .unwrap();
+        admin.close().await;
+
+        let url = database_url(&base_url, &name);
+        let pool = PgPool::connect(&url).await.unwrap();
+        migration::up(&pool).await.unwrap();
+        TestDb {
+            base_url,
+            name,
+            url,
+            pool,
+        }
+    }
+
+    async fn import(&self, workbook: &str) -> anyhow::Result<()> {
+        let repo = PgRepository::new(self.pool.clone());
+        let mut data = InternalData::new();
+        data.read_exist_data(&repo).await?;
+        data.import_from_excel(&fixture(workbook))?;
+        data.load_to_database(&repo).await
+    }
+
+    async fn document_id(&self, name: &str) -> Uuid {
+        sqlx::query("SELECT id FROM documents WHERE name = $1")
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await
+            .unwrap()
+            .get("id")
+    }
+
+    async fn tag_id(&self, dimension: Dimension, level: TagLevel, name: &str) -> Uuid {
+        let sql = format!("SELECT id FROM {} WHERE name = $1", dimension.table(level));
+        sqlx::query(&sql)
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await
+            .unwrap()
+            .get("id")
+    }
+
+    async fn count(&self, table: &str) -> i64 {
+        sqlx::query(&format!("SELECT COUNT(*) AS count FROM {}", table))
+            .fetch_one(&self.pool)
+            .await
+            .unwrap()
+            .get("count")
+    }
+
+    // id -> readable placeholder, for every document, tag and snapshot in the database
+    async fn names(&self, names: &mut HashMap<String, String>) {
+        for (table, prefix) in [("documents", "doc"), ("snapshot", "snapshot")] {
+            let rows = sqlx::query(&format!("SELECT id, name FROM {}", table))
+                .fetch_all(&self.pool)
+                .await
+                .unwrap();
+            for row in rows {
+                names.insert(
+                    row.get::<Uuid, _>("id").to_string(),
+                    format!("<{}:{}>", prefix, row.get::<String, _>("name")),
+                );
+            }
+        }
+        for dimension in Dimension::all() {
+            for level in [TagLevel::L1, TagLevel::L2] {
+                let table = dimension.table(level);
+                let rows = sqlx::query(&format!("SELECT id, name FROM {}", table))
+                    .fetch_all(&self.pool)
+                    .await
+                    .unwrap();
+                for row in rows {
+                    names.insert(
+                        row.get::<Uuid, _>("id").to_string(),
+                        format!("<{}:{}>", table, row.get::<String, _>("name")),
+                    );
+                }
+            }
+        }
+    }
+}
+
+// drop the database even when the test panicked
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let base_url = self.base_url.clone();
+        let name = self.name.clone();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            // FORCE also closes the connections of the pool
+            runtime.block_on(async {
+                if let Ok(admin) = PgPool::connect(&base_url).await {
+                    let sql = format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", name);
+                    sqlx::query(&sql).execute(&admin).await.ok();
+                    admin.close().await;
+                }
+            });
+        })
+        .join()
+        .ok();
+    }
+}
+
+struct Server {
+    child: Child,
+    base: String,
+    client: reqwest::Client,
+}
+
+impl Server {
+    async fn start(database_url: &str) -> Server {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let listen = format!("127.0.0.1:{}", port);
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_arch_map"));
+        // only the settings below, not the ones of the developer's shell, .env or arch_map.toml
+        for (key, _) in std::env::vars() {
+            if key.starts_with("ARCH_MAP_") {
+                command.env_remove(key);
+            }
+        }
+        let child = command
+            .args(["--tls", "false", "--listen", &listen])
+            .current_dir(std::env::temp_dir())
+            .env("DATABASE_URL", database_url)
+            .env("ARCH_MAP_API_TOKENS", format!("alice:{}:editor,bob:{}:admin", EDITOR, ADMIN))
+            .env("ARCH_MAP_MAX_SYNC_AGE", "0")
+            .env("ARCH_MAP_LOG_LEVEL", "warn")
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let mut server = Server {
+            child,
+            base: format!("http://{}", listen),
+            client: reqwest::Client::new(),
+        };
+        for _ in 0..100 {
+            if let Ok(Some(status)) = server.child.try_wait() {
+                panic!("arch_map exited during startup: {}", status);
+            }
+            let healthz = server.client.get(format!("{}/healthz", server.base)).send().await;
+            if healthz.is_ok_and(|response| response.status().is_success()) {
+                return server;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("arch_map did not come up on {}", listen);
+    }
+
+    // status and body, json bodies are parsed, text bodies kept as a string
+    async fn call(
+        &self,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        body: Option<JsonValue>,
+    ) -> (u16, JsonValue) {
+        let mut request = self.client.request(method, format!("{}{}", self.base, path));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request.send().await.unwrap();
+        let status = response.status().as_u16();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let bytes = response.bytes().await.unwrap();
+
+        let body = if content_type.starts_with("application/json") {
+            serde_json::from_slice(&bytes).unwrap()
+        } else if content_type.contains("spreadsheetml") {
+            assert!(bytes.starts_with(b"PK"), "{} is not an xlsx file", path);
+            json!({ "content_type": content_type, "xlsx": true })
+        } else if bytes.is_empty() {
+            JsonValue::Null
+        } else {
+            JsonValue::String(String::from_utf8(bytes.to_vec()).unwrap())
+        };
+        (status, body)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+// document ids, audit log ids and timestamps change with every run: document and tag ids are
+// replaced by their names, the others by placeholders, and lists of documents, links or changed
+// rows, whose order follows the ids, are sorted
+fn normalize(value: &mut JsonValue, names: &HashMap<String, String>) {
+    match value {
+        JsonValue::String(text) => {
+            if chrono::DateTime::parse_from_rfc3339(text).is_ok() {
+                *text = "<timestamp>".to_string();
+            }
+            // cursors hold document ids in hex
+            if let Some(start) = text.find("cursor=") {
+                let end = text[start..].find('&').map_or(text.len(), |end| start + end);
+                text.replace_range(start + "cursor=".len()..end, "<cursor>");
+            }
+            for (id, name) in names {
+                if text.contains(id.as_str()) {
+                    *text = text.replace(id.as_str(), name);
+                }
+            }
+        }
+        JsonValue::Array(items) => {
+            for item in items.iter_mut() {
+                normalize(item, names);
+            }
+            let by_id = items.iter().all(|item| match item {
+                JsonValue::Object(object) => ["id", "address", "document", "source", "row_key"]
+                    .iter()
+                    .any(|key| object.contains_key(*key)),
+                // rows of numbers are a matrix, their order is meaningful
+                JsonValue::Array(inner) => inner.iter().any(|item| !item.is_number()),
+                _ => false,
+            });
+            if by_id {
+                items.sort_by_cached_key(|item| item.to_string());
+            }
+        }
+        JsonValue::Object(object) => {
+            // serial ids of the audit log follow the order of the inserts of the import
+            if object.get("id").is_some_and(|id| id.is_number()) {
+                object.insert("id".to_string(), json!("<serial>"));
+            }
+            for (key, item) in object.iter_mut() {
+                if key.to_lowercase().ends_with("cursor") && item.is_string() {
+                    *item = json!("<cursor>");
+                }
+                normalize(item, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+// compare with tests/fixtures/api/<name>.json, or rewrite it with UPDATE_GOLDEN=1
+fn assert_golden(name: &str, actual: &JsonValue) {
+    let path = fixture(&format!("{}.json", name));
+    let actual = serde_json::to_string_pretty(actual).unwrap() + "\n";
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!("missing {}, run with UPDATE_GOLDEN=1 to create it", path.display())
+    });
+    if let Some((line, (expected, actual))) = expected
+        .lines()
+        .zip(actual.lines())
+        .enumerate()
+        .find(|(_, (expected, actual))| expected != actual)
+    {
+        panic!(
+            "{} differs at line {}:\n  expected: {}\n  actual:   {}",
+            path.display(),
+            line + 1,
+            expected,
+            actual
+        );
+    }
+    assert_eq!(expected.lines().count(), actual.lines().count(), "{} differs in length", path.display());
+}
+
+struct Recorder<'a> {
+    db: &'a TestDb,
+    server: &'a Server,
+    names: HashMap<String, String>,
+    responses: Vec<JsonValue>,
+}
+
+impl<'a> Recorder<'a> {
+    async fn new(db: &'a TestDb, server: &'a Server) -> Recorder<'a> {
+        let mut names = HashMap::new();
+        db.names(&mut names).await;
+        Recorder {
+            db,
+            server,
+            names,
+            responses: Vec::new(),
+        }
+    }
+
+    async fn call(
+        &mut self,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        body: Option<JsonValue>,
+    ) -> (u16, JsonValue) {
+        let (status, response) = self
+            .server
+            .call(method.clone(), path, token, body.clone())
+            .await;
+        // names of deleted rows are kept for the error messages mentioning them
+        self.db.names(&mut self.names).await;
+
+        let mut record = json!({
+            "request": format!("{} {}", method, path),
+            "status": status,
+            "body": response,
+        });
+        if let Some(body) = body {
+            record["request_body"] = body;
+        }
+        if let Some(token) = token {
+            record["token"] = json!(token);
+        }
+        normalize(&mut record, &self.names);
+        self.responses.push(record);
+        (status, response)
+    }
+
+    async fn get(&mut self, path: &str) -> (u16, JsonValue) {
+        self.call(Method::GET, path, None, None).await
+    }
+
+    fn assert_golden(&self, name: &str) {
+        assert_golden(name, &JsonValue::Array(self.responses.clone()));
+    }
+}
+
+#[tokio::test]
+async fn read_routes_match_golden() {
+    let db = TestDb::create().await;
+    db.import("documents.xlsx").await.unwrap();
+
+    // importing the same workbook again adds nothing
+    let documents = db.count("documents").await;
+    let trace_links = db.count("document_trace_link").await;
+    db.import("documents.xlsx").await.unwrap();
+    assert_eq!(documents, db.count("documents").await);
+    assert_eq!(trace_links, db.count("document_trace_link").await);
+
+    let server = Server::start(&db.url).await;
+    let req_noa = db.document_id("REQ-NOA").await;
+    let ut = db.document_id("UT-目标检测").await;
+    let unknown = Uuid::nil();
+
+    // results of a link check: a broken and a working link, and a broken one since changed
+    let repo = PgRepository::new(db.pool.clone());
+    let docs = repo.read_documents().await.unwrap();
+    let link_check = |name: &str, ok: bool, status_code: i32| {
+        let doc = docs.iter().find(|doc| doc.name == name).unwrap();
+        LinkCheck {
+            docid: doc.id,
+            link: doc.link.clone(),
+            ok,
+            status_code: Some(status_code),
+            final_url: Some(doc.link.clone()),
+            error: None,
+            checked_at: chrono::Utc::now(),
+        }
+    };
+    let mut changed = link_check("IT-ACC", false, 404);
+    changed.link = "https://old.example.com/IT-ACC".to_string();
+    let checks = vec![
+        link_check("UT-目标检测", false, 404),
+        link_check("REQ-NOA", true, 200),
+        changed,
+    ];
+    repo.insert_link_checks(checks).await.unwrap();
+
+    let mut recorder = Recorder::new(&db, &server).await;
+    for path in [
+        "/hello".to_string(),
+        "/healthz".to_string(),
+        "/readyz".to_string(),
+        "/filter_and_classify/目标检测".to_string(),
+        "/filter_and_classify/摄像头".to_string(),
+        "/filter_and_classify/NOA".to_string(),
+        "/filter_and_classify/ProjA".to_string(),
+        "/filter_and_classify/不存在的方案".to_string(),
+        "/arch_tree/component".to_string(),
+        "/arch_tree/mf".to_string(),
+        "/arch_tree/project".to_string(),
+        "/component_tree/arch".to_string(),
+        "/mf_tree/project".to_string(),
+        "/arch_tree/arch".to_string(),
+        "/component_tree/component".to_string(),
+        "/mf_tree/mf".to_string(),
+        "/component_tree/nope".to_string(),
+        "/mf_tree/nope".to_string(),
+        "/project_tree/ProjA".to_string(),
+        "/project_tree/主线".to_string(),
+        "/project_tree/不存在的项目".to_string(),
+        "/cooccurrence/arch/component".to_string(),
+        "/cooccurrence/mf/project?level=l1".to_string(),
+        "/cooccurrence/project/project".to_string(),
+        "/cooccurrence/arch/unknown".to_string(),
+        "/cooccurrence/arch/mf?level=l3".to_string(),
+        "/graph".to_string(),
+        "/graph?format=dot&around=system_l2:激光雷达&hops=2".to_string(),
+        "/graph?format=graphml&around=document:IT-ACC&hops=1".to_string(),
+        "/graph?format=mermaid&around=mf_l1:行车&hops=1".to_string(),
+        "/graph?around=arch_l2:不存在".to_string(),
+        "/graph?around=激光雷达".to_string(),
+        "/impact?start=component_l2:激光雷达".to_string(),
+        "/impact?start=document:REQ-ACC&depth=1&trace_links=true".to_string(),
+        "/impact?start=arch_l1:规划&depth=3".to_string(),
+        "/impact?start=mf_l2:不存在".to_string(),
+        "/requirements".to_string(),
+        format!("/requirements/{}/trace", req_noa),
+        format!("/requirements/{}/trace?project=ProjA&tech=目标检测", req_noa),
+        format!("/requirements/{}/trace?project=项目", req_noa),
+        format!("/requirements/{}/trace?project=ProjB,主线", req_noa),
+        format!("/requirements/{}/trace?project=不存在的项目", req_noa),
+        format!("/requirements/{}/trace", unknown),
+        format!("/requirements/{}/trace", ut),
+        "/aspice_coverage".to_string(),
+        "/aspice_coverage?by_project=true".to_string(),
+        "/aspice_coverage?mf=NOA".to_string(),
+        "/aspice_coverage?format=csv".to_string(),
+        "/aspice_coverage?format=xlsx".to_string(),
+        format!("/documents/{}/trace/upstream", ut),
+        format!("/documents/{}/trace/downstream", req_noa),
+        format!("/documents/{}/trace/upstream", unknown),
+        "/trace_links/check".to_string(),
+        "/links/broken".to_string(),
+        "/data_quality".to_string(),
+        "/data_quality?format=csv&similarity=0.8".to_string(),
+        "/data_quality?similarity=2".to_string(),
+        "/filter_and_classify/目标检测?limit=1&sort=-name&fields=name,isRelated".to_string(),
+        "/filter_and_classify/目标检测?cursor=00".to_string(),
+        "/filter_and_classify/目标检测/documents?group=component:摄像头&limit=500&sort=aspice".to_string(),
+        "/filter_and_classify/目标检测/documents?group=arch:目标检测".to_string(),
+        "/filter_and_classify/目标检测/documents?group=nope:x".to_string(),
+        "/filter_and_classify/目标检测/documents?cursor=zz".to_string(),
+        "/requirements?sort=-name&fields=name".to_string(),
+        "/requirements?limit=0".to_string(),
+        "/requirements?sort=size".to_string(),
+        "/links/broken?limit=10&fields=name,status_code".to_string(),
+        format!("/documents/{}/history", req_noa),
+        "/arch_tree/component?project=ProjB".to_string(),
+        "/component_tree/arch?project=ProjA,主线".to_string(),
+        "/mf_tree/project?project=项目".to_string(),
+        "/arch_tree/mf?project=不存在的项目".to_string(),
+        "/filter_and_classify/目标检测?project=ProjB".to_string(),
+        "/filter_and_classify/NOA?project=ProjA".to_string(),
+        "/filter_and_classify/目标检测/documents?group=component:摄像头&project=主线".to_string(),
+    ] {
+        recorder.get(&path).await;
+    }
+    let graphql = [
+        "{ tags(dimension: COMPONENT) { name children { name documentCount } } }",
+        "{ documents(tag: \"目标检测\") { id name aspiceSteps tags { dimension name } requirements { name } } }",
+        "{ requirements { name aspiceSteps { step name documents { name } } } }",
+        "{ documents { nope } }",
+    ];
+    for query in graphql {
+        let body = json!({ "query": query });
+        recorder.call(Method::POST, "/graphql", None, Some(body)).await;
+    }
+    recorder.assert_golden("read_routes");
+
+    // walking the cursors one document at a time yields the whole list once
+    let (_, all) = server.call(Method::GET, "/requirements?sort=-modified", None, None).await;
+    let mut walked = Vec::new();
+    let mut path = "/requirements?sort=-modified&limit=1".to_string();
+    loop {
+        let (status, page) = server.call(Method::GET, &path, None, None).await;
+        assert_eq!(status, 200);
+        assert_eq!(page["total"], all.as_array().unwrap().len());
+        walked.extend(page["items"].as_array().unwrap().iter().cloned());
+        match page["next_cursor"].as_str() {
+            Some(cursor) => path = format!("/requirements?sort=-modified&limit=1&cursor={}", cursor),
+            None => break,
+        }
+    }
+    assert_eq!(JsonValue::Array(walked), all);
+
+    // metrics change with every request, only check they are served
+    let (status, metrics) = server.call(Method::GET, "/metrics", None, None).await;
+    assert_eq!(status, 200);
+    assert!(metrics.as_str().unwrap().contains("http_requests_total"));
+}
+
+#[tokio::test]
+async fn write_routes_match_golden() {
+    let db = TestDb::create().await;
+    db.import("documents.xlsx").await.unwrap();
+    let server = Server::start(&db.url).await;
+    let req_noa = db.document_id("REQ-NOA").await;
+    let arch = db.document_id("ARCH-感知").await;
+    let perception = db.tag_id(Dimension::Tech, TagLevel::L1, "感知").await;
+
+    let mut recorder = Recorder::new(&db, &server).await;
+    // the changes below, without the import
+    let since = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+    recorder.names.insert(since.clone(), "<since>".to_string());
+    // the map before the changes, to be served again as of the snapshot
+    let (_, before) = recorder.get("/arch_tree/component").await;
+    recorder
+        .call(Method::POST, "/snapshots", None, Some(json!({ "name": "M1" })))
+        .await;
+    for name in ["M1", "M1", "2026-10-19T08:00:00Z", " "] {
+        recorder
+            .call(Method::POST, "/snapshots", Some(EDITOR), Some(json!({ "name": name })))
+            .await;
+    }
+    let document = json!({
+        "name": "DES-车道线",
+        "link": "https://example.com/des-lane",
+        "description": "车道线详设",
+    });
+
+    // anonymous requests are viewers, editors can not delete
+    recorder
+        .call(Method::POST, "/documents", None, Some(document.clone()))
+        .await;
+    let (status, created) = recorder
+        .call(Method::POST, "/documents", Some(EDITOR), Some(document.clone()))
+        .await;
+    assert_eq!(status, 201);
+    let des: Uuid = created["id"].as_str().unwrap().parse().unwrap();
+    recorder
+        .call(Method::POST, "/documents", Some(EDITOR), Some(document))
+        .await;
+    recorder
+        .call(
+            Method::PUT,
+            &format!("/documents/{}", des),
+            Some(EDITOR),
+            Some(json!({ "description": "" })),
+        )
+        .await;
+
+    let (_, tag) = recorder
+        .call(
+            Method::POST,
+            "/tags/tech/l2",
+            Some(EDITOR),
+            Some(json!({ "name": "车道线拟合", "parent": "感知" })),
+        )
+        .await;
+    let lane_fitting = tag["id"].as_str().unwrap().to_string();
+    recorder
+        .call(
+            Method::POST,
+            "/tags/tech/l2",
+            Some(EDITOR),
+            Some(json!({ "name": "车道线拟合", "parent": "感知" })),
+        )
+        .await;
+    recorder
+        .call(
+            Method::POST,
+            "/tags/tech/l2",
+            Some(EDITOR),
+            Some(json!({ "name": "轨迹预测", "parent": "不存在" })),
+        )
+        .await;
+    recorder
+        .call(
+            Method::PUT,
+            &format!("/tags/tech/l1/{}", perception),
+            Some(EDITOR),
+            Some(json!({ "sort_order": 10 })),
+        )
+        .await;
+    recorder
+        .call(
+            Method::PUT,
+            "/tags/tech/l2/sort_order",
+            Some(EDITOR),
+            Some(json!({ "车道线": 1, "目标检测": 2, "车道线拟合": 3 })),
+        )
+        .await;
+    // 车道线 keeps its order, the request names a tag that does not exist
+    recorder
+        .call(
+            Method::PUT,
+            "/tags/tech/l2/sort_order",
+            Some(EDITOR),
+            Some(json!({ "车道线": 5, "不存在的方案": 6 })),
+        )
+        .await;
+    recorder
+        .call(
+            Method::PUT,
+            "/tags/nope/l2/sort_order",
+            Some(EDITOR),
+            Some(json!({ "车道线": 1 })),
+        )
+        .await;
+
+    for tag in ["车道线", "车道线拟合", "不存在的方案"] {
+        recorder
+            .call(
+                Method::POST,
+                &format!("/documents/{}/tags/tech", des),
+                Some(EDITOR),
+                Some(json!({ "name": tag })),
+            )
+            .await;
+    }
+    recorder
+        .call(
+            Method::DELETE,
+            &format!("/documents/{}/tags/tech/车道线拟合", des),
+            Some(EDITOR),
+            None,
+        )
+        .await;
+    recorder
+        .call(
+            Method::POST,
+            &format!("/documents/{}/aspice/详设", des),
+            Some(EDITOR),
+            None,
+        )
+        .await;
+    recorder
+        .call(
+            Method::POST,
+            &format!("/documents/{}/aspice/不存在", des),
+            Some(EDITOR),
+            None,
+        )
+        .await;
+    recorder
+        .call(
+            Method::POST,
+            &format!("/documents/{}/requirements/{}", des, req_noa),
+            Some(EDITOR),
+            None,
+        )
+        .await;
+    recorder
+        .call(
+            Method::POST,
+            &format!("/documents/{}/trace_links", des),
+            Some(EDITOR),
+            Some(json!({ "target": arch, "type": "refines" })),
+        )
+        .await;
+    recorder
+        .call(
+            Method::POST,
+            &format!("/documents/{}/trace_links", des),
+            Some(EDITOR),
+            Some(json!({ "target": des, "type": "refines" })),
+        )
+        .await;
+    recorder
+        .get(&format!("/documents/{}/trace/upstream", des))
+        .await;
+    recorder.get("/arch_tree/component").await;
+
+    recorder
+        .call(
+            Method::DELETE,
+            &format!("/documents/{}/trace_links/{}", des, arch),
+            Some(EDITOR),
+            None,
+        )
+        .await;
+    recorder
+        .call(
+            Method::DELETE,
+            &format!("/documents/{}/trace_links/{}", des, arch),
+            Some(EDITOR),
+            None,
+        )
+        .await;
+    recorder
+        .call(
+            Method::DELETE,
+            &format!("/documents/{}/requirements/{}", des, req_noa),
+            Some(EDITOR),
+            None,
+        )
+        .await;
+    recorder
+        .call(
+            Method::DELETE,
+            &format!("/documents/{}/aspice/详设", des),
+            Some(EDITOR),
+            None,
+        )
+        .await;
+
+    // a requirement without downstream documents misses every other step
+    recorder
+        .call(
+            Method::POST,
+            &format!("/documents/{}/aspice/需求", des),
+            Some(EDITOR),
+            None,
+        )
+        .await;
+    recorder
+        .call(
+            Method::POST,
+            &format!("/documents/{}/tags/mf", des),
+            Some(EDITOR),
+            Some(json!({ "name": "ACC" })),
+        )
+        .await;
+    let (_, coverage) = recorder.get("/aspice_coverage?mf=ACC").await;
+    let lane = coverage["modules"][0]["requirements"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|requirement| requirement["name"] == "DES-车道线")
+        .unwrap()
+        .clone();
+    assert_eq!(lane["covered"], json!(["需求"]));
+    assert_eq!(lane["missing"].as_array().unwrap().len(), 5);
+
+    recorder
+        .call(Method::DELETE, &format!("/documents/{}", des), Some(EDITOR), None)
+        .await;
+    recorder
+        .call(Method::DELETE, &format!("/documents/{}", des), Some(ADMIN), None)
+        .await;
+    recorder
+        .call(Method::DELETE, &format!("/documents/{}", des), Some(ADMIN), None)
+        .await;
+    recorder
+        .call(
+            Method::DELETE,
+            &format!("/tags/tech/l2/{}", lane_fitting),
+            Some(ADMIN),
+            None,
+        )
+        .await;
+    recorder
+        .call(
+            Method::DELETE,
+            &format!("/tags/tech/l1/{}", perception),
+            Some(ADMIN),
+            None,
+        )
+        .await;
+    recorder.get("/arch_tree/component").await;
+    recorder
+        .get(&format!("/documents/{}/history", des))
+        .await;
+    recorder
+        .get(&format!("/history/diff?from={}", since))
+        .await;
+
+    // the trees as of the snapshot ignore the changes since
+    recorder.get("/snapshots").await;
+    let (_, as_of) = recorder.get("/arch_tree/component?as_of=M1").await;
+    assert_eq!(before, as_of);
+    let now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+    recorder.names.insert(now.clone(), "<now>".to_string());
+    for path in [
+        format!("/arch_tree/component?as_of={}", now),
+        "/component_tree/arch?as_of=M1".to_string(),
+        "/mf_tree/project?as_of=M1".to_string(),
+        "/project_tree/ProjA?as_of=M1".to_string(),
+        "/filter_and_classify/目标检测?as_of=M1".to_string(),
+        "/arch_tree/component?as_of=M2".to_string(),
+        format!("/arch_tree/component?as_of={}", since),
+    ] {
+        recorder.get(&path).await;
+    }
+    recorder
+        .call(Method::DELETE, "/snapshots/M1", Some(EDITOR), None)
+        .await;
+    recorder
+        .call(Method::DELETE, "/snapshots/M1", Some(ADMIN), None)
+        .await;
+    recorder
+        .call(Method::DELETE, "/snapshots/M1", Some(ADMIN), None)
+        .await;
+
+    recorder.assert_golden("write_routes");
+
+    // only the latest automatic snapshots are kept, the ones of editors stay
+    dml_interface::insert_snapshot(&db.pool, "M3", None, Some("editor"))
+        .await
+        .unwrap();
+    for name in ["sync-1", "sync-2", "sync-3"] {
+        dml_interface::insert_snapshot(&db.pool, name, Some(Uuid::new_v4()), Some("data_sync"))
+            .await
+            .unwrap();
+    }
+    assert_eq!(dml_interface::prune_sync_snapshots(&db.pool, 2).await.unwrap(), 1);
+    let names: Vec<String> = dml_interface::read_snapshots(&db.pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|snapshot| snapshot.name)
+        .collect();
+    assert_eq!(names, ["M3", "sync-2", "sync-3"]);
+
+    // a snapshot that no longer decodes fails the request, not the server
+    sqlx::query("INSERT INTO snapshot (id, name, data) VALUES ($1, 'broken', '{}')")
+        .bind(Uuid::new_v4())
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let (status, body) = server
+        .call(Method::GET, "/arch_tree/component?as_of=broken", None, None)
+        .await;
+    assert_eq!((status, body), (500, json!({ "error": "internal error" })));
+    let (status, _) = server.call(Method::GET, "/healthz", None, None).await;
+    assert_eq!(status, 200);
+}
+
+#[test]
+fn import_rejects_unknown_tags() {
+    let err = InternalData::new()
+        .import_from_excel(&fixture("unknown_tag.xlsx"))
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "tech tag '不存在的方案' of document 'ARCH-感知' is not in the tag sheet"
+    );
+}
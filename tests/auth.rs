@@ -0,0 +1,226 @@
+use arch_map::auth::*;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::routing::get;
+use axum::{middleware, Router};
+use jsonwebtoken::{encode, get_current_timestamp, Algorithm, EncodingKey, Header};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const ISSUER: &str = "https://sso.example.com";
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("auth")
+        .join(name)
+}
+
+// sign with one of the local test keys, only `jwt_private.pem` is in jwks.json
+fn sign(claims: serde_json::Value, key_file: &str) -> String {
+    sign_with(claims, key_file, Algorithm::RS256)
+}
+
+fn sign_with(claims: serde_json::Value, key_file: &str, algorithm: Algorithm) -> String {
+    let pem = std::fs::read(fixture(key_file)).unwrap();
+    let mut header = Header::new(algorithm);
+    header.kid = Some("test-key".to_string());
+    encode(&header, &claims, &EncodingKey::from_rsa_pem(&pem).unwrap()).unwrap()
+}
+
+fn claims(roles: serde_json::Value) -> serde_json::Value {
+    json!({
+        "sub": "alice",
+        "iss": ISSUER,
+        "exp": get_current_timestamp() + 3600,
+        "roles": roles,
+    })
+}
+
+fn authenticator(anonymous_role: Option<Role>) -> Authenticator {
+    let mut jwt = JwtConfig::from_jwks_file(&fixture("jwks.json")).unwrap();
+    jwt.issuer = Some(ISSUER.to_string());
+
+    let api_tokens = parse_api_tokens("viewer-token:viewer,ci:admin-token:admin").unwrap();
+
+    Authenticator::new(AuthConfig {
+        api_tokens,
+        jwt: Some(jwt),
+        anonymous_role,
+    })
+}
+
+fn bearer(token: &str) -> String {
+    format!("Bearer {}", token)
+}
+
+#[test]
+fn static_api_tokens_carry_their_role() {
+    let auth = authenticator(None);
+
+    let principal = auth.authenticate(Some("Bearer admin-token")).unwrap();
+    assert_eq!(principal.role, Role::Admin);
+    assert_eq!(
+        auth.authorize(Some("Bearer viewer-token"), Role::Editor),
+        Err(AuthError::Forbidden("editor role required".to_string()))
+    );
+    assert!(matches!(
+        auth.authenticate(Some("Bearer wrong-token")),
+        Err(AuthError::Unauthorized(_))
+    ));
+}
+
+#[test]
+fn static_api_tokens_are_named_by_their_subject() {
+    let auth = authenticator(None);
+
+    assert_eq!(auth.authenticate(Some("Bearer admin-token")).unwrap().subject, "ci");
+    assert_eq!(auth.authenticate(Some("Bearer viewer-token")).unwrap().subject, ApiToken::UNNAMED);
+
+    let tokens = parse_api_tokens("sync:abc:editor, s3cr3t:viewer").unwrap();
+    assert_eq!(tokens["abc"], ApiToken::new("sync", Role::Editor));
+    assert_eq!(tokens["s3cr3t"], ApiToken::new(ApiToken::UNNAMED, Role::Viewer));
+    assert!(parse_api_tokens(":abc:editor").is_err());
+    assert!(parse_api_tokens("sync::editor").is_err());
+    assert!(parse_api_tokens("sync:abc:owner").is_err());
+}
+
+#[test]
+fn anonymous_requests_use_the_configured_role() {
+    let principal = authenticator(Some(Role::Viewer)).authenticate(None).unwrap();
+    assert_eq!(principal.role, Role::Viewer);
+
+    assert!(matches!(
+        authenticator(None).authenticate(None),
+        Err(AuthError::Unauthorized(_))
+    ));
+}
+
+#[test]
+fn jwt_signed_by_jwks_key_is_accepted() {
+    let token = sign(claims(json!(["viewer", "editor"])), "jwt_private.pem");
+
+    let principal = authenticator(None).authenticate(Some(&bearer(&token))).unwrap();
+    assert_eq!(
+        principal,
+        Principal {
+            subject: "alice".to_string(),
+            role: Role::Editor,
+        }
+    );
+}
+
+#[test]
+fn jwt_role_claim_may_be_a_string() {
+    let token = sign(claims(json!("admin")), "jwt_private.pem");
+
+    let principal = authenticator(None).authenticate(Some(&bearer(&token))).unwrap();
+    assert_eq!(principal.role, Role::Admin);
+}
+
+#[test]
+fn jwt_signed_by_unknown_key_is_rejected() {
+    let token = sign(claims(json!(["admin"])), "jwt_other_private.pem");
+
+    assert!(matches!(
+        authenticator(None).authenticate(Some(&bearer(&token))),
+        Err(AuthError::Unauthorized(_))
+    ));
+}
+
+#[test]
+fn jwt_algorithm_is_pinned_by_the_key() {
+    // the key of jwks.json has "alg": "RS256"
+    let token = sign_with(claims(json!(["admin"])), "jwt_private.pem", Algorithm::RS384);
+    assert!(matches!(
+        authenticator(None).authenticate(Some(&bearer(&token))),
+        Err(AuthError::Unauthorized(_))
+    ));
+
+    // without `alg` on the key, the configured algorithms apply
+    let mut jwt = JwtConfig::from_jwks_file(&fixture("jwks.json")).unwrap();
+    jwt.issuer = Some(ISSUER.to_string());
+    jwt.keys.keys[0].common.key_algorithm = None;
+    jwt.algorithms = vec![Algorithm::RS384];
+    let authenticator = Authenticator::new(AuthConfig {
+        api_tokens: HashMap::new(),
+        jwt: Some(jwt),
+        anonymous_role: None,
+    });
+    assert!(authenticator.authenticate(Some(&bearer(&token))).is_ok());
+    let token = sign(claims(json!(["admin"])), "jwt_private.pem");
+    assert!(matches!(
+        authenticator.authenticate(Some(&bearer(&token))),
+        Err(AuthError::Unauthorized(_))
+    ));
+}
+
+#[test]
+fn expired_jwt_is_rejected() {
+    let mut expired = claims(json!(["admin"]));
+    expired["exp"] = json!(get_current_timestamp() - 3600);
+    let token = sign(expired, "jwt_private.pem");
+
+    assert!(matches!(
+        authenticator(None).authenticate(Some(&bearer(&token))),
+        Err(AuthError::Unauthorized(_))
+    ));
+}
+
+#[test]
+fn jwt_from_other_issuer_is_rejected() {
+    let mut other_issuer = claims(json!(["admin"]));
+    other_issuer["iss"] = json!("https://evil.example.com");
+    let token = sign(other_issuer, "jwt_private.pem");
+
+    assert!(matches!(
+        authenticator(None).authenticate(Some(&bearer(&token))),
+        Err(AuthError::Unauthorized(_))
+    ));
+}
+
+#[test]
+fn jwt_without_known_role_is_forbidden() {
+    let token = sign(claims(json!(["guest"])), "jwt_private.pem");
+
+    assert!(matches!(
+        authenticator(None).authenticate(Some(&bearer(&token))),
+        Err(AuthError::Forbidden(_))
+    ));
+}
+
+async fn status(app: &Router, method: &str, uri: &str, token: Option<&str>) -> StatusCode {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        request = request.header("Authorization", bearer(token));
+    }
+    app.clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn require_role_is_enforced_per_route() {
+    let auth = Arc::new(authenticator(Some(Role::Viewer)));
+    let guard = |role| middleware::from_fn_with_state(RequireRole::new(auth.clone(), role), require_role);
+
+    let app = Router::new()
+        .merge(Router::new().route("/read", get(|| async { "read" })).route_layer(guard(Role::Viewer)))
+        .merge(Router::new().route("/write", get(|| async { "write" })).route_layer(guard(Role::Editor)))
+        .merge(Router::new().route("/delete", get(|| async { "delete" })).route_layer(guard(Role::Admin)));
+
+    let editor = sign(claims(json!(["editor"])), "jwt_private.pem");
+
+    assert_eq!(status(&app, "GET", "/read", None).await, StatusCode::OK);
+    assert_eq!(status(&app, "GET", "/write", None).await, StatusCode::FORBIDDEN);
+    assert_eq!(status(&app, "GET", "/write", Some("bad")).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(status(&app, "GET", "/write", Some(&editor)).await, StatusCode::OK);
+    assert_eq!(status(&app, "GET", "/delete", Some(&editor)).await, StatusCode::FORBIDDEN);
+    assert_eq!(status(&app, "GET", "/delete", Some("admin-token")).await, StatusCode::OK);
+}
@@ -0,0 +1,112 @@
+// Layering and validation of the server configuration, from the config files in
+// tests/fixtures/config.
+use arch_map::config::{ServerArgs, ServerConfig, TlsConfig};
+use arch_map::logging::LogFormat;
+use clap::Parser;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("config")
+        .join(name)
+}
+
+fn args_with_config(name: &str) -> ServerArgs {
+    ServerArgs {
+        config: Some(fixture(name)),
+        ..ServerArgs::default()
+    }
+}
+
+#[test]
+fn defaults_file_environment_and_flags_are_layered() {
+    // the only test reading the environment, the others build ServerArgs directly
+    for (key, _) in std::env::vars() {
+        if key.starts_with("ARCH_MAP_") || key == "DATABASE_URL" {
+            std::env::remove_var(key);
+        }
+    }
+    std::env::set_var("ARCH_MAP_LOG_LEVEL", "warn");
+    std::env::set_var("ARCH_MAP_DB_MAX_CONNECTIONS", "7");
+    std::env::set_var("ARCH_MAP_LOG_FORMAT", "json");
+    let config_file = fixture("arch_map.toml");
+    let args = ServerArgs::try_parse_from([
+        "arch_map",
+        "--config",
+        config_file.to_str().unwrap(),
+        "--db-max-connections",
+        "9",
+    ])
+    .unwrap();
+    let config = ServerConfig::load(&args).unwrap();
+
+    // file over defaults
+    assert_eq!(config.listen, "127.0.0.1:4000".parse::<SocketAddr>().unwrap());
+    assert_eq!(config.database.url.as_deref(), Some("postgres://file/arch_map"));
+    // defaults the file leaves out
+    assert!(config.tls.enabled);
+    assert_eq!(config.database.acquire_timeout_ms, 30000);
+    // environment over file and defaults
+    assert_eq!(config.log_level, "warn");
+    assert_eq!(config.log_format, LogFormat::Json);
+    // flags over environment
+    assert_eq!(config.database.max_connections, 9);
+}
+
+#[test]
+fn relative_tls_paths_of_a_config_file_start_at_its_directory() {
+    let config = ServerConfig::resolve(&args_with_config("arch_map.toml")).unwrap();
+    assert_eq!(config.tls.cert, fixture("certs/cert.pem"));
+    assert_eq!(config.tls.key, fixture("certs/key.pem"));
+    config.validate().unwrap();
+
+    // flags are relative to the working directory, like the defaults without a config file
+    let args = ServerArgs {
+        tls_cert: Some(PathBuf::from("other/cert.pem")),
+        ..args_with_config("arch_map.toml")
+    };
+    let config = ServerConfig::resolve(&args).unwrap();
+    assert_eq!(config.tls.cert, PathBuf::from("other/cert.pem"));
+    assert_eq!(config.tls.key, fixture("certs/key.pem"));
+    assert_eq!(ServerConfig::default().tls, TlsConfig::default());
+    assert_eq!(TlsConfig::default().cert, PathBuf::from("self_signed_certs/cert.pem"));
+}
+
+#[test]
+fn validate_reports_every_problem_at_once() {
+    let config = ServerConfig::resolve(&args_with_config("invalid.toml")).unwrap();
+    let message = config.validate().unwrap_err().to_string();
+    let problems: Vec<&str> = message.lines().collect();
+
+    assert_eq!(problems[0], "invalid configuration:");
+    assert_eq!(
+        problems[1..problems.len() - 1],
+        [
+            format!("  - tls.cert: file {} not found", fixture("missing/cert.pem").display()),
+            "  - tls.redirect_listen: must use another port than listen".to_string(),
+            "  - cors.allowed_origins: \"*\" can not be combined with other origins".to_string(),
+            "  - cors.allowed_origins: 'https://example.com/' is not an origin like https://example.com"
+                .to_string(),
+            "  - database.url: not set (config file, DATABASE_URL or --database-url)".to_string(),
+            "  - database.max_connections: must be at least 1".to_string(),
+            "  - database.acquire_timeout_ms: must be at least 1".to_string(),
+        ]
+    );
+    assert!(problems[problems.len() - 1].starts_with("  - log_level: invalid log filter 'info,=='"));
+
+    // plain http without a redirect and a postgres url are fine, the missing cert no longer matters
+    let args = ServerArgs {
+        tls: Some(false),
+        listen: Some("127.0.0.1:4001".parse().unwrap()),
+        database_url: Some("mysql://localhost/arch_map".to_string()),
+        ..args_with_config("arch_map.toml")
+    };
+    let message = ServerConfig::load(&args).unwrap_err().to_string();
+    assert_eq!(
+        message,
+        "invalid configuration:\n  - database.url: must start with postgres:// or postgresql://"
+    );
+}
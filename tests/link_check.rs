@@ -0,0 +1,160 @@
+use arch_map::database::models::*;
+use arch_map::database::repository::{MemoryRepository, Repository};
+use arch_map::link_check::{check_links, LinkCheckOptions};
+use axum::extract::State;
+use axum::http::{Method, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{Redirect, Response};
+use axum::routing::{get, head};
+use axum::Router;
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// (method, path, time) of every request the mock server got
+type Requests = Arc<Mutex<Vec<(Method, String, Instant)>>>;
+
+async fn record<B>(State(requests): State<Requests>, request: Request<B>, next: Next<B>) -> Response {
+    requests
+        .lock()
+        .unwrap()
+        .push((request.method().clone(), request.uri().path().to_string(), Instant::now()));
+    next.run(request).await
+}
+
+// a local server with a working, a redirected, a missing and a HEAD refusing page
+fn mock_server() -> (String, Requests) {
+    let requests = Requests::default();
+    let app = Router::new()
+        .route("/ok", get(|| async { "ok" }))
+        .route("/moved", get(|| async { Redirect::permanent("/ok") }))
+        .route("/gone", get(|| async { StatusCode::NOT_FOUND }))
+        .route(
+            "/head-not-allowed",
+            head(|| async { StatusCode::METHOD_NOT_ALLOWED }).get(|| async { "ok" }),
+        )
+        .layer(middleware::from_fn_with_state(requests.clone(), record));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let server = axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service());
+    tokio::spawn(server);
+    (base, requests)
+}
+
+async fn repo_with_links(links: &[(&str, String)]) -> (MemoryRepository, HashMap<String, Document>) {
+    let repo = MemoryRepository::new();
+    let docs: Vec<Document> = links
+        .iter()
+        .map(|(name, link)| Document::new(name.to_string(), link.clone(), None))
+        .collect();
+    repo.insert_documents(docs.clone()).await.unwrap();
+    (repo, docs.into_iter().map(|doc| (doc.name.clone(), doc)).collect())
+}
+
+fn no_interval() -> LinkCheckOptions {
+    LinkCheckOptions {
+        host_interval: Duration::ZERO,
+        ..LinkCheckOptions::default()
+    }
+}
+
+#[tokio::test]
+async fn records_status_redirect_target_and_errors() {
+    let (base, requests) = mock_server();
+    let (repo, docs) = repo_with_links(&[
+        ("ok", format!("{}/ok", base)),
+        ("moved", format!("{}/moved", base)),
+        ("gone", format!("{}/gone", base)),
+        ("head", format!("{}/head-not-allowed", base)),
+        ("invalid", "见飞书文档".to_string()),
+        ("ftp", "ftp://example.com/doc".to_string()),
+        ("empty", String::new()),
+    ])
+    .await;
+
+    let summary = check_links(&repo, &no_interval()).await.unwrap();
+    assert_eq!((summary.checked, summary.broken), (6, 3));
+
+    let checks: HashMap<_, _> = repo
+        .read_link_checks()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|check| (check.docid, check))
+        .collect();
+    let check = |name: &str| checks.get(&docs[name].id);
+    assert!(check("empty").is_none());
+
+    let ok = check("ok").unwrap();
+    assert!(ok.ok);
+    assert_eq!(ok.status_code, Some(200));
+    assert_eq!(ok.final_url.as_deref(), Some(format!("{}/ok", base).as_str()));
+
+    let moved = check("moved").unwrap();
+    assert!(moved.ok);
+    assert_eq!(moved.status_code, Some(200));
+    assert_eq!(moved.final_url.as_deref(), Some(format!("{}/ok", base).as_str()));
+
+    let gone = check("gone").unwrap();
+    assert!(!gone.ok);
+    assert_eq!(gone.status_code, Some(404));
+    assert_eq!(gone.error, None);
+
+    let head = check("head").unwrap();
+    assert!(head.ok);
+    assert_eq!(head.status_code, Some(200));
+
+    for name in ["invalid", "ftp"] {
+        let check = check(name).unwrap();
+        assert!(!check.ok);
+        assert_eq!(check.status_code, None);
+        assert!(check.error.is_some(), "{}: {:?}", name, check);
+    }
+
+    // HEAD is enough for a working page, GET is only tried after a failed HEAD
+    let methods = |path: &str| -> Vec<Method> {
+        let requests = requests.lock().unwrap();
+        requests
+            .iter()
+            .filter(|(_, p, _)| p == path)
+            .map(|(method, _, _)| method.clone())
+            .collect()
+    };
+    assert_eq!(methods("/head-not-allowed"), vec![Method::HEAD, Method::GET]);
+    assert_eq!(methods("/gone"), vec![Method::HEAD, Method::GET]);
+
+    // the status is shown with the document while its link is unchanged
+    let status = docs["gone"].to_json_value(true, Some(gone))["linkStatus"].clone();
+    assert_eq!(status["statusCode"], 404);
+    assert_eq!(status["ok"], false);
+    let mut changed = docs["gone"].clone();
+    changed.link = format!("{}/ok", base);
+    assert!(changed.to_json_value(true, Some(gone))["linkStatus"].is_null());
+}
+
+#[tokio::test]
+async fn spaces_out_requests_to_one_host() {
+    let (base, requests) = mock_server();
+    let links: Vec<(&str, String)> = ["a", "b", "c", "d"]
+        .into_iter()
+        .map(|name| (name, format!("{}/ok?doc={}", base, name)))
+        .collect();
+    let (repo, _) = repo_with_links(&links).await;
+
+    let options = LinkCheckOptions {
+        concurrency: 4,
+        host_interval: Duration::from_millis(100),
+        ..LinkCheckOptions::default()
+    };
+    check_links(&repo, &options).await.unwrap();
+
+    let mut times: Vec<Instant> = requests.lock().unwrap().iter().map(|(_, _, at)| *at).collect();
+    times.sort();
+    assert_eq!(times.len(), 4);
+    for pair in times.windows(2) {
+        let gap = pair[1] - pair[0];
+        assert!(gap >= Duration::from_millis(90), "requests {:?} apart", gap);
+    }
+}
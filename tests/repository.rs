@@ -0,0 +1,1030 @@
+use arch_map::database::models::*;
+use arch_map::database::repository::{MemoryRepository, Repository};
+use arch_map::etl::transform_load::InternalData;
+use arch_map::graphql;
+use arch_map::report::cooccurrence::cooccurrence;
+use arch_map::report::graph;
+use arch_map::report::impact::{self, ImpactOptions};
+use arch_map::report::page::{self, ListOptions, SortKeys};
+use arch_map::report::quality::{data_quality, normalize_name, QualityOptions};
+use arch_map::report::trace::TraceGraph;
+use arch_map::report::tree::{self, ProjectScope};
+use rust_xlsxwriter::Workbook;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+type Sheet<'a> = (&'a str, Vec<Vec<&'a str>>);
+
+// tag sheets: column 1 is the L1 tag, column 2 the L2 tag, an empty L1 cell keeps the previous one.
+// column 0 is filled as in the export, calamine starts the range at the first used column
+fn tag_sheet<'a>(name: &'a str, tags: &[(&'a str, &'a str)]) -> Sheet<'a> {
+    let mut rows = vec![vec!["序号", "L1", "L2"]];
+    rows.extend(tags.iter().map(|(l1, l2)| vec!["-", *l1, *l2]));
+    (name, rows)
+}
+
+struct Doc<'a> {
+    name: &'a str,
+    tech: &'a str,
+    system: &'a str,
+    mf: &'a str,
+    requirements: &'a str,
+    aspice: &'a str,
+    project: &'a str,
+}
+
+// columns of `文档管理` as read by `InternalData::import_documents`
+fn document_sheet<'a>(docs: &[Doc<'a>]) -> Sheet<'a> {
+    let mut rows = vec![vec![
+        "文档名称",
+        "描述",
+        "",
+        "技术方案",
+        "",
+        "系统部件",
+        "",
+        "MF",
+        "关联需求",
+        "ASPICE",
+        "项目",
+        "链接",
+    ]];
+    for doc in docs {
+        rows.push(vec![
+            doc.name,
+            "",
+            "",
+            doc.tech,
+            "",
+            doc.system,
+            "",
+            doc.mf,
+            doc.requirements,
+            doc.aspice,
+            doc.project,
+            "http://example.com",
+        ]);
+    }
+    ("文档管理", rows)
+}
+
+fn write_workbook(name: &str, sheets: &[Sheet]) -> PathBuf {
+    let mut workbook = Workbook::new();
+    for (sheet_name, rows) in sheets {
+        let worksheet = workbook.add_worksheet().set_name(*sheet_name).unwrap();
+        for (row, cells) in rows.iter().enumerate() {
+            for (col, cell) in cells.iter().enumerate() {
+                // leave empty cells unwritten, like an empty cell in the exported sheet
+                if !cell.is_empty() {
+                    worksheet
+                        .write_string(row as u32, col as u16, *cell)
+                        .unwrap();
+                }
+            }
+        }
+    }
+    let path = std::env::temp_dir().join(format!("arch_map_{}_{}.xlsx", name, std::process::id()));
+    workbook.save(&path).unwrap();
+    path
+}
+
+fn tag_sheets<'a>() -> Vec<Sheet<'a>> {
+    vec![
+        tag_sheet(
+            "技术方案选项",
+            &[("感知", "方案A"), ("", "方案B"), ("规划", "方案C")],
+        ),
+        tag_sheet("系统部件选项", &[("系统", "相机"), ("", "雷达")]),
+        tag_sheet("MF软件选项", &[("MF", "融合"), ("", "定位")]),
+        tag_sheet("主线或项目选项", &[("项目", "主线"), ("", "P1")]),
+    ]
+}
+
+fn documents<'a>() -> Vec<Doc<'a>> {
+    vec![
+        Doc {
+            name: "REQ-1",
+            tech: "",
+            system: "",
+            mf: "",
+            requirements: "",
+            aspice: "需求",
+            project: "主线",
+        },
+        Doc {
+            name: "REQ-2",
+            tech: "",
+            system: "",
+            mf: "",
+            requirements: "",
+            aspice: "需求",
+            project: "P1",
+        },
+        Doc {
+            name: "ARCH-1",
+            tech: "方案A",
+            system: "相机",
+            mf: "融合",
+            requirements: "REQ-1",
+            aspice: "架构",
+            project: "主线",
+        },
+        Doc {
+            name: "UT-1",
+            tech: "方案A",
+            system: "雷达",
+            mf: "融合",
+            requirements: "REQ-1, REQ-2",
+            aspice: "单测",
+            project: "P1",
+        },
+        Doc {
+            name: "DES-1",
+            tech: "方案B",
+            system: "雷达",
+            mf: "定位",
+            requirements: "REQ-2",
+            aspice: "详设",
+            project: "P1",
+        },
+    ]
+}
+
+fn workbook(name: &str) -> PathBuf {
+    let mut sheets = tag_sheets();
+    sheets.push(document_sheet(&documents()));
+    write_workbook(name, &sheets)
+}
+
+async fn import(repo: &MemoryRepository, path: &Path) -> InternalData {
+    let mut data = InternalData::new();
+    data.import_and_load(repo, path).await.unwrap();
+    data
+}
+
+async fn document_ids(repo: &MemoryRepository) -> HashMap<String, uuid::Uuid> {
+    let mut docs = HashMap::new();
+    repo.read_exist_documents(&mut docs).await.unwrap();
+    docs
+}
+
+#[tokio::test]
+async fn import_loads_tags_documents_and_links() {
+    let repo = MemoryRepository::new();
+    let data = import(&repo, &workbook("import")).await;
+
+    assert_eq!(data.documents.len(), 5);
+    assert_eq!(data.tech_l1.len(), 2);
+    assert_eq!(data.tech_l2.len(), 3);
+    assert_eq!(document_ids(&repo).await.len(), 5);
+
+    let mut tech = HashMap::new();
+    repo.read_exist_document_tags(Dimension::Tech, &mut tech)
+        .await
+        .unwrap();
+    let ut = document_ids(&repo).await["UT-1"];
+    assert_eq!(tech[&ut].iter().collect::<Vec<_>>(), vec!["方案A"]);
+
+    let mut requirements = HashMap::new();
+    repo.read_exist_document_requirement(&mut requirements)
+        .await
+        .unwrap();
+    let mut ut_requirements: Vec<_> = requirements[&ut].iter().cloned().collect();
+    ut_requirements.sort();
+    assert_eq!(ut_requirements, vec!["REQ-1", "REQ-2"]);
+}
+
+#[tokio::test]
+async fn requirements_start_the_trace_chain() {
+    let repo = MemoryRepository::new();
+    let data = import(&repo, &workbook("trace")).await;
+    let ids = document_ids(&repo).await;
+
+    let link_type = |source: &str, target: &str| {
+        data.document_trace_link
+            .iter()
+            .find(|link| link.source_docid == ids[source] && link.target_docid == ids[target])
+            .map(|link| link.link_type)
+    };
+    assert_eq!(link_type("ARCH-1", "REQ-1"), Some(TraceLinkType::Refines));
+    // unit tests verify their requirements
+    assert_eq!(link_type("UT-1", "REQ-2"), Some(TraceLinkType::Verifies));
+    assert_eq!(link_type("REQ-1", "REQ-2"), None);
+}
+
+#[tokio::test]
+async fn reimport_only_adds_new_rows() {
+    let repo = MemoryRepository::new();
+    let path = workbook("reimport");
+    import(&repo, &path).await;
+
+    // the same workbook again: nothing new, and no duplicate key errors
+    let data = import(&repo, &path).await;
+    assert!(data.documents.is_empty());
+    assert!(data.tech_l2.is_empty());
+    assert!(data.document_tech.is_empty());
+    assert!(data.document_requirement.is_empty());
+    assert!(data.document_trace_link.is_empty());
+    assert_eq!(document_ids(&repo).await.len(), 5);
+
+    // one more document reuses the existing tags
+    let mut docs = documents();
+    docs.push(Doc {
+        name: "IT-1",
+        tech: "方案C",
+        system: "",
+        mf: "",
+        requirements: "REQ-1",
+        aspice: "集测",
+        project: "",
+    });
+    let mut sheets = tag_sheets();
+    sheets.push(document_sheet(&docs));
+    let data = import(&repo, &write_workbook("reimport_more", &sheets)).await;
+    assert_eq!(data.documents.len(), 1);
+    assert_eq!(data.document_tech.len(), 1);
+    assert_eq!(data.document_trace_link.len(), 1);
+    assert_eq!(document_ids(&repo).await.len(), 6);
+}
+
+#[tokio::test]
+async fn reimport_follows_the_new_tag_order() {
+    let repo = MemoryRepository::new();
+    import(&repo, &workbook("order")).await;
+    // an order set through the API survives the reimport
+    let rows = repo
+        .set_tag_sort_order(Dimension::System, TagLevel::L2, "雷达", 0)
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+
+    let mut sheets = tag_sheets();
+    sheets[0] = tag_sheet(
+        "技术方案选项",
+        &[("规划", "方案C"), ("感知", "方案B"), ("", "方案A")],
+    );
+    sheets.push(document_sheet(&documents()));
+    import(&repo, &write_workbook("order_changed", &sheets)).await;
+
+    let tree = tree::tag_tree(&repo, Dimension::Tech, Dimension::System, &ProjectScope::all())
+        .await
+        .unwrap();
+    assert_eq!(tree[0]["label"], "规划");
+    assert_eq!(tree[1]["label"], "感知");
+    assert_eq!(tree[1]["children"][0]["label"], "方案B");
+    assert_eq!(tree[1]["children"][1]["label"], "方案A");
+
+    let tree = tree::tag_tree(&repo, Dimension::System, Dimension::Tech, &ProjectScope::all())
+        .await
+        .unwrap();
+    assert_eq!(tree[0]["children"][0]["label"], "雷达");
+    assert_eq!(tree[0]["children"][1]["label"], "相机");
+}
+
+#[tokio::test]
+async fn unknown_requirement_is_rejected() {
+    let mut docs = documents();
+    docs[2].requirements = "REQ-404";
+    let mut sheets = tag_sheets();
+    sheets.push(document_sheet(&docs));
+
+    let err = InternalData::new()
+        .import_and_load(
+            &MemoryRepository::new(),
+            &write_workbook("unknown_requirement", &sheets),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("REQ-404"), "{}", err);
+}
+
+#[tokio::test]
+async fn tag_tree_lists_cross_tags_and_document_counts() {
+    let repo = MemoryRepository::new();
+    import(&repo, &workbook("tag_tree")).await;
+
+    let tree = tree::tag_tree(&repo, Dimension::Tech, Dimension::System, &ProjectScope::all())
+        .await
+        .unwrap();
+    assert_eq!(
+        tree,
+        json!([
+            {
+                "label": "感知",
+                "level": 1,
+                "children": [
+                    { "label": "方案A", "level": 2, "textNum": 2, "content": ["相机", "雷达"] },
+                    { "label": "方案B", "level": 2, "textNum": 1, "content": ["雷达"] },
+                ],
+            },
+            {
+                "label": "规划",
+                "level": 1,
+                "children": [
+                    { "label": "方案C", "level": 2, "textNum": 0, "content": [] },
+                ],
+            },
+        ])
+    );
+
+    let tree = tree::tag_tree(&repo, Dimension::Mf, Dimension::Tech, &ProjectScope::all())
+        .await
+        .unwrap();
+    assert_eq!(tree[0]["children"][0]["content"], json!(["方案A"]));
+    assert!(tree::tag_tree(&repo, Dimension::Mf, Dimension::Mf, &ProjectScope::all())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn cooccurrence_counts_shared_documents() {
+    let repo = MemoryRepository::new();
+    import(&repo, &workbook("cooccurrence")).await;
+
+    let matrix = cooccurrence(&repo, Dimension::Tech, Dimension::System, TagLevel::L2)
+        .await
+        .unwrap();
+    assert_eq!(matrix.rows.labels, ["方案A", "方案B", "方案C"]);
+    assert_eq!(matrix.rows.totals, [2, 1, 0]);
+    assert_eq!(matrix.columns.labels, ["相机", "雷达"]);
+    assert_eq!(matrix.columns.totals, [1, 2]);
+    assert_eq!(matrix.counts, [[1, 1], [0, 1], [0, 0]]);
+    assert_eq!(matrix.max, 1);
+
+    let matrix = cooccurrence(&repo, Dimension::Tech, Dimension::System, TagLevel::L1)
+        .await
+        .unwrap();
+    assert_eq!(matrix.rows.labels, ["感知", "规划"]);
+    assert_eq!(matrix.counts, [[3], [0]]);
+
+    // a dimension with itself has the totals on the diagonal
+    let matrix = cooccurrence(&repo, Dimension::Project, Dimension::Project, TagLevel::L2)
+        .await
+        .unwrap();
+    assert_eq!(matrix.counts, [[2, 0], [0, 3]]);
+}
+
+#[tokio::test]
+async fn graph_links_documents_tags_and_requirements() {
+    let repo = MemoryRepository::new();
+    import(&repo, &workbook("graph")).await;
+
+    let map = graph::graph(&repo).await.unwrap();
+    let node = |label: &str| map.nodes.iter().find(|node| node.label == label).unwrap();
+    assert_eq!(node("REQ-1").kind, "requirement");
+    assert_eq!(node("UT-1").kind, "document");
+    assert_eq!(node("雷达").kind, "component_l2");
+    assert_eq!(node("系统").kind, "component_l1");
+    let has_edge = |map: &graph::Graph, source: &str, target: &str, kind: &str| {
+        let (source, target) = (&node(source).id, &node(target).id);
+        map.edges
+            .iter()
+            .any(|edge| &edge.source == source && &edge.target == target && edge.kind == kind)
+    };
+    assert!(has_edge(&map, "UT-1", "雷达", "tagged"));
+    assert!(has_edge(&map, "雷达", "系统", "belongs_to"));
+    assert!(has_edge(&map, "UT-1", "REQ-2", "requires"));
+
+    // 雷达 -> UT-1, DES-1, 系统 -> their tags and requirements, 相机
+    let around = map.around("system_l2:雷达", 2).unwrap();
+    let mut labels: Vec<&str> = around.nodes.iter().map(|node| node.label.as_str()).collect();
+    labels.sort();
+    let mut expected = vec![
+        "DES-1", "P1", "REQ-1", "REQ-2", "UT-1", "定位", "方案A", "方案B", "相机", "系统", "融合",
+        "雷达",
+    ];
+    expected.sort();
+    assert_eq!(labels, expected);
+    assert!(has_edge(&around, "UT-1", "REQ-1", "requires"));
+    assert!(!has_edge(&around, "方案A", "感知", "belongs_to"));
+
+    assert!(map.around("document:REQ-1", 0).unwrap().edges.is_empty());
+    assert!(map.around("system_l2:不存在", 1).is_err());
+    assert!(map.to_dot().contains("shape=note"));
+    assert!(map.to_mermaid().starts_with("flowchart LR\n"));
+}
+
+#[tokio::test]
+async fn impact_follows_shared_documents() {
+    let repo = MemoryRepository::new();
+    import(&repo, &workbook("impact")).await;
+    let map = graph::graph(&repo).await.unwrap();
+    let steps = impact::read_aspice_steps(&repo).await.unwrap();
+
+    let report = impact::impact(&map, &steps, "system_l2:雷达", &ImpactOptions::default()).unwrap();
+    assert_eq!(report.start, "component_l2:雷达");
+    let labels = |dimension: &str| -> Vec<String> {
+        report.dimensions[dimension]
+            .iter()
+            .map(|affected| affected.label.clone())
+            .collect()
+    };
+    assert_eq!(labels("arch"), ["方案A", "方案B"]);
+    assert_eq!(labels("mf"), ["定位", "融合"]);
+    assert_eq!(labels("project"), ["P1"]);
+    assert!(labels("component").is_empty());
+    let scheme = &report.dimensions["arch"][0];
+    assert_eq!(scheme.l1.as_deref(), Some("感知"));
+    assert_eq!(scheme.distance, 2);
+    assert_eq!(scheme.path, ["component_l2:雷达", "document:UT-1", "arch_l2:方案A"]);
+    let step = |report: &impact::ImpactReport, step: Aspice| -> Vec<String> {
+        report
+            .aspice
+            .iter()
+            .find(|impact| impact.step == Some(step.clone()))
+            .unwrap()
+            .documents
+            .iter()
+            .map(|doc| doc.label.clone())
+            .collect()
+    };
+    assert_eq!(step(&report, Aspice::单测), ["UT-1"]);
+    assert_eq!(step(&report, Aspice::详设), ["DES-1"]);
+    assert!(step(&report, Aspice::需求).is_empty());
+
+    // requirements are only reached through the trace links
+    let options = ImpactOptions {
+        depth: 2,
+        trace_links: true,
+    };
+    let report = impact::impact(&map, &steps, "system_l2:雷达", &options).unwrap();
+    assert_eq!(step(&report, Aspice::需求), ["REQ-1", "REQ-2"]);
+
+    // an L1 tag starts from its L2 tags
+    let options = ImpactOptions {
+        depth: 1,
+        trace_links: false,
+    };
+    let report = impact::impact(&map, &steps, "component_l1:系统", &options).unwrap();
+    assert_eq!(report.dimensions["component"].len(), 2);
+    assert!(report.dimensions["arch"].is_empty());
+
+    assert!(impact::impact(&map, &steps, "document:不存在", &options).is_err());
+}
+
+#[tokio::test]
+async fn data_quality_lists_unused_tags_and_suspicious_documents() {
+    let repo = MemoryRepository::new();
+    import(&repo, &workbook("data_quality")).await;
+    repo.insert_tech_l1(vec![TechL1::new("空领域".to_string(), 9)]).await.unwrap();
+    // a copy of UT-1 under another spelling, without tags and ASPICE step
+    let copy = Document::new("ut_1 ".to_string(), "http://example.com/".to_string(), None);
+    repo.insert_documents(vec![copy]).await.unwrap();
+    // DES-1 names an architecture document as its requirement
+    let ids = document_ids(&repo).await;
+    repo.insert_document_requirement(vec![DocumentRequirement {
+        doc_id: ids["DES-1"],
+        requirement_id: ids["ARCH-1"],
+    }])
+    .await
+    .unwrap();
+
+    let report = data_quality(&repo, &QualityOptions::default()).await.unwrap();
+    let empty: Vec<(&str, &str)> = report
+        .empty_tags
+        .iter()
+        .map(|tag| (tag.dimension, tag.l2.as_str()))
+        .collect();
+    assert_eq!(empty, [("arch", "方案C")]);
+    assert_eq!(report.childless_tags.len(), 1);
+    assert_eq!(report.childless_tags[0].name, "空领域");
+
+    let untagged: Vec<(&str, Vec<&str>)> = report
+        .untagged_documents
+        .iter()
+        .map(|doc| (doc.document.name.as_str(), doc.dimensions.clone()))
+        .collect();
+    assert_eq!(
+        untagged,
+        [
+            ("REQ-1", vec!["arch", "component", "mf"]),
+            ("REQ-2", vec!["arch", "component", "mf"]),
+            ("ut_1 ", vec!["arch", "component", "mf", "project"]),
+        ]
+    );
+    let without_aspice: Vec<&str> = report
+        .documents_without_aspice
+        .iter()
+        .map(|doc| doc.name.as_str())
+        .collect();
+    assert_eq!(without_aspice, ["ut_1 "]);
+
+    assert_eq!(normalize_name("ＵＴ－1 "), "ut1");
+    assert_eq!(report.similar_names.len(), 1);
+    let pair: Vec<&str> = report.similar_names[0]
+        .documents
+        .iter()
+        .map(|doc| doc.name.as_str())
+        .collect();
+    assert_eq!(pair, ["UT-1", "ut_1 "]);
+    assert_eq!(report.similar_names[0].similarity, 1.0);
+    // REQ-1 and REQ-2 differ in one of four characters
+    let loose = QualityOptions { similarity: 0.7 };
+    let report_loose = data_quality(&repo, &loose).await.unwrap();
+    assert!(report_loose.similar_names.len() > 1);
+
+    // every document of the workbook links to http://example.com
+    assert_eq!(report.duplicate_links.len(), 1);
+    assert_eq!(report.duplicate_links[0].link, "http://example.com");
+    assert_eq!(report.duplicate_links[0].documents.len(), 6);
+
+    assert_eq!(report.non_requirement_links.len(), 1);
+    let link = &report.non_requirement_links[0];
+    assert_eq!(link.document.name, "DES-1");
+    assert_eq!(link.requirement.name, "ARCH-1");
+    assert_eq!(link.requirement_steps, [Aspice::架构]);
+    assert_eq!(report.summary["non_requirement_links"], 1);
+}
+
+#[tokio::test]
+async fn project_tree_only_lists_project_documents() {
+    let repo = MemoryRepository::new();
+    import(&repo, &workbook("project_tree")).await;
+
+    let tree = tree::project_tree(&repo, "P1").await.unwrap();
+    assert_eq!(
+        tree,
+        json!([
+            {
+                "name": "感知",
+                "level": 1,
+                "children": [
+                    {
+                        "name": "方案A",
+                        "level": 2,
+                        "children": [{ "name": "UT-1", "address": "http://example.com" }],
+                    },
+                    {
+                        "name": "方案B",
+                        "level": 2,
+                        "children": [{ "name": "DES-1", "address": "http://example.com" }],
+                    },
+                ],
+            },
+        ])
+    );
+    assert_eq!(tree::project_tree(&repo, "P404").await.unwrap(), json!([]));
+}
+
+#[tokio::test]
+async fn classify_groups_documents_by_the_other_dimensions() {
+    let repo = MemoryRepository::new();
+    import(&repo, &workbook("classify")).await;
+
+    let result = tree::filter_and_classify(&repo, "相机", &ProjectScope::all(), &ListOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(result["tag"], "相机");
+    let text = &result["text"];
+    assert_eq!(text["all"].as_array().unwrap().len(), 1);
+    assert!(text.get("component").is_none());
+
+    // 方案A also has UT-1, which is not a 相机 document
+    let arch = &text["arch"][0];
+    assert_eq!(arch["name"], "方案A");
+    let names: Vec<_> = arch["text"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|doc| {
+            (
+                doc["name"].as_str().unwrap(),
+                doc["isRelated"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(names, vec![("ARCH-1", true), ("UT-1", false)]);
+
+    // the main line is split from the projects
+    assert_eq!(text["mainLine"][0]["name"], "主线");
+    assert_eq!(text["project"], json!([]));
+}
+
+#[tokio::test]
+async fn classify_mf_tag_lists_aspice_steps_per_requirement() {
+    let repo = MemoryRepository::new();
+    import(&repo, &workbook("classify_mf")).await;
+
+    let result = tree::filter_and_classify(&repo, "融合", &ProjectScope::all(), &ListOptions::default())
+        .await
+        .unwrap();
+    assert!(result["text"].get("mf").is_none());
+    assert_eq!(
+        result["text"]["request"],
+        json!([
+            {
+                "name": "REQ-1",
+                "content": [
+                    { "name": "软件架构设计", "content": [{ "name": "ARCH-1", "address": "http://example.com" }] },
+                    { "name": "软件单元验证", "content": [{ "name": "UT-1", "address": "http://example.com" }] },
+                ],
+            },
+            {
+                "name": "REQ-2",
+                "content": [
+                    { "name": "软件单元验证", "content": [{ "name": "UT-1", "address": "http://example.com" }] },
+                ],
+            },
+        ])
+    );
+
+    assert_eq!(
+        tree::filter_and_classify(&repo, "nope", &ProjectScope::all(), &ListOptions::default())
+            .await
+            .unwrap(),
+        json!("No documents")
+    );
+}
+
+#[tokio::test]
+async fn classify_pages_follow_the_cursor() {
+    let repo = MemoryRepository::new();
+    import(&repo, &workbook("classify_pages")).await;
+    let fields = ["id", "name", "isRelated"];
+    let options = |limit, cursor: Option<&str>, sort| {
+        ListOptions::parse(Some(limit), cursor, sort, Some("name,isRelated"), &fields).unwrap()
+    };
+
+    // 方案A has ARCH-1 and UT-1, its 雷达 group UT-1 and the unrelated DES-1
+    let result = tree::filter_and_classify(&repo, "方案A", &ProjectScope::all(), &options(1, None, None))
+        .await
+        .unwrap();
+    let text = &result["text"];
+    assert_eq!(text["all"], json!([{ "name": "ARCH-1", "isRelated": true }]));
+    assert_eq!(text["allCount"], 2);
+    let radar = text["component"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|group| group["name"] == "雷达")
+        .unwrap();
+    assert_eq!(radar["count"], 2);
+    assert_eq!(radar["text"], json!([{ "name": "UT-1", "isRelated": true }]));
+
+    // the cursor of the group leads to its next page, related documents stay first
+    let cursor = radar["nextCursor"].as_str().unwrap();
+    let group = Some((Dimension::System, "雷达"));
+    let docs = tree::classified_documents(&repo, "方案A", &ProjectScope::all(), group)
+        .await
+        .unwrap()
+        .unwrap();
+    let list = options(1, Some(cursor), None);
+    let next = page::page(docs.clone(), &list, &SortKeys::default()).unwrap();
+    assert_eq!(next.items, [json!({ "name": "DES-1", "isRelated": false })]);
+    assert_eq!((next.total, next.next_cursor), (2, None));
+
+    // the whole of `all`, latest V-model step first: UT-1 (单测) before ARCH-1 (架构)
+    let docs = tree::classified_documents(&repo, "方案A", &ProjectScope::all(), None).await.unwrap().unwrap();
+    let list = options(10, None, Some("-aspice"));
+    let keys = SortKeys::load(&repo, &list).await.unwrap();
+    let names: Vec<_> = page::page(docs, &list, &keys)
+        .unwrap()
+        .items
+        .iter()
+        .map(|doc| doc["name"].clone())
+        .collect();
+    assert_eq!(names, [json!("UT-1"), json!("ARCH-1")]);
+
+    // a cursor only fits the sort it was made for
+    assert!(ListOptions::parse(Some(1), Some(cursor), Some("aspice"), None, &fields).is_err());
+    assert!(ListOptions::parse(None, None, Some("size"), None, &fields).is_err());
+    assert!(ListOptions::parse(None, None, None, Some("link"), &fields).is_err());
+    let group = Some((Dimension::Tech, "方案B"));
+    assert!(tree::classified_documents(&repo, "方案A", &ProjectScope::all(), group)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn project_scope_recounts_trees_and_classification() {
+    let repo = MemoryRepository::new();
+    import(&repo, &workbook("project_scope")).await;
+    let scope = |projects: &[&str]| {
+        let projects: Vec<String> = projects.iter().map(|p| p.to_string()).collect();
+        let repo = &repo;
+        async move { ProjectScope::load(repo, &projects).await }
+    };
+
+    // P1 has REQ-2, UT-1 and DES-1, every tag stays in the tree
+    let p1 = scope(&["P1"]).await.unwrap();
+    let tree = tree::tag_tree(&repo, Dimension::Tech, Dimension::System, &p1).await.unwrap();
+    assert_eq!(
+        tree[0]["children"][0],
+        json!({ "label": "方案A", "level": 2, "textNum": 1, "content": ["雷达"] })
+    );
+    assert_eq!(tree[1]["children"][0]["textNum"], 0);
+
+    // an L1 project stands for all of its L2 projects
+    let all = scope(&["项目"]).await.unwrap();
+    let tree = tree::tag_tree(&repo, Dimension::Tech, Dimension::System, &all).await.unwrap();
+    assert_eq!(tree[0]["children"][0]["textNum"], 2);
+
+    // groups only hold documents of the scope, 雷达 has no 主线 document of 方案A
+    let main_line = scope(&["主线"]).await.unwrap();
+    let result = tree::filter_and_classify(&repo, "方案A", &main_line, &ListOptions::default())
+        .await
+        .unwrap();
+    let text = &result["text"];
+    let names = |list: &serde_json::Value| -> Vec<serde_json::Value> {
+        list.as_array().unwrap().iter().map(|item| item["name"].clone()).collect()
+    };
+    assert_eq!(names(&text["all"]), [json!("ARCH-1")]);
+    assert_eq!(names(&text["component"]), [json!("相机")]);
+
+    // the ASPICE graph of an MF tag is recalculated as well
+    let result = tree::filter_and_classify(&repo, "融合", &p1, &ListOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        result["text"]["request"][0],
+        json!({
+            "name": "REQ-1",
+            "content": [
+                { "name": "软件单元验证", "content": [{ "name": "UT-1", "address": "http://example.com" }] },
+            ],
+        })
+    );
+
+    assert_eq!(
+        tree::filter_and_classify(&repo, "相机", &p1, &ListOptions::default())
+            .await
+            .unwrap(),
+        json!("No documents")
+    );
+    assert!(scope(&["P404"]).await.is_err());
+}
+
+// documents with one ASPICE step each, linked by (source, target, type) indexes
+async fn trace_graph(
+    docs: &[(&str, Aspice)],
+    links: &[(usize, usize, TraceLinkType)],
+) -> (TraceGraph, Vec<uuid::Uuid>) {
+    let repo = MemoryRepository::new();
+    let documents: Vec<Document> = docs
+        .iter()
+        .map(|(name, _)| Document::new(name.to_string(), "http://example.com".to_string(), None))
+        .collect();
+    let ids: Vec<uuid::Uuid> = documents.iter().map(|doc| doc.id).collect();
+    repo.insert_documents(documents).await.unwrap();
+    let steps = docs
+        .iter()
+        .zip(&ids)
+        .map(|((_, step), id)| DocumentAspiceMapping::new(*id, step.clone()))
+        .collect();
+    repo.insert_document_aspice(steps).await.unwrap();
+    let links = links
+        .iter()
+        .map(|(source, target, link_type)| DocumentTraceLink::new(ids[*source], ids[*target], *link_type))
+        .collect();
+    repo.insert_document_trace_link(links).await.unwrap();
+    (TraceGraph::load(&repo).await.unwrap(), ids)
+}
+
+// documents of a walk with their links listed
+fn expanded_names(node: &serde_json::Value, names: &mut Vec<String>) {
+    if let Some(links) = node["links"].as_array() {
+        names.push(node["name"].as_str().unwrap().to_string());
+        for link in links {
+            expanded_names(&link["document"], names);
+        }
+    }
+}
+
+#[tokio::test]
+async fn trace_walk_expands_shared_documents_once() {
+    // 12 layers of two documents, each linked to both of the layer above:
+    // 2^11 paths from the top, but every document is listed with its links only once
+    let names: Vec<String> = (0..24).map(|i| format!("DOC-{}", i)).collect();
+    let docs: Vec<(&str, Aspice)> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let step = match i / 2 {
+                0 => Aspice::需求,
+                1 => Aspice::架构,
+                _ => Aspice::详设,
+            };
+            (name.as_str(), step)
+        })
+        .collect();
+    let mut links = Vec::new();
+    for layer in 1..12 {
+        for i in 0..2 {
+            for target in 0..2 {
+                links.push((layer * 2 + i, (layer - 1) * 2 + target, TraceLinkType::Refines));
+            }
+        }
+    }
+    let (graph, ids) = trace_graph(&docs, &links).await;
+
+    let tree = graph.walk(&ids[0], false);
+    let mut expanded = Vec::new();
+    expanded_names(&tree, &mut expanded);
+    assert_eq!(expanded.len(), 23);
+    // the first document of the first layer already listed all of the second layer
+    let other = &tree["links"][1]["document"];
+    for link in other["links"].as_array().unwrap() {
+        assert_eq!(link["document"]["repeated"], true);
+        assert!(link["document"].get("links").is_none());
+    }
+    assert!(graph.cycles().is_empty());
+}
+
+#[tokio::test]
+async fn trace_check_reports_cycles_and_broken_links() {
+    let (graph, ids) = trace_graph(
+        &[
+            ("ARCH", Aspice::架构),
+            ("DES", Aspice::详设),
+            ("UT", Aspice::单测),
+            ("DES-2", Aspice::详设),
+        ],
+        &[
+            // UT -> DES -> ARCH -> UT
+            (2, 1, TraceLinkType::Verifies),
+            (1, 0, TraceLinkType::Refines),
+            (0, 2, TraceLinkType::Refines),
+            // a design can not verify
+            (3, 0, TraceLinkType::Verifies),
+        ],
+    )
+    .await;
+
+    let mut cycle = vec![ids[0], ids[2], ids[1]];
+    let min = cycle.iter().position(|id| id == cycle.iter().min().unwrap()).unwrap();
+    cycle.rotate_left(min);
+    assert_eq!(graph.cycles(), vec![cycle]);
+    let tree = graph.walk(&ids[2], true);
+    let arch = &tree["links"][0]["document"]["links"][0]["document"];
+    assert_eq!(arch["links"][0]["document"]["circular"], true);
+
+    assert_eq!(graph.broken_reason(&ids[1], &ids[0], TraceLinkType::Refines), None);
+    assert_eq!(
+        graph.broken_reason(&ids[0], &ids[2], TraceLinkType::Refines),
+        Some("source step is not downstream of target step")
+    );
+    assert_eq!(
+        graph.broken_reason(&ids[3], &ids[0], TraceLinkType::Verifies),
+        Some("verifies link from a non-verification document")
+    );
+    let check = graph.check();
+    assert_eq!(check["broken"].as_array().unwrap().len(), 2);
+    assert_eq!(check["circular"][0].as_array().unwrap().len(), 3);
+
+    // a link left behind by a deleted document
+    let deleted = uuid::Uuid::new_v4();
+    let graph = TraceGraph::new(
+        Vec::new(),
+        HashMap::from([(ids[2], HashSet::from([Aspice::单测]))]),
+        vec![DocumentTraceLink::new(ids[2], deleted, TraceLinkType::Verifies)],
+    );
+    assert_eq!(
+        graph.broken_reason(&ids[2], &deleted, TraceLinkType::Verifies),
+        Some("target document does not exist")
+    );
+    assert_eq!(graph.check()["broken"][0]["target"], json!({ "id": deleted.to_string(), "steps": [] }));
+}
+
+#[tokio::test]
+async fn trace_cycles_lists_overlapping_cycles() {
+    let (graph, ids) = trace_graph(
+        &[("A", Aspice::架构), ("B", Aspice::详设), ("C", Aspice::单测)],
+        &[
+            // A -> B -> C -> A and A -> C -> A share the link C -> A
+            (0, 1, TraceLinkType::Refines),
+            (1, 2, TraceLinkType::Refines),
+            (2, 0, TraceLinkType::Refines),
+            (0, 2, TraceLinkType::Refines),
+        ],
+    )
+    .await;
+
+    let rotated = |mut cycle: Vec<uuid::Uuid>| {
+        let min = cycle.iter().position(|id| id == cycle.iter().min().unwrap()).unwrap();
+        cycle.rotate_left(min);
+        cycle
+    };
+    let mut expected = vec![rotated(vec![ids[0], ids[1], ids[2]]), rotated(vec![ids[0], ids[2]])];
+    expected.sort();
+    assert_eq!(graph.cycles(), expected);
+    assert_eq!(graph.check()["circular"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn graphql_resolves_tags_documents_and_requirements() {
+    let repo = MemoryRepository::new();
+    import(&repo, &workbook("graphql")).await;
+    let repo: Arc<dyn Repository> = Arc::new(repo);
+
+    let query = r#"{
+        tags(dimension: ARCH) {
+            name
+            documentCount
+            children { name parent { name } documents { name } }
+        }
+        documents(tag: "雷达") {
+            name
+            aspiceSteps
+            tags(dimension: MF) { name level parent { name } }
+            requirements { name }
+        }
+        requirements {
+            name
+            documents { name }
+            aspiceSteps { step documents { name } }
+        }
+    }"#;
+    let response = graphql::execute(repo.clone(), query.into()).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+
+    assert_eq!(
+        data["tags"],
+        json!([
+            {
+                "name": "感知",
+                "documentCount": 3,
+                "children": [
+                    { "name": "方案A", "parent": { "name": "感知" }, "documents": [{ "name": "ARCH-1" }, { "name": "UT-1" }] },
+                    { "name": "方案B", "parent": { "name": "感知" }, "documents": [{ "name": "DES-1" }] },
+                ],
+            },
+            {
+                "name": "规划",
+                "documentCount": 0,
+                "children": [{ "name": "方案C", "parent": { "name": "规划" }, "documents": [] }],
+            },
+        ])
+    );
+    assert_eq!(
+        data["documents"],
+        json!([
+            {
+                "name": "DES-1",
+                "aspiceSteps": ["详设"],
+                "tags": [{ "name": "定位", "level": "L2", "parent": { "name": "MF" } }],
+                "requirements": [{ "name": "REQ-2" }],
+            },
+            {
+                "name": "UT-1",
+                "aspiceSteps": ["单测"],
+                "tags": [{ "name": "融合", "level": "L2", "parent": { "name": "MF" } }],
+                "requirements": [{ "name": "REQ-1" }, { "name": "REQ-2" }],
+            },
+        ])
+    );
+    let req_2 = &data["requirements"][1];
+    assert_eq!(req_2["name"], "REQ-2");
+    assert_eq!(req_2["documents"], json!([{ "name": "DES-1" }, { "name": "UT-1" }]));
+    assert_eq!(req_2["aspiceSteps"][0], json!({ "step": "需求", "documents": [{ "name": "REQ-2" }] }));
+    assert_eq!(req_2["aspiceSteps"][3], json!({ "step": "单测", "documents": [{ "name": "UT-1" }] }));
+
+    // nesting is limited, relations could otherwise multiply without end
+    let mut deep = "name".to_string();
+    for _ in 0..8 {
+        deep = format!("tags {{ documents {{ {} }} }}", deep);
+    }
+    let response = graphql::execute(repo, format!("{{ documents {{ {} }} }}", deep).into()).await;
+    assert!(!response.errors.is_empty());
+}
+
+#[tokio::test]
+async fn memory_repository_keeps_the_schema_constraints() {
+    let repo = MemoryRepository::new();
+    let doc = Document::new("A".to_string(), "http://a".to_string(), None);
+    repo.insert_documents(vec![doc.clone()]).await.unwrap();
+    assert!(repo.insert_documents(vec![doc.clone()]).await.is_err());
+    let same_name = Document::new("A".to_string(), "http://b".to_string(), None);
+    assert!(repo.insert_documents(vec![same_name]).await.is_err());
+
+    let l1 = TechL1::new("L1".to_string(), 1);
+    // an L2 tag needs its L1 tag
+    assert!(repo
+        .insert_tech_l2(vec![TechL2::new("L2".to_string(), l1.id, 1)])
+        .await
+        .is_err());
+    repo.insert_tech_l1(vec![l1.clone()]).await.unwrap();
+    let l2 = TechL2::new("L2".to_string(), l1.id, 1);
+    repo.insert_tech_l2(vec![l2.clone()]).await.unwrap();
+
+    repo.insert_document_tech(vec![DocumentTech::new(doc.id, l2.id)])
+        .await
+        .unwrap();
+    assert!(repo
+        .insert_document_tech(vec![DocumentTech::new(doc.id, l2.id)])
+        .await
+        .is_err());
+    assert!(repo
+        .insert_document_tech(vec![DocumentTech::new(uuid::Uuid::new_v4(), l2.id)])
+        .await
+        .is_err());
+    assert_eq!(
+        repo.read_documents_by_tag(Dimension::Tech, "L2")
+            .await
+            .unwrap(),
+        vec![doc]
+    );
+}